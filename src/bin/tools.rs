@@ -56,7 +56,7 @@ fn define_type(file: &mut File, class_name: &str, field_list: &str) -> io::Resul
     
     let fields: Vec<&str> = field_list.split(',').collect();
     for field in &fields {
-        let field_parts: Vec<&str> = field.trim().split_whitespace().collect();
+        let field_parts: Vec<&str> = field.split_whitespace().collect();
         let field_type = field_parts[0];
         let field_name = field_parts[1];
         writeln!(file, "            {}: {},", field_name, map_type(field_type))?;        
@@ -0,0 +1,32 @@
+use super::syntax::components::expression::LoxValue;
+use super::syntax::token::Token;
+
+/// A statement that stops running the enclosing statements early.
+///
+/// Executing a statement yields `Ok(None)` when control falls through to the
+/// next one, or `Ok(Some(signal))` while unwinding to the loop or function
+/// that handles the signal. Errors stay in `RuntimeError`.
+#[derive(Debug, Clone)]
+pub enum ControlFlow {
+    /// `label` names the loop to leave; `None` means the innermost one.
+    Break { keyword: Token, label: Option<String> },
+    Continue { keyword: Token, label: Option<String> },
+    Return(LoxValue),
+}
+
+impl ControlFlow {
+    /// Whether this is a `break` or `continue` for the loop labeled
+    /// `label`, which unlabeled ones always are since they only travel to
+    /// the innermost loop.
+    pub fn targets(&self, label: Option<&Token>) -> bool {
+        match self {
+            ControlFlow::Break { label: target, .. } | ControlFlow::Continue { label: target, .. } => {
+                match target {
+                    Some(target) => label.is_some_and(|label| label.lexeme == *target),
+                    None => true,
+                }
+            }
+            ControlFlow::Return(_) => false,
+        }
+    }
+}
@@ -0,0 +1,145 @@
+use crate::core::syntax::components::expression::LoxValue;
+
+/// Rust types a host function can take as a parameter.
+pub trait FromLox: Sized {
+    /// How the type reads in type-mismatch errors, e.g. `"number"`.
+    const EXPECTED: &'static str;
+
+    /// `None` when `value` does not hold this type.
+    fn from_lox(value: LoxValue) -> Option<Self>;
+}
+
+/// Rust types a host function can return.
+pub trait IntoLox {
+    fn into_lox(self) -> LoxValue;
+}
+
+impl FromLox for LoxValue {
+    const EXPECTED: &'static str = "value";
+
+    fn from_lox(value: LoxValue) -> Option<Self> {
+        Some(value)
+    }
+}
+
+impl FromLox for f64 {
+    const EXPECTED: &'static str = "number";
+
+    fn from_lox(value: LoxValue) -> Option<Self> {
+        match value {
+            LoxValue::Number(n) => Some(n),
+            LoxValue::Integer(n) => Some(n as f64),
+            _ => None,
+        }
+    }
+}
+
+// Integers accept integers, and whole floats, within their range only
+macro_rules! integer_conversions {
+    ($($t:ty),*) => {
+        $(
+            impl FromLox for $t {
+                const EXPECTED: &'static str = "integer";
+
+                fn from_lox(value: LoxValue) -> Option<Self> {
+                    match value {
+                        LoxValue::Integer(n) => <$t>::try_from(n).ok(),
+                        // `MAX + 1` is a power of two, so exact as a float,
+                        // while `i64::MAX as f64` alone would round up to it
+                        LoxValue::Number(n)
+                            if n.fract() == 0.0 && n >= <$t>::MIN as f64 && n < <$t>::MAX as f64 + 1.0 =>
+                        {
+                            Some(n as $t)
+                        }
+                        _ => None,
+                    }
+                }
+            }
+
+            impl IntoLox for $t {
+                fn into_lox(self) -> LoxValue {
+                    // Only a `usize` past `i64::MAX` falls back to a float
+                    i64::try_from(self).map_or(LoxValue::Number(self as f64), LoxValue::Integer)
+                }
+            }
+        )*
+    };
+}
+
+integer_conversions!(i32, i64, u32, usize);
+
+impl FromLox for bool {
+    const EXPECTED: &'static str = "boolean";
+
+    fn from_lox(value: LoxValue) -> Option<Self> {
+        match value {
+            LoxValue::Boolean(b) => Some(b),
+            _ => None,
+        }
+    }
+}
+
+impl FromLox for String {
+    const EXPECTED: &'static str = "string";
+
+    fn from_lox(value: LoxValue) -> Option<Self> {
+        match value {
+            LoxValue::String(s) => Some(s),
+            _ => None,
+        }
+    }
+}
+
+/// `nil` becomes `None`; anything else must convert to `T`.
+impl<T: FromLox> FromLox for Option<T> {
+    const EXPECTED: &'static str = T::EXPECTED;
+
+    fn from_lox(value: LoxValue) -> Option<Self> {
+        match value {
+            LoxValue::Nil => Some(None),
+            value => T::from_lox(value).map(Some),
+        }
+    }
+}
+
+impl IntoLox for LoxValue {
+    fn into_lox(self) -> LoxValue {
+        self
+    }
+}
+
+impl IntoLox for f64 {
+    fn into_lox(self) -> LoxValue {
+        LoxValue::Number(self)
+    }
+}
+
+impl IntoLox for bool {
+    fn into_lox(self) -> LoxValue {
+        LoxValue::Boolean(self)
+    }
+}
+
+impl IntoLox for String {
+    fn into_lox(self) -> LoxValue {
+        LoxValue::String(self)
+    }
+}
+
+impl IntoLox for &str {
+    fn into_lox(self) -> LoxValue {
+        LoxValue::String(self.to_string())
+    }
+}
+
+impl IntoLox for () {
+    fn into_lox(self) -> LoxValue {
+        LoxValue::Nil
+    }
+}
+
+impl<T: IntoLox> IntoLox for Option<T> {
+    fn into_lox(self) -> LoxValue {
+        self.map_or(LoxValue::Nil, IntoLox::into_lox)
+    }
+}
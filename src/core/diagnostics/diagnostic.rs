@@ -0,0 +1,242 @@
+use crate::core::syntax::span::Span;
+use crate::utils::colors::Color;
+
+use super::source_map::{SourceFile, SourceMap};
+
+/// How many times in a row a traceback lists the same call before summing
+/// up the rest, so deep recursion doesn't bury the error.
+pub const REPEATS_SHOWN: usize = 3;
+
+/// Groups consecutive equal frames, as each frame and how many times it
+/// repeats.
+pub fn runs<T: PartialEq>(frames: &[T]) -> Vec<(&T, usize)> {
+    let mut runs: Vec<(&T, usize)> = vec![];
+    for frame in frames {
+        match runs.last_mut() {
+            Some((last, times)) if *last == frame => *times += 1,
+            _ => runs.push((frame, 1)),
+        }
+    }
+    runs
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+#[derive(Debug, Clone)]
+pub struct Label {
+    pub span: Span,
+    pub message: String,
+}
+
+/// A renderable error report in the style of rustc:
+///
+/// ```text
+/// error[E0207]: Already exists a variable called 'a' in this scope.
+///  --> script.lox:3:9
+///   |
+/// 2 |     var a = 1;
+///   |         - 'a' first declared here
+/// 3 |     var a = 2;
+///   |         ^ redeclared here
+///   |
+///   = help: rename one of the variables or drop the second `var`
+/// ```
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub code: &'static str,
+    pub message: String,
+    pub primary: Option<Label>,
+    pub secondary: Vec<Label>,
+    pub help: Vec<String>,
+    // Runtime call stack, outermost call first: (function, call site)
+    pub frames: Vec<(String, Span)>,
+}
+
+impl Diagnostic {
+    pub fn error(code: &'static str, message: impl Into<String>) -> Self {
+        Self::new(Severity::Error, code, message)
+    }
+
+    pub fn warning(code: &'static str, message: impl Into<String>) -> Self {
+        Self::new(Severity::Warning, code, message)
+    }
+
+    fn new(severity: Severity, code: &'static str, message: impl Into<String>) -> Self {
+        Self {
+            severity,
+            code,
+            message: message.into(),
+            primary: None,
+            secondary: vec![],
+            help: vec![],
+            frames: vec![],
+        }
+    }
+
+    /// The `^^^` underline. An empty `message` draws just the carets.
+    pub fn with_label(mut self, span: Span, message: impl Into<String>) -> Self {
+        self.primary = Some(Label { span, message: message.into() });
+        self
+    }
+
+    /// A `---` underline pointing at related code, e.g. a previous declaration.
+    pub fn with_secondary(mut self, span: Span, message: impl Into<String>) -> Self {
+        self.secondary.push(Label { span, message: message.into() });
+        self
+    }
+
+    pub fn with_help(mut self, help: impl Into<String>) -> Self {
+        self.help.push(help.into());
+        self
+    }
+
+    /// Appends a call to the traceback printed under the snippet.
+    pub fn with_frame(mut self, function: &str, call_site: Span) -> Self {
+        self.frames.push((function.to_string(), call_site));
+        self
+    }
+
+    pub fn render(&self, sources: &SourceMap, color: bool) -> String {
+        let (severity, severity_color) = match self.severity {
+            Severity::Error => ("error", Color::Red),
+            Severity::Warning => ("warning", Color::Yellow),
+        };
+
+        let mut out = format!(
+            "{}{}\n",
+            severity_color.paint(&format!("{}[{}]", severity, self.code), color),
+            Color::Bold.paint(&format!(": {}", self.message), color),
+        );
+
+        // Labels without a location (line 0) or outside the primary file are dropped
+        let file = self
+            .primary
+            .as_ref()
+            .filter(|label| label.span.line > 0)
+            .and_then(|label| sources.get(label.span.file_id).map(|file| (label, file)));
+
+        let Some((primary, file)) = file else {
+            for help in &self.help {
+                out.push_str(&format!("  = {}: {}\n", Color::Bold.paint("help", color), help));
+            }
+            self.render_frames(&mut out, sources, 2, color);
+            return out;
+        };
+
+        let mut labels: Vec<(&Label, bool)> = vec![(primary, true)];
+        labels.extend(
+            self.secondary
+                .iter()
+                .filter(|label| label.span.line > 0 && label.span.file_id == primary.span.file_id)
+                .map(|label| (label, false)),
+        );
+        labels.sort_by_key(|(label, _)| (label.span.line, label.span.column));
+
+        let max_line = labels.iter().map(|(label, _)| label.span.line).max().unwrap_or(1);
+        let width = max_line.to_string().len();
+        let gutter = |line: &str| Color::Blue.paint(&format!("{:>width$} |", line), color);
+
+        out.push_str(&format!(
+            "{}{} {}:{}:{}\n",
+            " ".repeat(width),
+            Color::Blue.paint("-->", color),
+            file.name,
+            primary.span.line,
+            primary.span.column
+        ));
+        out.push_str(&format!("{}\n", gutter("")));
+
+        let mut previous_line: Option<usize> = None;
+        for (label, is_primary) in &labels {
+            let line = label.span.line;
+            if previous_line != Some(line) {
+                if previous_line.is_some_and(|previous| line > previous + 1) {
+                    out.push_str(&format!("{}\n", Color::Blue.paint("...", color)));
+                }
+                let text = file.line(line).unwrap_or("");
+                out.push_str(&format!("{} {}\n", gutter(&line.to_string()), text));
+                previous_line = Some(line);
+            }
+            out.push_str(&format!("{} {}\n", gutter(""), Self::underline(file, label, *is_primary, color)));
+        }
+
+        if !self.help.is_empty() || !self.frames.is_empty() {
+            out.push_str(&format!("{}\n", gutter("")));
+        }
+        for help in &self.help {
+            out.push_str(&format!(
+                "{} = {}: {}\n",
+                " ".repeat(width),
+                Color::Bold.paint("help", color),
+                help
+            ));
+        }
+        self.render_frames(&mut out, sources, width + 1, color);
+        out
+    }
+
+    fn render_frames(&self, out: &mut String, sources: &SourceMap, indent: usize, color: bool) {
+        if self.frames.is_empty() {
+            return;
+        }
+        out.push_str(&format!(
+            "{}= {}\n",
+            " ".repeat(indent),
+            Color::Bold.paint("traceback (most recent call last):", color)
+        ));
+        for ((function, call_site), times) in runs(&self.frames) {
+            let location = match sources.get(call_site.file_id) {
+                Some(file) if call_site.line > 0 => format!("{}:{}", file.name, call_site),
+                _ => format!("line {}", call_site),
+            };
+            for _ in 0..times.min(REPEATS_SHOWN) {
+                out.push_str(&format!("{}    {}() called at {}\n", " ".repeat(indent), function, location));
+            }
+            if times > REPEATS_SHOWN {
+                out.push_str(&format!(
+                    "{}    [previous call repeated {} more times]\n",
+                    " ".repeat(indent),
+                    times - REPEATS_SHOWN
+                ));
+            }
+        }
+    }
+
+    fn underline(file: &SourceFile, label: &Label, is_primary: bool, color: bool) -> String {
+        let text = file.line(label.span.line).unwrap_or("");
+        let column = label.span.column.max(1) - 1;
+
+        // Keep tabs in the padding so the marks line up with the quoted line
+        let padding: String = text
+            .chars()
+            .take(column)
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect();
+
+        // Multi-line spans are underlined up to the end of their first line
+        let spanned = file
+            .source
+            .get(label.span.start..label.span.end)
+            .unwrap_or("")
+            .split('\n')
+            .next()
+            .unwrap_or("")
+            .chars()
+            .count();
+        let available = text.chars().count().saturating_sub(column);
+        let length = spanned.min(available).max(1);
+
+        let (mark, mark_color) = if is_primary { ('^', Color::Red) } else { ('-', Color::Blue) };
+        let mut marks = mark.to_string().repeat(length);
+        if !label.message.is_empty() {
+            marks.push(' ');
+            marks.push_str(&label.message);
+        }
+        format!("{}{}", padding, mark_color.paint(&marks, color))
+    }
+}
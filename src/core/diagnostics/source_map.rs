@@ -0,0 +1,46 @@
+/// A named piece of source text. Spans refer to it by its index in the
+/// [`SourceMap`].
+#[derive(Debug, Clone)]
+pub struct SourceFile {
+    pub name: String,
+    pub source: String,
+}
+
+impl SourceFile {
+    /// The text of the 1-based `line`, without its line terminator.
+    pub fn line(&self, line: usize) -> Option<&str> {
+        if line == 0 {
+            return None;
+        }
+        self.source
+            .split('\n')
+            .nth(line - 1)
+            .map(|text| text.strip_suffix('\r').unwrap_or(text))
+    }
+}
+
+/// Every source handed to a session, so diagnostics can quote the lines a
+/// span points at.
+#[derive(Debug, Clone, Default)]
+pub struct SourceMap {
+    files: Vec<SourceFile>,
+}
+
+impl SourceMap {
+    pub fn new() -> Self {
+        Self { files: vec![] }
+    }
+
+    /// Stores `source` and returns the file id to scan it with.
+    pub fn add(&mut self, name: &str, source: &str) -> usize {
+        self.files.push(SourceFile {
+            name: name.to_string(),
+            source: source.to_string(),
+        });
+        self.files.len() - 1
+    }
+
+    pub fn get(&self, file_id: usize) -> Option<&SourceFile> {
+        self.files.get(file_id)
+    }
+}
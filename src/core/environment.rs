@@ -1,14 +1,20 @@
 use super::error_types::runtime_error::RuntimeError;
+use super::gc::{Trace, Tracer};
 use super::syntax::components::expression::LoxValue;
 use super::syntax::token::Token;
-use super::syntax::token_type::TokenType;
 use std::cell::RefCell;
 use std::collections::HashMap;
+use std::fmt;
 use std::rc::Rc;
 
+/// A scope of bindings. The global scope is keyed by name, so REPL lines
+/// and native functions can add to it at any time. Every other scope is a
+/// frame of slots: the resolver numbers each local in declaration order and
+/// the interpreter pushes values in that same order.
 #[derive(Debug, Clone)]
 pub struct Environment {
     values: HashMap<String, LoxValue>,
+    slots: Vec<LoxValue>,
     pub enclosing: Option<Rc<RefCell<Environment>>>,
 }
 
@@ -16,6 +22,7 @@ impl Environment {
     pub fn new(enclosing: Option<Rc<RefCell<Environment>>>) -> Self {
         Self {
             values: HashMap::new(),
+            slots: vec![],
             enclosing,
         }
     }
@@ -28,6 +35,17 @@ impl Environment {
         Ok(())
     }
 
+    /// Like `define`, but overwrites an existing binding instead of failing.
+    pub fn redefine(&mut self, name: &str, value: LoxValue) {
+        self.values.insert(name.to_owned(), value);
+    }
+
+    /// Stores the next local of this frame; its slot is the number of
+    /// locals pushed before it.
+    pub fn push(&mut self, value: LoxValue) {
+        self.slots.push(value);
+    }
+
     pub fn get(&self, name: &Token) -> Result<LoxValue, RuntimeError> {
         match self.values.get(&name.lexeme) {
             Some(value) => Ok(value.clone()),
@@ -50,43 +68,80 @@ impl Environment {
     }
     
     pub fn ancestor(&self, distance: usize) -> Option<Rc<RefCell<Environment>>> {
-        let mut env = Some(Rc::new(RefCell::new(self.clone()))); 
-        for _ in 0..distance {
-            env = match &env {
-                Some(e) => e.borrow_mut().enclosing.take(),
-                None => return None,
-            };
+        let mut env = self.enclosing.clone()?;
+        for _ in 1..distance {
+            let next = env.borrow().enclosing.clone()?;
+            env = next;
         }
-        env
+        Some(env)
     }
-    
-    pub fn get_at(&self, distance: usize, name: &str) -> Result<LoxValue, RuntimeError> {
-        match self.ancestor(distance) {
-            Some(env) => env.borrow().values.get(name).cloned().ok_or_else(|| {
-                RuntimeError::UndefinedVariable(Token {
-                    lexeme: name.to_string(),
-                    line: 0,
-                    literal: LoxValue::Nil,
-                    t_type: TokenType::VAR,
-                })
-            }),
-            None => Err(RuntimeError::UndefinedVariable(Token {
-                lexeme: name.to_string(),
-                line: 0,
-                literal: LoxValue::Nil,
-                t_type: TokenType::VAR,
-            })),
+
+    /// The local `distance` frames up at `slot`, or `None` when that frame
+    /// or slot does not exist.
+    pub fn get_at(&self, distance: usize, slot: usize) -> Option<LoxValue> {
+        if distance == 0 {
+            return self.slots.get(slot).cloned();
         }
+        self.ancestor(distance)?.borrow().slots.get(slot).cloned()
     }
 
-    pub fn assing_at(&mut self, distance: usize, name: &str, value: LoxValue) {
+    /// Overwrites the local `distance` frames up at `slot`. Returns `false`
+    /// when that frame or slot does not exist.
+    pub fn assing_at(&mut self, distance: usize, slot: usize, value: LoxValue) -> bool {
+        if distance == 0 {
+            return Self::store(&mut self.slots, slot, value);
+        }
         match self.ancestor(distance) {
-            Some(e) => {
-                e.borrow_mut().values.insert(name.to_string(), value);
-            }
-            None => {
-                panic!("Environment, Assing at function failed ")
+            Some(env) => Self::store(&mut env.borrow_mut().slots, slot, value),
+            None => false,
+        }
+    }
+
+    fn store(slots: &mut [LoxValue], slot: usize, value: LoxValue) -> bool {
+        match slots.get_mut(slot) {
+            Some(current) => {
+                *current = value;
+                true
             }
-        } 
+            None => false,
+        }
     }
-}
\ No newline at end of file
+}
+
+// One "name = value" line per global, sorted by name, then one
+// "#slot = value" line per local.
+impl fmt::Display for Environment {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut names: Vec<&String> = self.values.keys().collect();
+        names.sort();
+        for name in names {
+            writeln!(f, "{} = {}", name, self.values[name])?;
+        }
+        for (slot, value) in self.slots.iter().enumerate() {
+            writeln!(f, "#{} = {}", slot, value)?;
+        }
+        Ok(())
+    }
+}
+
+impl Trace for RefCell<Environment> {
+    fn trace(&self, tracer: &mut Tracer) {
+        let Ok(env) = self.try_borrow() else {
+            return tracer.opaque();
+        };
+        for value in env.values.values().chain(&env.slots) {
+            value.trace(tracer);
+        }
+        if let Some(enclosing) = &env.enclosing {
+            tracer.edge(enclosing);
+        }
+    }
+
+    fn clear(&self) {
+        if let Ok(mut env) = self.try_borrow_mut() {
+            env.values.clear();
+            env.slots.clear();
+            env.enclosing = None;
+        }
+    }
+}
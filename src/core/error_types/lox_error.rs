@@ -0,0 +1,73 @@
+use std::fmt;
+
+use crate::core::diagnostics::diagnostic::Diagnostic;
+
+use super::{parse_error::ParseError, runtime_error::RuntimeError, scanner_error::ScannerError};
+
+#[derive(Debug, Clone)]
+pub enum LoxError {
+    Scanner(Vec<ScannerError>),
+    Parse(Vec<ParseError>),
+    Resolve(RuntimeError),
+    // Raised while lowering to bytecode for the VM
+    Compile(RuntimeError),
+    Runtime(RuntimeError),
+}
+
+impl LoxError {
+    pub fn is_compile_error(&self) -> bool {
+        !matches!(self, LoxError::Runtime(_))
+    }
+
+    /// One renderable report per underlying error.
+    pub fn diagnostics(&self) -> Vec<Diagnostic> {
+        match self {
+            LoxError::Scanner(errors) => errors.iter().map(ScannerError::diagnostic).collect(),
+            LoxError::Parse(errors) => errors.iter().map(ParseError::diagnostic).collect(),
+            LoxError::Resolve(e) | LoxError::Compile(e) | LoxError::Runtime(e) => vec![e.diagnostic()],
+        }
+    }
+}
+
+impl fmt::Display for LoxError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LoxError::Scanner(errors) => {
+                let messages: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+                write!(f, "{}", messages.join("\n"))
+            }
+            LoxError::Parse(errors) => {
+                let messages: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+                write!(f, "{}", messages.join("\n"))
+            }
+            LoxError::Resolve(e) | LoxError::Compile(e) => write!(f, "{}", e),
+            LoxError::Runtime(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for LoxError {}
+
+impl From<Vec<ScannerError>> for LoxError {
+    fn from(errors: Vec<ScannerError>) -> Self {
+        LoxError::Scanner(errors)
+    }
+}
+
+impl From<Vec<ParseError>> for LoxError {
+    fn from(errors: Vec<ParseError>) -> Self {
+        LoxError::Parse(errors)
+    }
+}
+
+impl From<ParseError> for LoxError {
+    fn from(error: ParseError) -> Self {
+        LoxError::Parse(vec![error])
+    }
+}
+
+impl From<RuntimeError> for LoxError {
+    fn from(error: RuntimeError) -> Self {
+        LoxError::Runtime(error)
+    }
+}
@@ -1,4 +1,5 @@
 pub mod parse_error;
 pub mod runtime_error;
 pub mod scanner_error;
-pub mod resolver_error;
\ No newline at end of file
+pub mod resolver_error;
+pub mod lox_error;
\ No newline at end of file
@@ -1,226 +1,192 @@
 use std::fmt;
 
-use crate::core::syntax::{token::Token, token_type::TokenType};
+use crate::core::diagnostics::diagnostic::Diagnostic;
+use crate::core::syntax::{span::Span, token::Token, token_type::TokenType};
 
 #[derive(Debug, Clone)]
 pub enum ParseError {
-    UnexpectedToken(Token, usize),
-    MissingToken(Token, usize),
-    InvalidExpression(String, usize),
-    UnterminatedString(usize),
-    DivisionByZero(usize),
-    UndefinedVariable(String, usize),
-    UnexpectedEOF(usize),
-    ExpectedRightParen(usize),
-    ExpectedTernaryBranch(usize, usize),
-    MissingLeftOperand(usize),
-    EspectSemicolonAfterValue(usize),
-    EspectSemicolonAfterExpression(usize),
-    ExpectedVariableName(usize),
-    ExpectedVariableDeclaration(usize),
-    InvalidAssignmentTarget(usize),
-    ExpectedRightBraceAfterBlock(usize),
-    ExpectedRightBraceAfterClassBody(usize),
-    ExpectedLeftBraceAfterClassBody(usize),
-    ExpectedSomeTokenTypeAfterSomething(TokenType, usize, String),
-    ExpectedBreak(usize),
-    TooManyArguments(usize),
-    ExpectedIdentifier(usize, String),
-    ExpectedParameterName(usize),
-    ExpectClassName(usize),
-    ExpectedPropertyNameAfterDot(usize),
-    ExpectedSuperClassName(usize),
-    ExpectDotAfterSuper(usize),
-    ExpectSuperClassMethodName(usize)
+    UnexpectedToken(Token, Span),
+    MissingToken(Token, Span),
+    InvalidExpression(String, Span),
+    UnterminatedString(Span),
+    DivisionByZero(Span),
+    UndefinedVariable(String, Span),
+    UnexpectedEOF(Span),
+    ExpectedRightParen(Span),
+    ExpectedTernaryBranch(Span, usize),
+    MissingLeftOperand(Span),
+    EspectSemicolonAfterValue(Span),
+    EspectSemicolonAfterExpression(Span),
+    ExpectedVariableName(Span),
+    ExpectedVariableDeclaration(Span),
+    InvalidAssignmentTarget(Span),
+    ExpectedRightBraceAfterBlock(Span),
+    ExpectedRightBraceAfterClassBody(Span),
+    ExpectedLeftBraceAfterClassBody(Span),
+    ExpectedSomeTokenTypeAfterSomething(TokenType, Span, String),
+    ExpectedBreak(Span),
+    TooManyArguments(Span),
+    ExpectedIdentifier(Span, String),
+    ExpectedParameterName(Span),
+    ExpectClassName(Span),
+    ExpectedPropertyNameAfterDot(Span),
+    ExpectedSuperClassName(Span),
+    ExpectDotAfterSuper(Span),
+    ExpectSuperClassMethodName(Span),
+    MissingParameterDefault(Span),
+    InvalidRestParameter(Span),
+    ExpectedLoopAfterLabel(Span)
 }
 
-impl fmt::Display for ParseError {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+impl ParseError {
+    pub fn span(&self) -> Span {
         match self {
-            ParseError::UnexpectedToken(token, line) => {
-                write!(f, "[PARSER]: Unexpected token '{}' at line {}", token.lexeme, line)
-            }
-            ParseError::MissingToken(token, line) => {
-                write!(f, "[PARSER]: Expected token '{}' but it was missing at line {}", token.lexeme, line)
-            }
-            ParseError::InvalidExpression(expr, line) => {
-                write!(f, "[PARSER]: Invalid expression: '{}' at line {}", expr, line)
-            }
-            ParseError::UnterminatedString(line) => {
-                write!(f, "[PARSER]: Unterminated string literal at line {}", line)
-            }
-            ParseError::DivisionByZero(line) => {
-                write!(f, "[PARSER]: Division by zero detected at line {}", line)
-            }
-            ParseError::UndefinedVariable(var_name, line) => {
-                write!(f, "[PARSER]: Undefined variable '{}' at line {}", var_name, line)
-            }
-            ParseError::UnexpectedEOF(line) => {
-                write!(f, "[PARSER]: Unexpected end of file at line {}", line)
-            }
-            ParseError::ExpectedRightParen(line) => {
-                write!(f, "[PARSER]: Expect ')' after expression, at line {}", line)
-            }
-            ParseError::ExpectedTernaryBranch(line, expr_line) => {
-                write!(f, "[PARSER]: Expect ':' after then branch of ternary expression at line {} (expression at line {})", line, expr_line)
-            }
-            ParseError::MissingLeftOperand(line) => {
-                write!(f, "[PARSER]: Expect a left operand after an unary expression, at line: {}", line)
-            }
-            ParseError::EspectSemicolonAfterValue(line) => {
-                write!(f, "[PARSER]: Expect ';' after value. At line: {}", line)
-            }
-            ParseError::EspectSemicolonAfterExpression(line) => {
-                write!(f, "[PARSER]: Expect ';' after expression. At line: {}", line)
-            }
-            ParseError::ExpectedVariableName(line) => {
-                write!(f, "[PARSER]: Expect variable name. At line: {}", line)
-            }
-            ParseError::ExpectedVariableDeclaration(line) => {
-                write!(f, "[PARSER]: Expect variable declaration. At line: {}", line)
-            }
-            ParseError::InvalidAssignmentTarget(line) => {
-                write!(f, "[PARSER]: Invalid assignment target. At line {}", line)
-            }
-            ParseError::ExpectedRightBraceAfterBlock(line) => {
-                write!(f, "[PARSER]: Expect '}}' after block. At line {}", line)
-            }
-            ParseError::ExpectedSomeTokenTypeAfterSomething(tt, line, something) => {
-                write!(f, "[PARSER]: Expect '{}' after {} statement at line {}", tt,  something, line)
-            }
-            ParseError::ExpectedBreak(line) => {
-                write!(f, "[PARSER]: Expect break statement at line {}", line)
-            }
-            ParseError::TooManyArguments(line) => {
-                write!(f, "[PARSER]: Too many arguments at line {}", line)
-            }
-            ParseError::ExpectedIdentifier(line, kind) => {
-                write!(f, "[PARSER]: Expect {} name at line {}", line, kind)  
+            ParseError::UnexpectedToken(_, span)
+            | ParseError::MissingToken(_, span)
+            | ParseError::InvalidExpression(_, span)
+            | ParseError::UnterminatedString(span)
+            | ParseError::DivisionByZero(span)
+            | ParseError::UndefinedVariable(_, span)
+            | ParseError::UnexpectedEOF(span)
+            | ParseError::ExpectedRightParen(span)
+            | ParseError::ExpectedTernaryBranch(span, _)
+            | ParseError::MissingLeftOperand(span)
+            | ParseError::EspectSemicolonAfterValue(span)
+            | ParseError::EspectSemicolonAfterExpression(span)
+            | ParseError::ExpectedVariableName(span)
+            | ParseError::ExpectedVariableDeclaration(span)
+            | ParseError::InvalidAssignmentTarget(span)
+            | ParseError::ExpectedRightBraceAfterBlock(span)
+            | ParseError::ExpectedRightBraceAfterClassBody(span)
+            | ParseError::ExpectedLeftBraceAfterClassBody(span)
+            | ParseError::ExpectedSomeTokenTypeAfterSomething(_, span, _)
+            | ParseError::ExpectedBreak(span)
+            | ParseError::TooManyArguments(span)
+            | ParseError::ExpectedIdentifier(span, _)
+            | ParseError::ExpectedParameterName(span)
+            | ParseError::ExpectClassName(span)
+            | ParseError::ExpectedPropertyNameAfterDot(span)
+            | ParseError::ExpectedSuperClassName(span)
+            | ParseError::ExpectDotAfterSuper(span)
+            | ParseError::ExpectSuperClassMethodName(span)
+            | ParseError::MissingParameterDefault(span)
+            | ParseError::InvalidRestParameter(span)
+            | ParseError::ExpectedLoopAfterLabel(span) => *span,
+        }
+    }
 
-            }
-            ParseError::ExpectedParameterName(line) => {
-                write!(f, "[PARSER]: Expect parameter name at line {}", line)
-            }
-            ParseError::ExpectClassName(line) => {
-                write!(f, "[PARSER]: Expect class name {}", line)
-            }
-            ParseError::ExpectedLeftBraceAfterClassBody(line) => {
-                write!(f, "[PARSER]: Expect '{{' after class body. At line {}", line)
-            }
-            ParseError::ExpectedRightBraceAfterClassBody(line) => {
-                write!(f, "[PARSER]: Expect '}}' after class body. At line {}", line)
-            }
-            ParseError::ExpectedPropertyNameAfterDot(line) => {
-                write!(f, "[PARSER]: Expected property name after '.' at line {}", line)
-            }
-            ParseError::ExpectedSuperClassName(line) => {
-                write!(f, "[PARSER]: Expected superclass name at line {}", line)
-            }
-            ParseError::ExpectSuperClassMethodName(line) => {
-                write!(f, "[PARSER]: Expect superclass method name {}", line)
-            }
-            ParseError::ExpectDotAfterSuper(line) => {
-                write!(f, "[PARSER]: Expect dot after super name {}", line)
-            }
+    pub fn code(&self) -> &'static str {
+        match self {
+            ParseError::UnexpectedToken(..) => "E0100",
+            ParseError::MissingToken(..) => "E0101",
+            ParseError::InvalidExpression(..) => "E0102",
+            ParseError::UnterminatedString(_) => "E0103",
+            ParseError::DivisionByZero(_) => "E0104",
+            ParseError::UndefinedVariable(..) => "E0105",
+            ParseError::UnexpectedEOF(_) => "E0106",
+            ParseError::ExpectedRightParen(_) => "E0107",
+            ParseError::ExpectedTernaryBranch(..) => "E0108",
+            ParseError::MissingLeftOperand(_) => "E0109",
+            ParseError::EspectSemicolonAfterValue(_) => "E0110",
+            ParseError::EspectSemicolonAfterExpression(_) => "E0111",
+            ParseError::ExpectedVariableName(_) => "E0112",
+            ParseError::ExpectedVariableDeclaration(_) => "E0113",
+            ParseError::InvalidAssignmentTarget(_) => "E0114",
+            ParseError::ExpectedRightBraceAfterBlock(_) => "E0115",
+            ParseError::ExpectedRightBraceAfterClassBody(_) => "E0116",
+            ParseError::ExpectedLeftBraceAfterClassBody(_) => "E0117",
+            ParseError::ExpectedSomeTokenTypeAfterSomething(..) => "E0118",
+            ParseError::ExpectedBreak(_) => "E0119",
+            ParseError::TooManyArguments(_) => "E0120",
+            ParseError::ExpectedIdentifier(..) => "E0121",
+            ParseError::ExpectedParameterName(_) => "E0122",
+            ParseError::ExpectClassName(_) => "E0123",
+            ParseError::ExpectedPropertyNameAfterDot(_) => "E0124",
+            ParseError::ExpectedSuperClassName(_) => "E0125",
+            ParseError::ExpectDotAfterSuper(_) => "E0126",
+            ParseError::ExpectSuperClassMethodName(_) => "E0127",
+            ParseError::MissingParameterDefault(_) => "E0128",
+            ParseError::InvalidRestParameter(_) => "E0129",
+            ParseError::ExpectedLoopAfterLabel(_) => "E0130",
         }
     }
-}
 
-impl std::error::Error for ParseError {}
+    /// The error text without the `[PARSER]` prefix or location.
+    pub fn message(&self) -> String {
+        match self {
+            ParseError::UnexpectedToken(token, _) => format!("Unexpected token '{}'", token.lexeme),
+            ParseError::MissingToken(token, _) => format!("Expected token '{}' but it was missing", token.lexeme),
+            ParseError::InvalidExpression(expr, _) => format!("Invalid expression: '{}'", expr),
+            ParseError::UnterminatedString(_) => "Unterminated string literal".to_string(),
+            ParseError::DivisionByZero(_) => "Division by zero detected".to_string(),
+            ParseError::UndefinedVariable(var_name, _) => format!("Undefined variable '{}'", var_name),
+            ParseError::UnexpectedEOF(_) => "Unexpected end of file".to_string(),
+            ParseError::ExpectedRightParen(_) => "Expect ')' after expression".to_string(),
+            ParseError::ExpectedTernaryBranch(..) => "Expect ':' after then branch of ternary expression".to_string(),
+            ParseError::MissingLeftOperand(_) => "Expect a left operand after an unary expression".to_string(),
+            ParseError::EspectSemicolonAfterValue(_) => "Expect ';' after value".to_string(),
+            ParseError::EspectSemicolonAfterExpression(_) => "Expect ';' after expression".to_string(),
+            ParseError::ExpectedVariableName(_) => "Expect variable name".to_string(),
+            ParseError::ExpectedVariableDeclaration(_) => "Expect variable declaration".to_string(),
+            ParseError::InvalidAssignmentTarget(_) => "Invalid assignment target".to_string(),
+            ParseError::ExpectedRightBraceAfterBlock(_) => "Expect '}' after block".to_string(),
+            ParseError::ExpectedRightBraceAfterClassBody(_) => "Expect '}' after class body".to_string(),
+            ParseError::ExpectedLeftBraceAfterClassBody(_) => "Expect '{' before class body".to_string(),
+            ParseError::ExpectedSomeTokenTypeAfterSomething(tt, _, something) => {
+                format!("Expect '{}' after {} statement", tt, something)
+            }
+            ParseError::ExpectedBreak(_) => "Expect break statement".to_string(),
+            ParseError::TooManyArguments(_) => "Too many arguments".to_string(),
+            ParseError::ExpectedIdentifier(_, kind) => format!("Expect {} name", kind),
+            ParseError::ExpectedParameterName(_) => "Expect parameter name".to_string(),
+            ParseError::ExpectClassName(_) => "Expect class name".to_string(),
+            ParseError::ExpectedPropertyNameAfterDot(_) => "Expected property name after '.'".to_string(),
+            ParseError::ExpectedSuperClassName(_) => "Expected superclass name".to_string(),
+            ParseError::ExpectSuperClassMethodName(_) => "Expect superclass method name".to_string(),
+            ParseError::ExpectDotAfterSuper(_) => "Expect '.' after 'super'".to_string(),
+            ParseError::MissingParameterDefault(_) => "Expect a default value for a parameter after one with a default".to_string(),
+            ParseError::InvalidRestParameter(_) => "A rest parameter must come last and can't have a default value".to_string(),
+            ParseError::ExpectedLoopAfterLabel(_) => "Expect a loop after a label".to_string(),
+        }
+    }
 
-impl ParseError {
-    pub fn to_string(&self) -> String {
+    pub fn diagnostic(&self) -> Diagnostic {
+        let label = match self {
+            ParseError::ExpectedSomeTokenTypeAfterSomething(tt, ..) => format!("expected {} here", tt),
+            ParseError::EspectSemicolonAfterValue(_) | ParseError::EspectSemicolonAfterExpression(_) => {
+                "expected ';' here".to_string()
+            }
+            ParseError::InvalidAssignmentTarget(_) => "cannot assign to this".to_string(),
+            _ => String::new(),
+        };
+
+        let diagnostic = Diagnostic::error(self.code(), self.message()).with_label(self.span(), label);
         match self {
-            ParseError::UnexpectedToken(token, line) => {
-                format!("[PARSER]: Unexpected token '{}' at line {}", token.lexeme, line)
-            }
-            ParseError::MissingToken(token, line) => {
-                format!("[PARSER]: Expected token '{}' but it was missing at line {}", token.lexeme, line)
-            }
-            ParseError::InvalidExpression(expr, line) => {
-                format!("[PARSER]: Invalid expression: '{}' at line {}", expr, line)
-            }
-            ParseError::UnterminatedString(line) => {
-                format!("[PARSER]: Unterminated string literal at line {}", line)
-            }
-            ParseError::DivisionByZero(line) => {
-                format!("[PARSER]: Division by zero detected at line {}", line)
-            }
-            ParseError::UndefinedVariable(var_name, line) => {
-                format!("[PARSER]: Undefined variable '{}' at line {}", var_name, line)
-            }
-            ParseError::UnexpectedEOF(line) => {
-                format!("[PARSER]: Unexpected end of file at line {}", line)
-            }
-            ParseError::ExpectedRightParen(line) => {
-                format!("[PARSER]: Expect ')' after expression, at line {}", line)
-            }
-            ParseError::ExpectedTernaryBranch(line, expr_line) => {
-                format!("[PARSER]: Expect ':' after then branch of ternary expression at line {} (expression at line {})", line, expr_line)
-            }
-            ParseError::MissingLeftOperand(line) => {
-                format!("[PARSER]: Expect a left operand after an unary expression, at line: {}", line)
-            }
-            ParseError::EspectSemicolonAfterValue(line) => {
-                format!("[PARSER]: Expect ';' after value. At line: {}", line)
-            }
-            ParseError::EspectSemicolonAfterExpression(line) => {
-                format!("[PARSER]: Expect ';' after expression. At line: {}", line)
-            }
-            ParseError::ExpectedVariableName(line) => {
-                format!("[PARSER]: Expect variable name. At line: {}", line)
-            }
-            ParseError::ExpectedVariableDeclaration(line) => {
-                format!("[PARSER]: Expect variable declaration. At line: {}", line)
-            }
-            ParseError::InvalidAssignmentTarget(line) => {
-                format!("[PARSER]: Invalid assignment target. At line {}", line)
+            ParseError::TooManyArguments(_) => diagnostic.with_help("functions take at most 255 arguments"),
+            ParseError::MissingParameterDefault(_) => {
+                diagnostic.with_help("move the parameter before the ones with defaults, or give it a default too")
             }
-            ParseError::ExpectedRightBraceAfterBlock(line) => {
-                format!("[PARSER]: Expect '}}' after block. At line {}", line)
+            ParseError::InvalidAssignmentTarget(_) => {
+                diagnostic.with_help("only variables, properties (`obj.field = v`) and indexes (`xs[i] = v`) can be assigned")
             }
-            ParseError::ExpectedSomeTokenTypeAfterSomething(tt, line, something) => {
-                format!("[PARSER]: Expect '{}' after {} statement at line {}", tt,  something, line)
-            }
-            ParseError::ExpectedBreak(line) => {
-                format!("[PARSER]: Expect break statement at line {}", line)
-            }
-            ParseError::TooManyArguments(line) => {
-                format!("[PARSER]: Too many arguments at line {}", line)  // Mensaje para TooManyArguments
-            }
-            ParseError::ExpectedIdentifier(line, kind) => {
-                format!("[PARSER]: Expect {} name at line {}", line, kind)  
-            }
-            ParseError::ExpectedParameterName(line) => {
-                format!("[PARSER]: Expect parameter name at line {}", line)
-            }
-            ParseError::ExpectedLeftBraceAfterClassBody(line) => {
-                format!("[PARSER]: Expect '{{' after class body. At line {}", line)
-            }
-            ParseError::ExpectedRightBraceAfterClassBody(line) => {
-                format!("[PARSER]: Expect '}}' after class body. At line {}", line)
-            }
-            ParseError::ExpectClassName(line) => {
-                format!("[PARSER]: Expect '}}' after class body. At line {}", line)
-            }
-            ParseError::ExpectedPropertyNameAfterDot(line) => {
-                format!("[PARSER]: Expected property name after '.' at line {}", line)
-            }
-            ParseError::ExpectedSuperClassName(line) => {
-                format!("[PARSER]: Expected superclass name at line {}", line)
-            }
-            ParseError::ExpectSuperClassMethodName(line) => {
-                format!("[PARSER]: Expect superclass method name {}", line)
-            }
-            ParseError::ExpectDotAfterSuper(line) => {
-                format!("[PARSER]: Expect dot after super name {}", line)
+            ParseError::ExpectedLoopAfterLabel(_) => {
+                diagnostic.with_help("labels name a `while`, `for` or `loop`, like `outer: while (...)`")
             }
+            _ => diagnostic,
         }
     }
 }
 
-impl From<(String, usize)> for ParseError {
-    fn from(message: (String, usize)) -> Self {
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "[PARSER]: {} at line {}", self.message(), self.span().line)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+impl From<(String, Span)> for ParseError {
+    fn from(message: (String, Span)) -> Self {
         ParseError::InvalidExpression(message.0, message.1)
     }
 }
@@ -1,6 +1,21 @@
 use std::fmt;
 
-use crate::core::syntax::{components::expression::LoxValue, token::Token};
+use crate::core::diagnostics::diagnostic::{self, Diagnostic, REPEATS_SHOWN};
+use crate::core::fuctions::lox_callable::Arity;
+use crate::core::syntax::{span::Span, token::Token};
+
+/// How many calls deep a script can go on either backend before the next
+/// call fails with `StackOverflow`, unless the session sets its own limit.
+/// The tree-walker recurses on the Rust stack, and this many calls fit in
+/// the 2 MiB a spawned thread gets, even in debug builds.
+pub const DEFAULT_CALL_DEPTH: usize = 64;
+
+/// One active call: the function being run and where it was called from.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CallFrame {
+    pub function: String,
+    pub call_site: Span,
+}
 
 #[derive(Debug, Clone)]
 pub enum RuntimeError {
@@ -9,105 +24,332 @@ pub enum RuntimeError {
     UndefinedVariable(Token),
     RedefinedVariable(String),
     BadExpr(),
-    Break(),
-    BadCallable(),
-    ToManyArguments(Token, usize, usize),
+    BadCallable(Token),
+    // Call site, callee name, expected and received argument counts
+    ArityMismatch(Token, String, Arity, usize),
     NativeFunctionError(String),
-    Return(LoxValue),
+    // Host function name, parameter index (from 0), expected and received
+    // type names
+    ArgumentTypeMismatch(String, usize, &'static str, &'static str),
     BadArguments(String),
     InvalidFunction(String),
-    OnlyInstancesHaveProperties(),
-    UndefinedProperty(),
-    CantReturnFromInitializer(),
-    CantAccessPrivateMethod(),
-    CantCallStaticMethodFromInstance(),
-    ClassInheritFromItself(),
-    SuperClassMustBeSuperAClass(),
-    InvalidSuperclass(),
-    UnresolvedSuper(),
+    OnlyInstancesHaveProperties(Token),
+    UndefinedProperty(Token),
+    CantReturnFromInitializer(Token),
+    CantAccessPrivateMethod(Token),
+    CantCallStaticMethodFromInstance(Token),
+    ClassInheritFromItself(Token),
+    SuperClassMustBeSuperAClass(Token),
+    InvalidSuperclass(Token),
+    UnresolvedSuper(Token),
     InvalidClassMember(),
-    SuperWithoutSubclass(),
-    SuperOutsideClass()
+    // A native class property with a getter and no setter
+    ReadOnlyProperty(Token),
+    NativeSuperclass(Token),
+    // The index, and the length of the list or string
+    IndexOutOfRange(Token, i64, usize),
+    // What was used as an index instead of a whole number
+    InvalidIndex(Token, String),
+    NotIndexable(Token, &'static str),
+    ImmutableIndex(Token, &'static str),
+    NotSliceable(Token, &'static str),
+    // The type of the value used as a map key
+    UnhashableKey(Token, &'static str),
+    // The key, as it prints
+    KeyNotFound(Token, String),
+    IntegerOverflow(Token),
+    // The call that went past the limit, and the limit
+    StackOverflow(Span, usize),
+    SuperWithoutSubclass(Token),
+    SuperOutsideClass(Token),
+    ThisOutsideClass(Token),
+    ReturnOutsideFunction(Token),
+    // `break` or `continue` with no loop around it
+    OutsideLoop(Token),
+    // The label of a `break` or `continue` no loop around it has
+    UndefinedLabel(Token),
+    // The second span points at the previous declaration
+    VariableAlreadyDeclared(Token, Span),
+    ReadLocalInOwnInitializer(Token),
+    // Limits of the bytecode compiler, per function
+    TooManyConstants(Span),
+    TooManyLocals(Span),
+    TooManyUpvalues(Span),
+    // An error that escaped with the call stack at the moment it was raised,
+    // outermost call first
+    Traceback(Box<RuntimeError>, Vec<CallFrame>)
 }
 
-impl fmt::Display for RuntimeError {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+impl RuntimeError {
+    /// The error itself, without any traceback around it.
+    pub fn root(&self) -> &RuntimeError {
+        match self {
+            RuntimeError::Traceback(error, _) => error.root(),
+            _ => self,
+        }
+    }
+
+    pub fn traceback(&self) -> &[CallFrame] {
         match self {
+            RuntimeError::Traceback(_, frames) => frames,
+            _ => &[],
+        }
+    }
+
+    /// Where the error happened, when it is known.
+    pub fn span(&self) -> Option<Span> {
+        match self {
+            RuntimeError::Traceback(error, _) => error.span(),
+            RuntimeError::BadOperator(token, _)
+            | RuntimeError::UndefinedVariable(token)
+            | RuntimeError::BadCallable(token)
+            | RuntimeError::ArityMismatch(token, ..)
+            | RuntimeError::OnlyInstancesHaveProperties(token)
+            | RuntimeError::UndefinedProperty(token)
+            | RuntimeError::CantReturnFromInitializer(token)
+            | RuntimeError::CantAccessPrivateMethod(token)
+            | RuntimeError::CantCallStaticMethodFromInstance(token)
+            | RuntimeError::ClassInheritFromItself(token)
+            | RuntimeError::SuperClassMustBeSuperAClass(token)
+            | RuntimeError::InvalidSuperclass(token)
+            | RuntimeError::UnresolvedSuper(token)
+            | RuntimeError::SuperWithoutSubclass(token)
+            | RuntimeError::SuperOutsideClass(token)
+            | RuntimeError::ThisOutsideClass(token)
+            | RuntimeError::ReturnOutsideFunction(token)
+            | RuntimeError::OutsideLoop(token)
+            | RuntimeError::UndefinedLabel(token)
+            | RuntimeError::VariableAlreadyDeclared(token, _)
+            | RuntimeError::ReadLocalInOwnInitializer(token)
+            | RuntimeError::ReadOnlyProperty(token)
+            | RuntimeError::NativeSuperclass(token)
+            | RuntimeError::IndexOutOfRange(token, ..)
+            | RuntimeError::InvalidIndex(token, _)
+            | RuntimeError::NotIndexable(token, _)
+            | RuntimeError::ImmutableIndex(token, _)
+            | RuntimeError::NotSliceable(token, _)
+            | RuntimeError::UnhashableKey(token, _)
+            | RuntimeError::KeyNotFound(token, _)
+            | RuntimeError::IntegerOverflow(token) => Some(token.span),
+            RuntimeError::TooManyConstants(span)
+            | RuntimeError::TooManyLocals(span)
+            | RuntimeError::TooManyUpvalues(span)
+            | RuntimeError::StackOverflow(span, _) => Some(*span),
+            _ => None,
+        }
+    }
+
+    /// Resolver and bytecode compiler errors use `E02xx`, errors raised while
+    /// running use `E03xx`.
+    pub fn code(&self) -> &'static str {
+        match self {
+            RuntimeError::Traceback(error, _) => error.code(),
+            RuntimeError::CantReturnFromInitializer(_) => "E0200",
+            RuntimeError::ClassInheritFromItself(_) => "E0201",
+            RuntimeError::InvalidClassMember() => "E0202",
+            RuntimeError::SuperWithoutSubclass(_) => "E0203",
+            RuntimeError::SuperOutsideClass(_) => "E0204",
+            RuntimeError::ThisOutsideClass(_) => "E0205",
+            RuntimeError::ReturnOutsideFunction(_) => "E0206",
+            RuntimeError::VariableAlreadyDeclared(..) => "E0207",
+            RuntimeError::ReadLocalInOwnInitializer(_) => "E0208",
+            RuntimeError::OutsideLoop(_) => "E0209",
+            RuntimeError::TooManyConstants(_) => "E0210",
+            RuntimeError::TooManyLocals(_) => "E0211",
+            RuntimeError::TooManyUpvalues(_) => "E0212",
+            RuntimeError::UndefinedLabel(_) => "E0213",
+            RuntimeError::BadOperator(..) => "E0300",
+            RuntimeError::BadStatement(_) => "E0301",
+            RuntimeError::UndefinedVariable(_) => "E0302",
+            RuntimeError::RedefinedVariable(_) => "E0303",
+            RuntimeError::BadExpr() => "E0304",
+            RuntimeError::BadCallable(_) => "E0306",
+            RuntimeError::ArityMismatch(..) => "E0307",
+            RuntimeError::NativeFunctionError(_) => "E0308",
+            RuntimeError::BadArguments(_) => "E0310",
+            RuntimeError::InvalidFunction(_) => "E0311",
+            RuntimeError::OnlyInstancesHaveProperties(_) => "E0312",
+            RuntimeError::UndefinedProperty(_) => "E0313",
+            RuntimeError::CantAccessPrivateMethod(_) => "E0314",
+            RuntimeError::CantCallStaticMethodFromInstance(_) => "E0315",
+            RuntimeError::SuperClassMustBeSuperAClass(_) => "E0316",
+            RuntimeError::InvalidSuperclass(_) => "E0317",
+            RuntimeError::UnresolvedSuper(_) => "E0318",
+            RuntimeError::ArgumentTypeMismatch(..) => "E0319",
+            RuntimeError::ReadOnlyProperty(_) => "E0320",
+            RuntimeError::NativeSuperclass(_) => "E0321",
+            RuntimeError::IndexOutOfRange(..) => "E0322",
+            RuntimeError::InvalidIndex(..) => "E0323",
+            RuntimeError::NotIndexable(..) => "E0324",
+            RuntimeError::ImmutableIndex(..) => "E0325",
+            RuntimeError::UnhashableKey(..) => "E0326",
+            RuntimeError::KeyNotFound(..) => "E0327",
+            RuntimeError::NotSliceable(..) => "E0328",
+            RuntimeError::IntegerOverflow(_) => "E0329",
+            RuntimeError::StackOverflow(..) => "E0330",
+        }
+    }
+
+    /// The error text without the `[RUNTIME ERROR]` prefix.
+    pub fn message(&self) -> String {
+        match self {
+            RuntimeError::Traceback(error, _) => error.message(),
             RuntimeError::BadOperator(operator, message) => {
-                write!(f, "[RUNTIME ERROR]: Invalid operator '{}' used: {}", operator, message)
+                format!("Invalid operator '{}' used: {}", operator, message)
             }
-            RuntimeError::BadStatement(message) => {
-                write!(f, "[RUNTIME ERROR]: Invalid statement: {}", message)
+            RuntimeError::BadStatement(message) => format!("Invalid statement: {}", message),
+            RuntimeError::UndefinedVariable(token) => format!("Undefined variable: '{}'", token.lexeme),
+            RuntimeError::RedefinedVariable(name) => {
+                format!("The variable '{}' has already been defined and cannot be redefined.", name)
             }
-            RuntimeError::UndefinedVariable(token) => {
-                write!(f, "[RUNTIME ERROR]: Undefined variable: '{}'", token.lexeme)
+            RuntimeError::BadExpr() => "Invalid expression.".to_string(),
+            RuntimeError::BadCallable(_) => {
+                "Only functions and classes can be called as callable objects.".to_string()
             }
-            RuntimeError::RedefinedVariable(name) => {
-                write!(f, "[RUNTIME ERROR]: The variable '{}' has already been defined and cannot be redefined.", name)
+            RuntimeError::ArityMismatch(_, callee, arity, args_size) => {
+                format!("'{}' expects {} but received {}.", callee, arity, args_size)
             }
-            RuntimeError::BadExpr() => {
-                write!(f, "[RUNTIME ERROR]: Invalid expression.")
+            RuntimeError::NativeFunctionError(message) => format!("Error in native function: {}.", message),
+            RuntimeError::ArgumentTypeMismatch(function, index, expected, found) => {
+                format!("Argument {} of '{}' must be of type {}, found {}.", index + 1, function, expected, found)
             }
-            RuntimeError::Break() => {
-                write!(f, "[RUNTIME ERROR]: 'Break' statement used outside of a loop or control flow context.")
+            RuntimeError::ReturnOutsideFunction(_) => {
+                "'Return' statement used outside of a function.".to_string()
             }
-            RuntimeError::BadCallable() => {
-                write!(f, "[RUNTIME ERROR]: Only functions and classes can be called as callable objects.")
+            RuntimeError::OutsideLoop(keyword) => format!("Can't use '{}' outside of a loop.", keyword.lexeme),
+            RuntimeError::UndefinedLabel(label) => format!("No enclosing loop is labeled '{}'.", label.lexeme),
+            RuntimeError::CantReturnFromInitializer(_) => "Cannot return a value from an initializer.".to_string(),
+            RuntimeError::BadArguments(m) => format!("Invalid arguments provided: {}", m),
+            RuntimeError::InvalidFunction(m) => format!("The function '{}' is invalid or undefined.", m),
+            RuntimeError::OnlyInstancesHaveProperties(_) => {
+                "Only instances (not classes) can have properties.".to_string()
             }
-            RuntimeError::ToManyArguments(paren, arity , args_size) => {
-                write!(f, "[RUNTIME ERROR]: In '{}': Expected {} arguments but received {}.", paren, arity, args_size)
+            RuntimeError::UndefinedProperty(name) => {
+                format!("Property '{}' does not exist or is inaccessible.", name.lexeme)
             }
-            RuntimeError::NativeFunctionError(message) => {
-                write!(f, "[RUNTIME ERROR]: Error in native function: {}.", message)
+            RuntimeError::CantAccessPrivateMethod(name) => {
+                format!("Attempted to access the private method '{}' outside its class.", name.lexeme)
             }
-            RuntimeError::Return(_) => {
-                write!(f, "[RUNTIME ERROR]: 'Return' statement used outside of a function.")
+            RuntimeError::CantCallStaticMethodFromInstance(name) => {
+                format!("Cannot call the static method '{}' from an instance of the class.", name.lexeme)
             }
-            RuntimeError::CantReturnFromInitializer() => {
-                write!(f, "[RUNTIME ERROR]: Cannot return a value from an initializer.")
+            RuntimeError::ClassInheritFromItself(_) => "A class can't inherit from itself.".to_string(),
+            RuntimeError::SuperClassMustBeSuperAClass(_) => "SuperClass must be a SuperClass.".to_string(),
+            RuntimeError::InvalidSuperclass(_) => "Invalid Superclass.".to_string(),
+            RuntimeError::UnresolvedSuper(_) => "Unresolved Super.".to_string(),
+            RuntimeError::InvalidClassMember() => "Invalid class member.".to_string(),
+            RuntimeError::ReadOnlyProperty(name) => format!("Property '{}' is read-only.", name.lexeme),
+            RuntimeError::NativeSuperclass(name) => {
+                format!("Can't inherit from '{}', a class defined by the host.", name.lexeme)
             }
-            RuntimeError::BadArguments(m) => {
-                write!(f,"[RUNTIME ERROR]: Invalid arguments provided: {}", m)
+            RuntimeError::IndexOutOfRange(_, index, len) => {
+                format!("Index {} is out of range for a length of {}.", index, len)
             }
-            RuntimeError::InvalidFunction(m) => {
-                write!(f,"[RUNTIME ERROR]: The function '{}' is invalid or undefined.", m)
+            RuntimeError::InvalidIndex(_, found) => format!("Indices must be whole numbers, found {}.", found),
+            RuntimeError::NotIndexable(_, found) => format!("Values of type {} can't be indexed.", found),
+            RuntimeError::ImmutableIndex(_, found) => {
+                format!("Values of type {} can't be changed through an index.", found)
             }
-            RuntimeError::OnlyInstancesHaveProperties() => {
-                write!(f,"[RUNTIME ERROR]: Only instances (not classes) can have properties.")
+            RuntimeError::NotSliceable(_, found) => format!("Values of type {} can't be sliced.", found),
+            RuntimeError::UnhashableKey(_, found) => format!("Values of type {} can't be used as map keys.", found),
+            RuntimeError::KeyNotFound(_, key) => format!("Key {} is not in the map.", key),
+            RuntimeError::IntegerOverflow(operator) => format!("Integer overflow in '{}'.", operator.lexeme),
+            RuntimeError::StackOverflow(_, limit) => format!("Stack overflow: more than {} calls deep.", limit),
+            RuntimeError::SuperOutsideClass(_) => "Can't use 'super' outside of a class.".to_string(),
+            RuntimeError::SuperWithoutSubclass(_) => {
+                "Can't use 'super' in a class with no superclass.".to_string()
             }
-            RuntimeError::UndefinedProperty() => {
-                write!(f,"[RUNTIME ERROR]: Property does not exist or is inaccessible.")
+            RuntimeError::ThisOutsideClass(_) => "Can't use 'this' outside of a class.".to_string(),
+            RuntimeError::VariableAlreadyDeclared(token, _) => {
+                format!("Already exists a variable called '{}' in this scope.", token.lexeme)
             }
-            RuntimeError::CantAccessPrivateMethod() => {
-                write!(f,"[RUNTIME ERROR]: Attempted to access a private method outside its class.")
+            RuntimeError::ReadLocalInOwnInitializer(token) => {
+                format!("Can't read local variable '{}' in its own initializer.", token.lexeme)
             }
-            RuntimeError::CantCallStaticMethodFromInstance() => {
-                write!(f,"[RUNTIME ERROR]: Cannot call a static method from an instance of the class.")
-            }   
-            RuntimeError::ClassInheritFromItself() => {
-                write!(f,"[RUNTIME ERROR]: A class can't inherit from itself.")
+            RuntimeError::TooManyConstants(_) => "Too many constants in one function.".to_string(),
+            RuntimeError::TooManyLocals(_) => "Too many local variables in one function.".to_string(),
+            RuntimeError::TooManyUpvalues(_) => "Too many closure variables in one function.".to_string(),
+        }
+    }
+
+    pub fn diagnostic(&self) -> Diagnostic {
+        if let RuntimeError::Traceback(error, frames) = self {
+            return frames
+                .iter()
+                .fold(error.diagnostic(), |diagnostic, frame| {
+                    diagnostic.with_frame(&frame.function, frame.call_site)
+                });
+        }
+
+        let mut diagnostic = Diagnostic::error(self.code(), self.message());
+        if let Some(span) = self.span() {
+            let label = match self {
+                RuntimeError::UndefinedVariable(_) => "not found in this scope",
+                RuntimeError::BadCallable(_) => "call expression here",
+                RuntimeError::ArityMismatch(..) => "called here",
+                RuntimeError::VariableAlreadyDeclared(..) => "redeclared here",
+                RuntimeError::ReadLocalInOwnInitializer(_) => "read here",
+                RuntimeError::UndefinedLabel(_) => "unknown label",
+                RuntimeError::ClassInheritFromItself(_) => "superclass is the class being declared",
+                RuntimeError::IndexOutOfRange(..) | RuntimeError::InvalidIndex(..) => "indexed here",
+                RuntimeError::UnhashableKey(..) => "used as a key here",
+                RuntimeError::KeyNotFound(..) => "looked up here",
+                RuntimeError::IntegerOverflow(_) => "result doesn't fit in 64 bits",
+                RuntimeError::StackOverflow(..) => "one call too many",
+                _ => "",
+            };
+            diagnostic = diagnostic.with_label(span, label);
+        }
+
+        match self {
+            RuntimeError::VariableAlreadyDeclared(token, previous) => diagnostic
+                .with_secondary(*previous, format!("'{}' first declared here", token.lexeme))
+                .with_help("rename one of the variables or drop the second `var`"),
+            RuntimeError::SuperWithoutSubclass(_) => {
+                diagnostic.with_help("declare a superclass with `class Name < Parent`")
+            }
+            RuntimeError::CantReturnFromInitializer(_) => {
+                diagnostic.with_help("`init` always returns the new instance")
+            }
+            RuntimeError::UndefinedLabel(_) => {
+                diagnostic.with_help("only loops around the statement in the same function can be named")
+            }
+            RuntimeError::CantAccessPrivateMethod(_) => {
+                diagnostic.with_help("mark the method with `pub` to call it from outside the class")
+            }
+            RuntimeError::IndexOutOfRange(..) => {
+                diagnostic.with_help("valid indices go from 0 to length - 1, or from -length to -1 counting from the end")
             }
-            RuntimeError::SuperClassMustBeSuperAClass() => {
-                write!(f,"[RUNTIME ERROR]: SuperClass must be a SuperClass.")
+            RuntimeError::UnhashableKey(..) => {
+                diagnostic.with_help("map keys can be numbers, strings, booleans or nil")
+            }
+            RuntimeError::KeyNotFound(..) => {
+                diagnostic.with_help("check with `has` first, or use `get` with a default")
+            }
+            RuntimeError::IntegerOverflow(_) => {
+                diagnostic.with_help("make an operand a float, like `2.0`, to get an approximate result")
+            }
+            RuntimeError::StackOverflow(..) => {
+                diagnostic.with_help("check that the recursion reaches a case that returns without calling again")
+            }
+            _ => diagnostic,
+        }
+    }
+}
 
-            }  
-            RuntimeError::InvalidSuperclass() => {
-                write!(f,"[RUNTIME ERROR]: Invalid Superclass.")
-            }     
-            RuntimeError::UnresolvedSuper() => {
-                write!(f,"[RUNTIME ERROR]: Unresolved Super.")
-            }  
-            RuntimeError::InvalidClassMember() => {
-                write!(f,"[RUNTIME ERROR]: Invalid class member.")
-            }  
-            RuntimeError::SuperOutsideClass() => {
-                write!(f,"[RUNTIME ERROR]: Can't use 'super' outside of a class.")
-            }
-            RuntimeError::SuperWithoutSubclass() => {
-                write!(f,"[RUNTIME ERROR]: Can't use 'super' in a class with no superclass.")
-            }
-            
+impl fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "[RUNTIME ERROR]: {}", self.message())?;
+        let frames: Vec<&CallFrame> = self.traceback().iter().rev().collect();
+        for (frame, times) in diagnostic::runs(&frames) {
+            for _ in 0..times.min(REPEATS_SHOWN) {
+                write!(f, "\n    in {}() called at line {}", frame.function, frame.call_site)?;
+            }
+            if times > REPEATS_SHOWN {
+                write!(f, "\n    [previous call repeated {} more times]", times - REPEATS_SHOWN)?;
+            }
         }
+        Ok(())
     }
 }
 
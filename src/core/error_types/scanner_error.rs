@@ -1,28 +1,85 @@
+use crate::core::diagnostics::diagnostic::Diagnostic;
+use crate::core::syntax::span::Span;
+
 #[derive(Debug, Clone)]
 pub enum ScannerError {
-    UnexpectedCharacter(char, usize), // Carácter inesperado en una posición
-    UnterminatedString(usize),        // Cadena no terminada
-    UnfinishedMultilineComment(usize), // Comentario multilinea sin terminar
-    InvalidEscapeSequence(usize),     // Secuencia de escape no válida
+    UnexpectedCharacter(char, Span), // Carácter inesperado en una posición
+    UnterminatedString(Span),        // Cadena no terminada
+    UnfinishedMultilineComment(Span), // Comentario multilinea sin terminar
+    InvalidEscapeSequence(Span),     // Secuencia de escape no válida
+    UnterminatedRawString(Span),     // Cadena """ no terminada
+    InvalidNumber(Span),             // Número mal formado
+    IntegerTooLarge(Span),           // Entero que no cabe en 64 bits
 }
 
-impl std::fmt::Display for ScannerError {
-    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+impl ScannerError {
+    pub fn span(&self) -> Span {
+        match self {
+            ScannerError::UnexpectedCharacter(_, span)
+            | ScannerError::UnterminatedString(span)
+            | ScannerError::UnfinishedMultilineComment(span)
+            | ScannerError::InvalidEscapeSequence(span)
+            | ScannerError::UnterminatedRawString(span)
+            | ScannerError::InvalidNumber(span)
+            | ScannerError::IntegerTooLarge(span) => *span,
+        }
+    }
+
+    pub fn code(&self) -> &'static str {
+        match self {
+            ScannerError::UnexpectedCharacter(..) => "E0001",
+            ScannerError::UnterminatedString(_) => "E0002",
+            ScannerError::UnfinishedMultilineComment(_) => "E0003",
+            ScannerError::InvalidEscapeSequence(_) => "E0004",
+            ScannerError::UnterminatedRawString(_) => "E0005",
+            ScannerError::InvalidNumber(_) => "E0006",
+            ScannerError::IntegerTooLarge(_) => "E0007",
+        }
+    }
+
+    /// The error text without the `[SCANNER]` prefix or location.
+    pub fn message(&self) -> String {
+        match self {
+            ScannerError::UnexpectedCharacter(c, _) => format!("Unexpected character '{}'", c),
+            ScannerError::UnterminatedString(_) => "Unterminated string".to_string(),
+            ScannerError::UnfinishedMultilineComment(_) => "Unfinished multiline comment".to_string(),
+            ScannerError::InvalidEscapeSequence(_) => "Invalid escape sequence".to_string(),
+            ScannerError::UnterminatedRawString(_) => "Unterminated raw string".to_string(),
+            ScannerError::InvalidNumber(_) => "Invalid number literal".to_string(),
+            ScannerError::IntegerTooLarge(_) => "Integer literal too large".to_string(),
+        }
+    }
+
+    pub fn diagnostic(&self) -> Diagnostic {
+        let diagnostic = Diagnostic::error(self.code(), self.message());
         match self {
-            ScannerError::UnexpectedCharacter(c, line) => {
-                write!(f, "[SCANNER]: Unexpected character '{}' at line {}", c, line)
-            }
-            ScannerError::UnterminatedString(line) => {
-                write!(f, "[SCANNER]: Unterminated string at line {}", line)
-            }
-            ScannerError::UnfinishedMultilineComment(line) => {
-                write!(f, "[SCANNER]: Unfinished multiline comment at line {}", line)
-            }
-            ScannerError::InvalidEscapeSequence(line) => {
-                write!(f, "[SCANNER]: Invalid escape sequence at line {}", line)
-            }
+            ScannerError::UnterminatedString(span) => diagnostic
+                .with_label(*span, "string starts here")
+                .with_help("add a closing `\"`"),
+            ScannerError::UnfinishedMultilineComment(span) => diagnostic
+                .with_label(*span, "comment starts here")
+                .with_help("close the comment with `*/`"),
+            ScannerError::InvalidEscapeSequence(span) => diagnostic
+                .with_label(*span, "not a valid escape")
+                .with_help("escapes are `\\n`, `\\t`, `\\r`, `\\0`, `\\\\`, `\\\"`, `\\$` and `\\u{...}` with up to 6 hex digits"),
+            ScannerError::UnterminatedRawString(span) => diagnostic
+                .with_label(*span, "raw string starts here")
+                .with_help("add a closing `\"\"\"`"),
+            ScannerError::InvalidNumber(span) => diagnostic
+                .with_label(*span, "not a valid number")
+                .with_help("numbers look like `42`, `1_000`, `3.14`, `2.5e-3`, `0xFF`, `0b1010` or `0o17`"),
+            ScannerError::IntegerTooLarge(span) => diagnostic
+                .with_label(*span, "doesn't fit in 64 bits")
+                .with_help("write it as a float, like `1e20`"),
+            _ => diagnostic.with_label(self.span(), ""),
         }
     }
 }
 
+impl std::fmt::Display for ScannerError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "[SCANNER]: {} at line {}", self.message(), self.span().line)
+    }
+}
+
 impl std::error::Error for ScannerError {}
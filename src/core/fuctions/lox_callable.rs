@@ -1,7 +1,63 @@
-use crate::core::{error_types::runtime_error::RuntimeError, interpreter::Interpreter, syntax::components::expression::LoxValue};
+use std::fmt;
 
+use crate::core::{error_types::runtime_error::RuntimeError, gc::{Trace, Tracer}, interpreter::Interpreter, syntax::components::{expression::LoxValue, stmt::Param}};
+
+/// How many arguments a callable accepts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Arity {
+    Exact(usize),
+    // Both ends included
+    Range(usize, usize),
+    AtLeast(usize),
+}
+
+impl Arity {
+    /// What a function declared with `params` accepts: every parameter
+    /// without a default is required, and a rest parameter lifts the limit.
+    pub fn of(params: &[Param]) -> Arity {
+        let required = params.iter().filter(|param| param.default.is_none() && !param.rest).count();
+        let positional = params.iter().filter(|param| !param.rest).count();
+        match params.last() {
+            Some(param) if param.rest => Arity::AtLeast(required),
+            _ if positional > required => Arity::Range(required, positional),
+            _ => Arity::Exact(required),
+        }
+    }
+
+    pub fn accepts(&self, count: usize) -> bool {
+        match *self {
+            Arity::Exact(n) => count == n,
+            Arity::Range(min, max) => (min..=max).contains(&count),
+            Arity::AtLeast(min) => count >= min,
+        }
+    }
+}
+
+// "1 argument", "1 to 3 arguments", "at least 2 arguments"
+impl fmt::Display for Arity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (text, last) = match *self {
+            Arity::Exact(n) => (n.to_string(), n),
+            Arity::Range(min, max) => (format!("{} to {}", min, max), max),
+            Arity::AtLeast(min) => (format!("at least {}", min), min),
+        };
+        let plural = if last == 1 { "" } else { "s" };
+        write!(f, "{} argument{}", text, plural)
+    }
+}
 
 pub trait LoxCallable {
     fn call(&self, interpreter: &mut Interpreter, arguments: Vec<LoxValue>) -> Result<LoxValue, RuntimeError>;
-    fn arity(&self) -> usize;
-}
\ No newline at end of file
+    fn arity(&self) -> Arity;
+    /// Shown in stack traces.
+    fn name(&self) -> String;
+    /// Reports the objects this callable keeps alive to the cycle
+    /// collector. Natives usually hold none.
+    fn trace(&self, _tracer: &mut Tracer) {}
+}
+
+impl Trace for dyn LoxCallable {
+    fn trace(&self, tracer: &mut Tracer) {
+        LoxCallable::trace(self, tracer)
+    }
+}
@@ -1,9 +1,9 @@
 use core::fmt;
 use std::{cell::RefCell, fmt::{Display, Formatter}, rc::Rc};
 
-use crate::core::{environment::Environment, error_types::runtime_error::RuntimeError, interpreter::Interpreter, oop::{lox_class::LoxClass, lox_instance::LoxInstance}, syntax::components::{expression::LoxValue, stmt::Stmt}};
+use crate::core::{control_flow::ControlFlow, environment::Environment, error_types::runtime_error::RuntimeError, gc::Tracer, interpreter::Interpreter, oop::{lox_class::LoxClass, lox_instance::LoxInstance}, syntax::components::{expression::LoxValue, stmt::Stmt}};
 
-use super::lox_callable::LoxCallable;
+use super::lox_callable::{Arity, LoxCallable};
 #[derive(Clone, Debug)]
 
 pub struct LoxFunction {
@@ -11,23 +11,31 @@ pub struct LoxFunction {
     closure: Rc<RefCell<Environment>>,
     is_initializer: bool,
     is_public: bool,
-    is_static: bool
+    is_static: bool,
+    // Set for methods, so stack traces read `Class.method`
+    class_name: Option<String>
 }
 
 impl LoxFunction {
     pub fn new(declaration: Stmt, closure: Rc<RefCell<Environment>>, is_initializer: bool, is_public: bool, is_static: bool) -> Self {
-        Self { declaration, closure, is_initializer, is_public, is_static}
+        Self { declaration, closure, is_initializer, is_public, is_static, class_name: None }
+    }
+
+    pub fn in_class(mut self, class_name: &str) -> Self {
+        self.class_name = Some(class_name.to_string());
+        self
     }
     pub fn bind(&self, instance: Rc<RefCell<LoxInstance>>) -> Result<LoxFunction, RuntimeError> {
         let env = Rc::new(RefCell::new(Environment::new(Some(Rc::clone(&self.closure))))); 
-        env.borrow_mut().define("this", LoxValue::LoxInstance(instance))?;
-        Ok(LoxFunction::new(self.declaration.clone(), env, self.is_initializer, self.is_public, self.is_static))
+        env.borrow_mut().push(LoxValue::LoxInstance(instance));
+        Ok(LoxFunction { closure: env, ..self.clone() })
     }
 
     pub fn inject(&self, lox_class: &LoxClass) -> Result<LoxFunction, RuntimeError> {
         let env = Rc::new(RefCell::new(Environment::new(Some(Rc::clone(&self.closure))))); 
-        env.borrow_mut().define(&lox_class.name, LoxValue::LoxClass(lox_class.clone()))?;
-        Ok(LoxFunction::new(self.declaration.clone(), env, self.is_initializer, self.is_public, self.is_static))
+        // Takes the slot `this` has in instance methods
+        env.borrow_mut().push(LoxValue::LoxClass(lox_class.clone()));
+        Ok(LoxFunction { closure: env, ..self.clone() })
     }
 
     pub fn is_public(&self) -> bool {
@@ -49,38 +57,51 @@ impl LoxCallable for LoxFunction {
         
         
         if let Stmt::Function { params, body, .. } = &self.declaration {
-            if arguments.len() < params.len() {
+            if !self.arity().accepts(arguments.len()) {
                 return Err(RuntimeError::BadArguments(format!(
-                    "Expected {} arguments but got {}.",
-                    params.len(),
+                    "Expected {} but got {}.",
+                    self.arity(),
                     arguments.len()
                 )));
             }
 
-            for (i, param) in params.iter().enumerate() {
-                env.borrow_mut().define(&param.lexeme, arguments[i].clone())?;
+            interpreter.heap.track(&env);
+            let mut arguments = arguments.into_iter();
+            for param in params {
+                let value = if param.rest {
+                    let rest = Rc::new(RefCell::new(arguments.by_ref().collect()));
+                    interpreter.heap.track(&rest);
+                    LoxValue::List(rest)
+                } else if let Some(argument) = arguments.next() {
+                    argument
+                } else if let Some(default) = &param.default {
+                    // Pushed in order, so a default sees the parameters before it
+                    interpreter.evaluate_in(default, Rc::clone(&env))?
+                } else {
+                    LoxValue::Nil
+                };
+                env.borrow_mut().push(value);
             }
 
-            if self.is_initializer {
-                return Ok(self.closure.borrow().get_at(0, "this")?);
-            }  
-            
-            
-            match interpreter.execute_block(body, Rc::clone(&env)) {
-                Ok(_) => {
-                    return Ok(LoxValue::Nil);
-                },
-                Err(RuntimeError::Return(LoxValue::Nil)) => {
-                    return Ok(LoxValue::Nil);
+            let signal = interpreter.execute_block(body, Rc::clone(&env))?;
+
+            // `init` always hands back the instance, also when called directly
+            if self.is_initializer && !matches!(signal, Some(ControlFlow::Break { .. } | ControlFlow::Continue { .. })) {
+                return self.closure.borrow().get_at(0, 0).ok_or_else(|| {
+                    RuntimeError::InvalidFunction("Initializer is not bound to an instance.".to_string())
+                });
+            }
+
+            match signal {
+                Some(ControlFlow::Return(v)) => {
+                    Ok(v)
                 },
-                Err(RuntimeError::Return(v)) => {
-                    if self.is_initializer {
-                        return self.closure.borrow().get_at(0, "this");
-                    }
-                    return Ok(v);
+                // The resolver keeps `break`/`continue` inside loops
+                Some(ControlFlow::Break { keyword, .. } | ControlFlow::Continue { keyword, .. }) => {
+                    Err(RuntimeError::OutsideLoop(keyword))
                 },
-                Err(e) => {
-                    return Err(e);
+                None => {
+                    Ok(LoxValue::Nil)
                 }
             }
         } else {
@@ -88,13 +109,28 @@ impl LoxCallable for LoxFunction {
         }
     }
 
-    fn arity(&self) -> usize {
+    fn arity(&self) -> Arity {
         if let Stmt::Function { params, .. } = &self.declaration {
-            params.len()
+            Arity::of(params)
         } else {
-            0
+            Arity::Exact(0)
+        }
+    }
+
+    fn name(&self) -> String {
+        let name = match &self.declaration {
+            Stmt::Function { token, .. } => token.lexeme.clone(),
+            _ => "anonymous".to_string(),
+        };
+        match &self.class_name {
+            Some(class_name) => format!("{}.{}", class_name, name),
+            None => name,
         }
     }
+
+    fn trace(&self, tracer: &mut Tracer) {
+        tracer.edge(&self.closure);
+    }
 }
 
 impl Display for LoxFunction {
@@ -0,0 +1,225 @@
+use std::fmt;
+use std::rc::{Rc, Weak};
+
+use rustc_hash::FxHashMap;
+
+/// Tracked allocations before the first automatic collection.
+const INITIAL_THRESHOLD: usize = 16 * 1024;
+
+/// An object the cycle collector can look into.
+///
+/// Lox objects are reference counted, which frees everything except cycles:
+/// an instance holding a bound method of itself, or a closure stored in the
+/// scope it captured. Every such cycle runs through a mutable container
+/// (an environment, an instance, a class or an upvalue), so only those are
+/// tracked by the [`Heap`]; immutable objects in between are found by
+/// tracing.
+pub trait Trace {
+    /// Hands `tracer` every traceable object this one holds a strong
+    /// reference to, once per reference.
+    fn trace(&self, tracer: &mut Tracer);
+
+    /// Drops the references this object holds. Only called on objects the
+    /// collector proved unreachable, to break their cycles.
+    fn clear(&self) {}
+}
+
+/// Collects the outgoing references of one object during a collection.
+pub struct Tracer {
+    edges: Vec<Box<dyn Node>>,
+    opaque: bool,
+}
+
+impl Tracer {
+    fn new() -> Self {
+        Self { edges: vec![], opaque: false }
+    }
+
+    pub fn edge<T: Trace + ?Sized + 'static>(&mut self, object: &Rc<T>) {
+        self.edges.push(Box::new(Rc::clone(object)));
+    }
+
+    /// Marks the traced object as unreadable right now (it is mutably
+    /// borrowed), so it and everything it may reach are kept.
+    pub fn opaque(&mut self) {
+        self.opaque = true;
+    }
+}
+
+// A strong handle to some traceable object
+trait Node {
+    fn address(&self) -> usize;
+    fn strong_count(&self) -> usize;
+    fn trace(&self, tracer: &mut Tracer);
+    fn clear(&self);
+}
+
+impl<T: Trace + ?Sized> Node for Rc<T> {
+    fn address(&self) -> usize {
+        Rc::as_ptr(self) as *const () as usize
+    }
+
+    fn strong_count(&self) -> usize {
+        Rc::strong_count(self)
+    }
+
+    fn trace(&self, tracer: &mut Tracer) {
+        Trace::trace(&**self, tracer)
+    }
+
+    fn clear(&self) {
+        Trace::clear(&**self)
+    }
+}
+
+// A tracked allocation, which the heap must not keep alive
+trait Tracked {
+    fn upgrade(&self) -> Option<Box<dyn Node>>;
+    fn is_alive(&self) -> bool;
+}
+
+impl<T: Trace + ?Sized + 'static> Tracked for Weak<T> {
+    fn upgrade(&self) -> Option<Box<dyn Node>> {
+        Weak::upgrade(self).map(|object| Box::new(object) as Box<dyn Node>)
+    }
+
+    fn is_alive(&self) -> bool {
+        self.strong_count() > 0
+    }
+}
+
+/// Counters reported by [`Heap::stats`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HeapStats {
+    /// Tracked objects still alive.
+    pub live: usize,
+    pub collections: usize,
+    /// Objects freed by all collections so far.
+    pub reclaimed: usize,
+    /// Tracked allocations that trigger the next automatic collection.
+    pub threshold: usize,
+}
+
+impl fmt::Display for HeapStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "live objects: {}, collections: {}, reclaimed: {}, next collection at: {}",
+            self.live, self.collections, self.reclaimed, self.threshold
+        )
+    }
+}
+
+/// The cycle collector.
+///
+/// A collection traces everything reachable from the tracked objects and
+/// counts the references found between them. An object whose strong count
+/// is higher than that is also held from outside the graph (a variable on
+/// the Rust stack, the globals, the VM stack) and is a root. Whatever no
+/// root reaches only keeps itself alive, so it is cleared and freed.
+pub struct Heap {
+    tracked: Vec<Box<dyn Tracked>>,
+    threshold: usize,
+    collections: usize,
+    reclaimed: usize,
+}
+
+impl Default for Heap {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Heap {
+    pub fn new() -> Self {
+        Self {
+            tracked: vec![],
+            threshold: INITIAL_THRESHOLD,
+            collections: 0,
+            reclaimed: 0,
+        }
+    }
+
+    /// Registers a new object and collects once enough have piled up
+    /// since the last collection.
+    pub fn track<T: Trace + ?Sized + 'static>(&mut self, object: &Rc<T>) {
+        self.tracked.push(Box::new(Rc::downgrade(object)));
+        if self.tracked.len() >= self.threshold {
+            self.collect();
+        }
+    }
+
+    /// Frees every unreachable cycle and returns how many objects went with
+    /// them.
+    pub fn collect(&mut self) -> usize {
+        self.tracked.retain(|object| object.is_alive());
+
+        // Exactly one handle per object lives in `nodes` from here on
+        let mut nodes: Vec<Box<dyn Node>> = vec![];
+        let mut index: FxHashMap<usize, usize> = FxHashMap::default();
+        for node in self.tracked.iter().filter_map(|object| object.upgrade()) {
+            index.entry(node.address()).or_insert_with(|| {
+                nodes.push(node);
+                nodes.len() - 1
+            });
+        }
+
+        let mut edges: Vec<Vec<usize>> = vec![];
+        let mut opaque: Vec<bool> = vec![];
+        let mut current = 0;
+        while current < nodes.len() {
+            let mut tracer = Tracer::new();
+            nodes[current].trace(&mut tracer);
+            let targets = tracer
+                .edges
+                .into_iter()
+                .map(|node| {
+                    *index.entry(node.address()).or_insert_with(|| {
+                        nodes.push(node);
+                        nodes.len() - 1
+                    })
+                })
+                .collect();
+            edges.push(targets);
+            opaque.push(tracer.opaque);
+            current += 1;
+        }
+
+        let mut internal = vec![0; nodes.len()];
+        for &target in edges.iter().flatten() {
+            internal[target] += 1;
+        }
+
+        let mut reachable = vec![false; nodes.len()];
+        let mut pending: Vec<usize> = (0..nodes.len())
+            .filter(|&node| opaque[node] || nodes[node].strong_count() - 1 > internal[node])
+            .collect();
+        while let Some(node) = pending.pop() {
+            if !reachable[node] {
+                reachable[node] = true;
+                pending.extend(edges[node].iter().copied().filter(|&target| !reachable[target]));
+            }
+        }
+
+        let garbage: Vec<usize> = (0..nodes.len()).filter(|&node| !reachable[node]).collect();
+        for &node in &garbage {
+            nodes[node].clear();
+        }
+        drop(nodes);
+
+        self.tracked.retain(|object| object.is_alive());
+        self.threshold = INITIAL_THRESHOLD.max(self.tracked.len() * 2);
+        self.collections += 1;
+        self.reclaimed += garbage.len();
+        garbage.len()
+    }
+
+    pub fn stats(&self) -> HeapStats {
+        HeapStats {
+            live: self.tracked.iter().filter(|object| object.is_alive()).count(),
+            collections: self.collections,
+            reclaimed: self.reclaimed,
+            threshold: self.threshold,
+        }
+    }
+}
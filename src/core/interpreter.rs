@@ -1,37 +1,60 @@
 use std::cell::RefCell;
+use std::cmp::Ordering;
 use std::rc::Rc;
-use std::usize;
 
 use rustc_hash::FxHashMap;
+use super::control_flow::ControlFlow;
 use super::environment::Environment;
-use super::error_types::runtime_error::RuntimeError;
+use super::gc::Heap;
+use super::error_types::runtime_error::{CallFrame, RuntimeError, DEFAULT_CALL_DEPTH};
 
+use super::fuctions::lox_callable::Arity;
 use super::fuctions::lox_function::LoxFunction;
+use super::native_functions::collection::{Element, Host};
 use super::native_functions::lox_clock::LoxClock;
+use super::native_functions::lox_gc::LoxGc;
+use super::native_functions::lox_list::LoxListNew;
+use super::native_functions::lox_list::{self, LoxListMethod};
+use super::native_functions::lox_map::{LoxMap, LoxMapMethod, MapKey};
+use super::native_functions::lox_math::{LoxMax, LoxMin};
 use super::native_functions::lox_print::{LoxDbg, LoxPrint, LoxPrintLn};
+use super::native_functions::lox_string::LoxToString;
+use super::number::Number;
 use super::oop::lox_class::LoxClass;
 use super::syntax::components::expression::{Expr, LoxValue, Visitor as ExpressionVisitor};
-use super::syntax::components::stmt::{Stmt, Visitor as StatementVisitor};
+use super::syntax::components::stmt::{Param, Stmt, Visitor as StatementVisitor};
 use super::syntax::token::Token;
 use super::syntax::token_type::TokenType;
 
 pub struct Interpreter {
-    pub globals: Box<Environment>,
+    pub globals: Rc<RefCell<Environment>>,
     pub environment: Rc<RefCell<Environment>>,
-    pub locals: FxHashMap<Expr, usize>,
+    // Expression id -> (frames up, slot) for every resolved local
+    pub locals: FxHashMap<usize, (usize, usize)>,
+    call_stack: Vec<CallFrame>,
+    max_call_depth: usize,
+    // Tracks environments and instances so cycles between them get freed
+    pub heap: Heap,
 }
 
 impl ExpressionVisitor<LoxValue> for Interpreter {
     fn visit_unary(&mut self, operator: &Token, right: &Expr,) -> Result<LoxValue, RuntimeError> {
         let lit = self.evaluate(right)?;
         match operator.t_type {
-            TokenType::MINUS => match lit {
-                LoxValue::Number(n) => Ok(LoxValue::Number(-n)),
-                _ => Err(RuntimeError::BadOperator(
+            TokenType::MINUS => match Number::from_lox(&lit) {
+                Some(n) => n.checked_neg().map(Number::to_lox).map_err(|error| error.at(operator)),
+                None => Err(RuntimeError::BadOperator(
                     operator.clone(),
                     "Operand must be a number.".to_string(),
                 )),
             },
+            TokenType::TILDE => match Number::from_lox(&lit) {
+                Some(n) => n.bit_not().map(Number::to_lox).map_err(|error| error.at(operator)),
+                None => Err(RuntimeError::BadOperator(
+                    operator.clone(),
+                    "Operand must be an integer.".to_string(),
+                )),
+            },
             TokenType::BANG => Ok(LoxValue::Boolean(!self.is_truthy(&lit))),
             _ => Err(RuntimeError::BadOperator(
                 operator.clone(),
@@ -48,58 +71,7 @@ impl ExpressionVisitor<LoxValue> for Interpreter {
     ) -> Result<LoxValue, RuntimeError> {
         let left_lit = self.evaluate(left)?;
         let right_lit = self.evaluate(right)?;
-
-        match (operator.t_type.clone(), &left_lit, &right_lit) {
-            (TokenType::PLUS, LoxValue::Number(n1), LoxValue::Number(n2)) => {
-                Ok(LoxValue::Number(n1 + n2))
-            }
-            (TokenType::PLUS, LoxValue::String(s1), LoxValue::String(s2)) => {
-                Ok(LoxValue::String(format!("{}{}", s1, s2)))
-            }
-            (TokenType::PLUS, LoxValue::String(s1), LoxValue::Number(n)) => {
-                Ok(LoxValue::String(format!("{}{}", s1, n)))
-            }
-            (TokenType::MINUS, LoxValue::Number(n1), LoxValue::Number(n2)) => {
-                Ok(LoxValue::Number(n1 - n2))
-            }
-            (TokenType::MINUS, LoxValue::String(s1), LoxValue::String(s2)) => {
-                Ok(LoxValue::String(s1.replacen(s2, "", 1)))
-            }
-            (TokenType::SLASH, LoxValue::Number(n1), LoxValue::Number(n2)) => {
-                if *n2 == 0.0 {
-                    return Err(RuntimeError::BadOperator(
-                        operator.clone(),
-                        "Division by zero.".to_string(),
-                    ));
-                }
-                Ok(LoxValue::Number(n1 / n2))
-            }
-            (TokenType::STAR, LoxValue::Number(n1), LoxValue::Number(n2)) => {
-                Ok(LoxValue::Number(n1 * n2))
-            }
-            (TokenType::GREATER, LoxValue::Number(n1), LoxValue::Number(n2)) => {
-                Ok(LoxValue::Boolean(n1 > n2))
-            }
-            (TokenType::GREATER_EQUAL, LoxValue::Number(n1), LoxValue::Number(n2)) => {
-                Ok(LoxValue::Boolean(n1 >= n2))
-            }
-            (TokenType::LESS, LoxValue::Number(n1), LoxValue::Number(n2)) => {
-                Ok(LoxValue::Boolean(n1 < n2))
-            }
-            (TokenType::LESS_EQUAL, LoxValue::Number(n1), LoxValue::Number(n2)) => {
-                Ok(LoxValue::Boolean(n1 <= n2))
-            }
-            (TokenType::BANG_EQUAL, _, _) => {
-                Ok(LoxValue::Boolean(!self.is_equal(&left_lit, &right_lit)))
-            }
-            (TokenType::EQUAL_EQUAL, _, _) => {
-                Ok(LoxValue::Boolean(self.is_equal(&left_lit, &right_lit)))
-            }
-            _ => Err(RuntimeError::BadOperator(
-                operator.clone(),
-                "Invalid binary operation.".to_string(),
-            )),
-        }
+        self.binary(operator, &left_lit, &right_lit)
     }
 
     fn visit_literal(&mut self, value: &LoxValue) -> Result<LoxValue, RuntimeError> {
@@ -129,25 +101,13 @@ impl ExpressionVisitor<LoxValue> for Interpreter {
         }
     }
 
-    fn visit_variable(&mut self, name: &Token, e: &Expr) -> Result<LoxValue, RuntimeError> {
-        Ok(self.look_up_variable(name, e)?)
-        // Ok(self.environment.borrow().get(name)?)
+    fn visit_variable(&mut self, id: usize, name: &Token) -> Result<LoxValue, RuntimeError> {
+        self.look_up_variable(id, name)
     }
 
-    fn visit_assing(&mut self, name: &Token, expr: &Expr) -> Result<LoxValue, RuntimeError> {
+    fn visit_assing(&mut self, id: usize, name: &Token, expr: &Expr) -> Result<LoxValue, RuntimeError> {
         let value = self.evaluate(expr)?;
-        let distance = self.locals.get(expr);
-        match distance {
-            Some(d) => {
-                self.environment.borrow_mut().assing_at(*d, &name.lexeme, value.clone());
-            }
-            None => {
-                self.globals.assign(name, value.clone())?;
-            }
-        }
-
-        // self.environment.borrow_mut().assign(name, value.clone())?;
-        Ok(value)
+        self.assign_variable(id, name, value)
     }
 
     fn visit_logical(
@@ -166,7 +126,7 @@ impl ExpressionVisitor<LoxValue> for Interpreter {
                 return Ok(left_value);
             }
         }
-        Ok(self.evaluate(right)?)
+        self.evaluate(right)
     }
 
     fn visit_call(&mut self, callee: &Expr, paren: &Token, arguments: &[Expr]) -> Result<LoxValue, RuntimeError> {        
@@ -177,74 +137,68 @@ impl ExpressionVisitor<LoxValue> for Interpreter {
         }
         
         if let Some(fun) = callee_val.return_fn_if_callable() {
-            if arguments.len() != fun.arity() {
-                return Err(RuntimeError::ToManyArguments(
+            if !fun.arity().accepts(arguments.len()) {
+                Err(RuntimeError::ArityMismatch(
                     paren.clone(),
+                    fun.name(),
                     fun.arity(),
                     arguments.len()
-                ));
+                ))
+            } else if self.call_stack.len() >= self.max_call_depth {
+                Err(RuntimeError::StackOverflow(paren.span, self.max_call_depth))
             } else {
-                return Ok(fun.call(self, args)?);
+                self.call_stack.push(CallFrame {
+                    function: fun.name(),
+                    call_site: paren.span,
+                });
+                let result = fun.call(self, args).map_err(|e| self.with_traceback(e));
+                self.call_stack.pop();
+                result
             }
         } else {
-            return Err(RuntimeError::BadCallable());
+            Err(RuntimeError::BadCallable(paren.clone()))
         }
     }
 
     fn visit_get(&mut self, name: &Token, object: &Expr) -> Result<LoxValue, RuntimeError> {
         
-        let is_this = if let Expr::This { .. } = object {
-            true
-        } else {
-            false
-        };
+        let is_this = matches!(object, Expr::This { .. });
 
 
         let obj = self.evaluate(object)?;
-        if let LoxValue::LoxInstance(i) = obj {
-            return Ok(i.borrow().get(Rc::clone(&i), name, is_this)?)
-        }
-        if let LoxValue::LoxClass(i) = obj {
-            return Ok(i.find_static(&name.lexeme)?)
-        }
-        Err(RuntimeError::OnlyInstancesHaveProperties())
+        Self::get_property(obj, name, is_this)
     }
 
-
     fn visit_set(&mut self, object: &Expr, name: &Token, value: &Expr) -> Result<LoxValue, RuntimeError> {
         let obj = self.evaluate(object)?;
 
         if let LoxValue::LoxInstance(i) = obj {
             let value = self.evaluate(value)?;
-            i.borrow_mut().set(name.clone(), value.clone());
+            i.borrow_mut().set(name.clone(), value.clone())?;
             return Ok(value);
         }
 
-        Err(RuntimeError::OnlyInstancesHaveProperties())
+        Err(RuntimeError::OnlyInstancesHaveProperties(name.clone()))
             
         
     }
-    fn visit_this(&mut self, keyword: &Token) -> Result<LoxValue, RuntimeError> {
-        self.look_up_variable(keyword, &Expr::Literal { id: 0, value: LoxValue::Nil })
+    fn visit_this(&mut self, id: usize, keyword: &Token) -> Result<LoxValue, RuntimeError> {
+        self.look_up_variable(id, keyword)
     }
-    fn visit_super(&mut self, keyword: &Token, method: &Token) -> Result<LoxValue, RuntimeError> {
-        let expr = Expr::Super {
-            keyword: keyword.clone(),
-            method: method.clone(),
-        };
-    
-        let distance = self.locals.get(&expr).copied();
+    fn visit_super(&mut self, id: usize, keyword: &Token, method: &Token) -> Result<LoxValue, RuntimeError> {
+        let distance = self.locals.get(&id).map(|&(distance, _)| distance);
         
         if let Some(distance) = distance {
             let env = self.environment.borrow();
     
-            let loxvalue = env.get_at(distance, "super")?;
+            // `super` and `this` are the only locals of their scopes
+            let loxvalue = env.get_at(distance, 0).ok_or_else(|| RuntimeError::UnresolvedSuper(keyword.clone()))?;
             let superclass = match loxvalue {
                 LoxValue::LoxClass(ref instance) => instance,
-                _ => return Err(RuntimeError::InvalidSuperclass()),
+                _ => return Err(RuntimeError::InvalidSuperclass(keyword.clone())),
             };
     
-            let loxvalue2 = env.get_at(distance - 1, "this")?;
+            let loxvalue2 = env.get_at(distance - 1, 0).ok_or_else(|| RuntimeError::UnresolvedSuper(keyword.clone()))?;
             let instance = match loxvalue2 {
                 LoxValue::LoxInstance(ref instance) => instance,
                 _ => {todo!()},
@@ -253,37 +207,139 @@ impl ExpressionVisitor<LoxValue> for Interpreter {
             let method_value = superclass.find_method(&method.lexeme);
             let method = match method_value {
                 LoxValue::LoxFunction(ref func) => func,
-                _ => return Err(RuntimeError::UndefinedProperty()),
+                _ => return Err(RuntimeError::UndefinedProperty(method.clone())),
             };
             
             let bound_method = method.clone().bind(Rc::clone(instance))?;
             return Ok(LoxValue::LoxFunction(bound_method.into()));
         }
     
-        Err(RuntimeError::UnresolvedSuper())
+        Err(RuntimeError::UnresolvedSuper(keyword.clone()))
+    }
+
+    fn visit_list(&mut self, _bracket: &Token, elements: &[Expr]) -> Result<LoxValue, RuntimeError> {
+        let mut items = Vec::with_capacity(elements.len());
+        for element in elements {
+            items.push(self.evaluate(element)?);
+        }
+        Ok(self.new_list(items))
+    }
+
+    fn visit_map(&mut self, brace: &Token, entries: &[(Expr, Expr)]) -> Result<LoxValue, RuntimeError> {
+        let mut map = LoxMap::new();
+        for (key, value) in entries {
+            let key = self.evaluate(key)?;
+            let key = Self::map_key(brace, &key)?;
+            map.insert(key, self.evaluate(value)?);
+        }
+        let map = Rc::new(RefCell::new(map));
+        self.heap.track(&map);
+        Ok(LoxValue::Map(map))
+    }
+
+    fn visit_index(&mut self, object: &Expr, bracket: &Token, index: &Expr) -> Result<LoxValue, RuntimeError> {
+        let object = self.evaluate(object)?;
+        let index = self.evaluate(index)?;
+        Self::get_index(bracket, object, &index)
+    }
+
+    fn visit_set_index(&mut self, object: &Expr, bracket: &Token, index: &Expr, value: &Expr) -> Result<LoxValue, RuntimeError> {
+        let object = self.evaluate(object)?;
+        let index = self.evaluate(index)?;
+        let value = self.evaluate(value)?;
+        Self::set_index(bracket, object, &index, value)
+    }
+
+    fn visit_slice(&mut self, object: &Expr, bracket: &Token, start: Option<&Expr>, end: Option<&Expr>) -> Result<LoxValue, RuntimeError> {
+        let object = self.evaluate(object)?;
+        let mut bounds = [None, None];
+        for (bound, expr) in bounds.iter_mut().zip([start, end]) {
+            if let Some(expr) = expr {
+                let value = self.evaluate(expr)?;
+                *bound = Some(Self::index_number(bracket, &value)?);
+            }
+        }
+        let [start, end] = bounds;
+        match object {
+            LoxValue::List(list) => {
+                let items = {
+                    let list = list.borrow();
+                    list[lox_list::slice(bracket, start, end, list.len())?].to_vec()
+                };
+                Ok(self.new_list(items))
+            }
+            LoxValue::String(s) => {
+                let range = lox_list::slice(bracket, start, end, s.chars().count())?;
+                Ok(LoxValue::String(s.chars().skip(range.start).take(range.len()).collect()))
+            }
+            object => Err(RuntimeError::NotSliceable(bracket.clone(), object.type_name())),
+        }
+    }
+
+    fn visit_update(&mut self, target: &Expr, operator: &Token, value: &Expr, postfix: bool) -> Result<LoxValue, RuntimeError> {
+        let (old, new) = match target {
+            Expr::Variable { id, name } => {
+                let old = self.look_up_variable(*id, name)?;
+                let new = self.updated(&old, operator, value)?;
+                self.assign_variable(*id, name, new.clone())?;
+                (old, new)
+            }
+            Expr::Get { object, name } => {
+                let is_this = matches!(**object, Expr::This { .. });
+                let object = self.evaluate(object)?;
+                let old = Self::get_property(object.clone(), name, is_this)?;
+                let new = self.updated(&old, operator, value)?;
+                match object {
+                    LoxValue::LoxInstance(i) => i.borrow_mut().set(name.clone(), new.clone())?,
+                    _ => return Err(RuntimeError::OnlyInstancesHaveProperties(name.clone())),
+                }
+                (old, new)
+            }
+            Expr::Index { object, bracket, index, .. } => {
+                let object = self.evaluate(object)?;
+                let index = self.evaluate(index)?;
+                let old = Self::get_index(bracket, object.clone(), &index)?;
+                let new = self.updated(&old, operator, value)?;
+                Self::set_index(bracket, object, &index, new.clone())?;
+                (old, new)
+            }
+            _ => unreachable!("the parser only builds updates of assignable targets"),
+        };
+        Ok(if postfix { old } else { new })
+    }
+
+    fn visit_lambda(&mut self, keyword: &Token, params: &[Param], body: &[Stmt]) -> Result<LoxValue, RuntimeError> {
+        let declaration = Stmt::anonymous_function(keyword, params, body);
+        let function = LoxFunction::new(declaration, Rc::clone(&self.environment), false, true, false);
+        Ok(LoxValue::Callable(Rc::new(function)))
+    }
+
+    fn visit_interpolated(&mut self, expression: &Expr) -> Result<LoxValue, RuntimeError> {
+        Ok(LoxValue::String(self.evaluate(expression)?.text()))
     }
-    
 }
-impl StatementVisitor<()> for Interpreter {
-    fn visit_expression(&mut self, expression: &Expr) -> Result<(), RuntimeError> {
+impl StatementVisitor<Option<ControlFlow>> for Interpreter {
+    fn visit_expression(&mut self, expression: &Expr) -> Result<Option<ControlFlow>, RuntimeError> {
         let _ = self.evaluate(expression)?;
-        Ok(())
+        Ok(None)
     }
 
-    fn visit_print(&mut self, expression: &Expr) -> Result<(), RuntimeError> {
+    fn visit_print(&mut self, expression: &Expr) -> Result<Option<ControlFlow>, RuntimeError> {
         let value = self.evaluate(expression)?;
         println!("{}", value);
-        Ok(())
+        Ok(None)
     }
 
-    fn visit_var_declaration(&mut self, name: &Token, initializer: &Expr) -> Result<(), RuntimeError> {
+    fn visit_var_declaration(&mut self, name: &Token, initializer: &Expr) -> Result<Option<ControlFlow>, RuntimeError> {
         let value = self.evaluate(initializer)?;
-        self.environment.borrow_mut().define(&name.lexeme, value)?;
-        Ok(())
+        self.define(&name.lexeme, value)?;
+        Ok(None)
     }
 
-    fn visit_block(&mut self, statements: &[Stmt]) -> Result<(), RuntimeError> {
-        self.execute_block(statements, Rc::clone(&self.environment))
+    fn visit_block(&mut self, statements: &[Stmt]) -> Result<Option<ControlFlow>, RuntimeError> {
+        let environment = Rc::new(RefCell::new(Environment::new(Some(Rc::clone(&self.environment)))));
+        self.heap.track(&environment);
+        self.execute_block(statements, environment)
     }
 
     fn visit_if(
@@ -291,14 +347,14 @@ impl StatementVisitor<()> for Interpreter {
         condition: &Expr,
         then_branch: &Stmt,
         else_branch: Option<&Stmt>,
-    ) -> Result<(), RuntimeError> {
+    ) -> Result<Option<ControlFlow>, RuntimeError> {
         let value = self.evaluate(condition)?;
         if self.is_truthy(&value) {
             self.execute(then_branch)
         } else if let Some(t_else_branch) = else_branch {
             self.execute(t_else_branch)
         } else {
-            Ok(())
+            Ok(None)
         }
     }
 
@@ -307,64 +363,59 @@ impl StatementVisitor<()> for Interpreter {
         condition: &Expr,
         body: &Stmt,
         else_branch: Option<&Stmt>,
-    ) -> Result<(), RuntimeError> {
+        increment: Option<&Expr>,
+        label: Option<&Token>,
+    ) -> Result<Option<ControlFlow>, RuntimeError> {
         loop {
             let value = self.evaluate(condition)?;
             let truthy = self.is_truthy(&value);
-            if truthy {
-                match self.execute(&body) {
-                    Ok(_) => {}
-                    Err(err) => match err {
-                        RuntimeError::Break() => {
-                            break;
-                        }
-                        _ => {
-                            return Err(err);
-                        }
-                    },
-                }
+            let branch = if truthy {
+                body
             } else if let Some(t_else_branch) = else_branch {
-                match self.execute(&t_else_branch) {
-                    Ok(_) => {}
-                    Err(err) => match err {
-                        RuntimeError::Break() => {
-                            break;
-                        }
-                        _ => {
-                            return Err(err);
-                        }
-                    },
-                }
+                t_else_branch
             } else {
                 break;
+            };
+
+            match self.execute(branch)? {
+                Some(signal) if !signal.targets(label) => return Ok(Some(signal)),
+                Some(ControlFlow::Break { .. }) => break,
+                _ => {}
+            }
+            if let Some(increment) = increment.filter(|_| truthy) {
+                self.evaluate(increment)?;
             }
         }
 
-        Ok(())
+        Ok(None)
     }
 
-    fn visit_loop(&mut self, body: &Stmt) -> Result<(), RuntimeError> {
+    fn visit_loop(&mut self, body: &Stmt, label: Option<&Token>) -> Result<Option<ControlFlow>, RuntimeError> {
         loop {
-            match self.execute(body) {
-                Ok(_) => {}
-                Err(err) => match err {
-                    RuntimeError::Break() => {
-                        break;
-                    }
-                    _ => {
-                        return Err(err);
-                    }
-                },
+            match self.execute(body)? {
+                Some(signal) if !signal.targets(label) => return Ok(Some(signal)),
+                Some(ControlFlow::Break { .. }) => break,
+                _ => {}
             }
         }
-        Ok(())
+        Ok(None)
     }
 
-    fn visit_break(&mut self) -> Result<(), RuntimeError> {
-        Err(RuntimeError::Break())
+    fn visit_break(&mut self, keyword: &Token, label: Option<&Token>) -> Result<Option<ControlFlow>, RuntimeError> {
+        Ok(Some(ControlFlow::Break {
+            keyword: keyword.clone(),
+            label: label.map(|label| label.lexeme.clone()),
+        }))
     }
 
-    fn visit_function(&mut self, token: &Token, params: &[Token], body: &[Stmt], public: bool, is_static: bool) -> Result<(), RuntimeError> {
+    fn visit_continue(&mut self, keyword: &Token, label: Option<&Token>) -> Result<Option<ControlFlow>, RuntimeError> {
+        Ok(Some(ControlFlow::Continue {
+            keyword: keyword.clone(),
+            label: label.map(|label| label.lexeme.clone()),
+        }))
+    }
+
+    fn visit_function(&mut self, token: &Token, params: &[Param], body: &[Stmt], public: bool, is_static: bool) -> Result<Option<ControlFlow>, RuntimeError> {
         let function = LoxFunction::new(
             Stmt::Function {
                 token: token.clone(),
@@ -379,13 +430,13 @@ impl StatementVisitor<()> for Interpreter {
             is_static
         );
 
-        self.environment.borrow_mut().define(&token.lexeme, LoxValue::Callable(Rc::new(function)))?;
-        Ok(())
+        self.define(&token.lexeme, LoxValue::Callable(Rc::new(function)))?;
+        Ok(None)
     }
 
-    fn visit_return(&mut self, _: &Token, v: &Expr) -> Result<(), RuntimeError> {
+    fn visit_return(&mut self, _: &Token, v: &Expr) -> Result<Option<ControlFlow>, RuntimeError> {
         let val = self.evaluate(v)?;
-        Err(RuntimeError::Return(val))
+        Ok(Some(ControlFlow::Return(val)))
     }
 
     fn visit_class(
@@ -393,26 +444,30 @@ impl StatementVisitor<()> for Interpreter {
         name: &Token,
         methods: &[Stmt],
         super_class: &Option<Expr>,
-    ) -> Result<(), RuntimeError> {
+    ) -> Result<Option<ControlFlow>, RuntimeError> {
         let super_klass = if let Some(expr) = super_class {
             let evaluated = self.evaluate(expr)?;
+            let super_name = match expr {
+                Expr::Variable { name, .. } => name.clone(),
+                _ => name.clone(),
+            };
             match evaluated {
+                LoxValue::LoxClass(ref c) if c.native.is_some() => {
+                    return Err(RuntimeError::NativeSuperclass(super_name));
+                }
                 LoxValue::LoxClass(ref c) => Some(Box::new(c.clone())),
-                _ => return Err(RuntimeError::SuperClassMustBeSuperAClass()),
+                _ => return Err(RuntimeError::SuperClassMustBeSuperAClass(super_name)),
             }
         } else {
             None
         };
     
-        self.environment
-            .borrow_mut()
-            .define(&name.lexeme, LoxValue::Nil)?;
-    
         if let Some(ref super_class) = super_klass {
             let parent = Rc::clone(&self.environment);
             let mut new_env = Environment::new(Some(parent));
-            new_env.define("super", LoxValue::LoxClass(*super_class.clone()))?;
+            new_env.push(LoxValue::LoxClass(*super_class.clone()));
             self.environment = Rc::new(RefCell::new(new_env));
+            self.heap.track(&self.environment);
         }
     
         let mut methods_map = FxHashMap::default();
@@ -431,7 +486,8 @@ impl StatementVisitor<()> for Interpreter {
                     is_initializer,
                     *public,
                     *is_static,
-                );
+                )
+                .in_class(&name.lexeme);
                 methods_map.insert(token.lexeme.clone(), function);
             }
         }
@@ -448,69 +504,324 @@ impl StatementVisitor<()> for Interpreter {
                 self.environment = enclosing;
             }
         }
-        
-        self.environment
-            .borrow_mut()
-            .assign(name, LoxValue::LoxClass(klass))?;
+
+        // Methods capture this scope, so they still see the class by name
+        self.define(&name.lexeme, LoxValue::LoxClass(klass))?;
     
-        Ok(())
+        Ok(None)
     }
     
 }
 
 impl Interpreter {
-    pub fn new(mut global_env: Box<Environment>) -> Self {
+    pub fn new(mut global_env: Environment) -> Self {
 
         let _ = global_env.define("clock", LoxValue::Callable(Rc::new(LoxClock::new())));
         let _ = global_env.define("print", LoxValue::Callable(Rc::new(LoxPrint::new())));
         let _ = global_env.define("println", LoxValue::Callable(Rc::new(LoxPrintLn::new())));
         let _ = global_env.define("dbg", LoxValue::Callable(Rc::new(LoxDbg::new())));
+        let _ = global_env.define("gc", LoxValue::Callable(Rc::new(LoxGc::new())));
+        let _ = global_env.define("List", LoxValue::Callable(Rc::new(LoxListNew::new())));
+        let _ = global_env.define("max", LoxValue::Callable(Rc::new(LoxMax::new())));
+        let _ = global_env.define("min", LoxValue::Callable(Rc::new(LoxMin::new())));
+        let _ = global_env.define("to_string", LoxValue::Callable(Rc::new(LoxToString::new())));
         //let _ = global_env.define("true", LoxValue::Boolean(true));
         //let _ = global_env.define("false", LoxValue::Boolean(false));
 
+        let globals = Rc::new(RefCell::new(global_env));
+
         Self {
-            globals: global_env.clone(),
-            environment: Rc::new(RefCell::new(*global_env)),
+            globals: Rc::clone(&globals),
+            environment: globals,
             locals: FxHashMap::default(),
+            call_stack: vec![],
+            max_call_depth: DEFAULT_CALL_DEPTH,
+            heap: Heap::new(),
         }
     }
 
-    pub fn execute_block(&mut self, statements: &[Stmt], environment: Rc<RefCell<Environment>>) -> Result<(), RuntimeError> {    
+    /// How many calls deep scripts may go before failing with a stack
+    /// overflow error.
+    pub fn set_max_call_depth(&mut self, depth: usize) {
+        self.max_call_depth = depth;
+    }
+
+    /// Runs `statements` in `environment`, stopping at the first error or
+    /// control-flow signal. The previous environment is restored either way.
+    pub fn execute_block(&mut self, statements: &[Stmt], environment: Rc<RefCell<Environment>>) -> Result<Option<ControlFlow>, RuntimeError> {
         let previous = std::mem::replace(&mut self.environment, environment);
-    
+
+        let mut result = Ok(None);
         for statement in statements {
-            self.execute(statement)?;
+            result = self.execute(statement);
+            if !matches!(result, Ok(None)) {
+                break;
+            }
         }
-    
+
         self.environment = previous;
-        Ok(())
+        result
+    }
+
+    /// Evaluates `expr` with `environment` as the current scope, like
+    /// `execute_block` does for statements.
+    pub fn evaluate_in(&mut self, expr: &Expr, environment: Rc<RefCell<Environment>>) -> Result<LoxValue, RuntimeError> {
+        let previous = std::mem::replace(&mut self.environment, environment);
+        let result = self.evaluate(expr);
+        self.environment = previous;
+        result
     }
     
+    /// Globals are stored by name and may be redeclared (so REPL lines can
+    /// redefine names). Anywhere else the value takes the next slot of the
+    /// current frame, matching the order the resolver numbered them in.
+    fn define(&mut self, name: &str, value: LoxValue) -> Result<(), RuntimeError> {
+        if Rc::ptr_eq(&self.environment, &self.globals) {
+            self.globals.borrow_mut().redefine(name, value);
+        } else {
+            self.environment.borrow_mut().push(value);
+        }
+        Ok(())
+    }
+
     fn evaluate(&mut self, expr: &Expr) -> Result<LoxValue, RuntimeError> {
         expr.accept(self)
     }
 
-    pub fn interpret(&mut self, statements: Vec<Stmt>) -> Result<(), RuntimeError> {
+    /// Runs `statements` and returns the value of the last one when it is an
+    /// expression statement, `nil` otherwise. Errors come back wrapped in
+    /// `RuntimeError::Traceback`.
+    ///
+    /// The resolver rejects `break`, `continue` and `return` outside a loop
+    /// or function; should one reach the top level anyway, `break` and
+    /// `continue` are errors and `return` ends the script with its value.
+    pub fn interpret(&mut self, statements: Vec<Stmt>) -> Result<LoxValue, RuntimeError> {
+        let mut last = LoxValue::Nil;
 
         for statement in statements {
-            self.execute(&statement)?;
+            let result = match &statement {
+                Stmt::Expression { expression } => self.evaluate(expression),
+                _ => match self.execute(&statement) {
+                    Ok(None) => Ok(LoxValue::Nil),
+                    Ok(Some(ControlFlow::Return(value))) => return Ok(value),
+                    Ok(Some(ControlFlow::Break { keyword, .. } | ControlFlow::Continue { keyword, .. })) => {
+                        Err(RuntimeError::OutsideLoop(keyword))
+                    }
+                    Err(e) => Err(e),
+                },
+            };
+            last = result.map_err(|e| self.with_traceback(e))?;
         }
 
-        Ok(())
+        Ok(last)
     }
 
-    fn execute(&mut self, stmt: &Stmt) -> Result<(), RuntimeError> {
-        stmt.accept(self)?;
-        Ok(())
+    /// Calls `callee` on behalf of the native `caller`, as `List.map` does
+    /// with its callback. The call shows up in tracebacks like any other.
+    pub fn call_value(&mut self, caller: &str, callee: &LoxValue, arguments: Vec<LoxValue>) -> Result<LoxValue, RuntimeError> {
+        let Some(fun) = callee.return_fn_if_callable() else {
+            return Err(RuntimeError::ArgumentTypeMismatch(caller.to_string(), 0, "function", callee.type_name()));
+        };
+        if !fun.arity().accepts(arguments.len()) {
+            return Err(RuntimeError::BadArguments(format!(
+                "'{}' passes {} to its callback, but '{}' expects {}.",
+                caller,
+                Arity::Exact(arguments.len()),
+                fun.name(),
+                fun.arity()
+            )));
+        }
+        let call_site = self.call_stack.last().map(|frame| frame.call_site).unwrap_or_default();
+        if self.call_stack.len() >= self.max_call_depth {
+            return Err(RuntimeError::StackOverflow(call_site, self.max_call_depth));
+        }
+        self.call_stack.push(CallFrame {
+            function: fun.name(),
+            call_site,
+        });
+        let result = fun.call(self, arguments).map_err(|e| self.with_traceback(e));
+        self.call_stack.pop();
+        result
+    }
+
+
+    // Numeric operators, on operands already known to be numbers
+    // Shared by binary expressions and updates like `x += 1`
+    fn binary(&self, operator: &Token, left: &LoxValue, right: &LoxValue) -> Result<LoxValue, RuntimeError> {
+        match (operator.t_type.clone(), left, right) {
+
+            (TokenType::PLUS, LoxValue::String(s1), LoxValue::String(s2)) => {
+                Ok(LoxValue::String(format!("{}{}", s1, s2)))
+            }
+            (TokenType::PLUS, LoxValue::String(s1), LoxValue::Number(_) | LoxValue::Integer(_)) => {
+                Ok(LoxValue::String(format!("{}{}", s1, right.text())))
+            }
+            (TokenType::MINUS, LoxValue::String(s1), LoxValue::String(s2)) => {
+                Ok(LoxValue::String(s1.replacen(s2, "", 1)))
+            }
+            (TokenType::BANG_EQUAL, _, _) => {
+                Ok(LoxValue::Boolean(!self.is_equal(left, right)))
+            }
+            (TokenType::EQUAL_EQUAL, _, _) => {
+                Ok(LoxValue::Boolean(self.is_equal(left, right)))
+            }
+            _ => match (Number::from_lox(left), Number::from_lox(right)) {
+                (Some(n1), Some(n2)) => Self::arithmetic(operator, n1, n2),
+                _ => Err(RuntimeError::BadOperator(
+                    operator.clone(),
+                    "Invalid binary operation.".to_string(),
+                )),
+            },
+        }
+    }
+
+
+    fn arithmetic(operator: &Token, n1: Number, n2: Number) -> Result<LoxValue, RuntimeError> {
+        let ordering = || n1.compare(n2);
+        let result = match operator.t_type {
+            TokenType::PLUS => n1.checked_add(n2),
+            TokenType::MINUS => n1.checked_sub(n2),
+            TokenType::STAR => n1.checked_mul(n2),
+            TokenType::SLASH => n1.checked_div(n2),
+            TokenType::TILDE_SLASH => n1.checked_floor_div(n2),
+            TokenType::PERCENT => n1.checked_rem(n2),
+            TokenType::STAR_STAR => n1.checked_pow(n2),
+            TokenType::AMPERSAND => n1.bit_and(n2),
+            TokenType::PIPE => n1.bit_or(n2),
+            TokenType::CARET => n1.bit_xor(n2),
+            TokenType::LESS_LESS => n1.checked_shl(n2),
+            TokenType::GREATER_GREATER => n1.checked_shr(n2),
+            TokenType::GREATER => return Ok(LoxValue::Boolean(ordering().is_some_and(Ordering::is_gt))),
+            TokenType::GREATER_EQUAL => return Ok(LoxValue::Boolean(ordering().is_some_and(Ordering::is_ge))),
+            TokenType::LESS => return Ok(LoxValue::Boolean(ordering().is_some_and(Ordering::is_lt))),
+            TokenType::LESS_EQUAL => return Ok(LoxValue::Boolean(ordering().is_some_and(Ordering::is_le))),
+            _ => {
+                return Err(RuntimeError::BadOperator(
+                    operator.clone(),
+                    "Invalid binary operation.".to_string(),
+                ))
+            }
+        };
+        result.map(Number::to_lox).map_err(|error| error.at(operator))
+    }
+
+    fn assign_variable(&mut self, id: usize, name: &Token, value: LoxValue) -> Result<LoxValue, RuntimeError> {
+        match self.locals.get(&id) {
+            Some(&(distance, slot)) => {
+                if !self.environment.borrow_mut().assing_at(distance, slot, value.clone()) {
+                    return Err(RuntimeError::UndefinedVariable(name.clone()));
+                }
+            }
+            None => {
+                self.globals.borrow_mut().assign(name, value.clone())?;
+            }
+        }
+
+        Ok(value)
+    }
+
+    // `object.name`; `is_this` when written as `this.name`, which may
+    // reach private members
+    fn get_property(obj: LoxValue, name: &Token, is_this: bool) -> Result<LoxValue, RuntimeError> {
+        if let LoxValue::LoxInstance(i) = obj {
+            return i.borrow().get(Rc::clone(&i), name, is_this)
+        }
+        if let LoxValue::LoxClass(i) = obj {
+            return i.find_static(&name.lexeme)
+        }
+        if let LoxValue::List(list) = obj {
+            return match LoxListMethod::new(list, &name.lexeme) {
+                Some(method) => Ok(LoxValue::Callable(Rc::new(method))),
+                None => Err(RuntimeError::UndefinedProperty(name.clone())),
+            };
+        }
+        if let LoxValue::Map(map) = obj {
+            return match LoxMapMethod::new(map, &name.lexeme) {
+                Some(method) => Ok(LoxValue::Callable(Rc::new(method))),
+                None => Err(RuntimeError::UndefinedProperty(name.clone())),
+            };
+        }
+        Err(RuntimeError::OnlyInstancesHaveProperties(name.clone()))
+    }
+
+    fn get_index(bracket: &Token, object: LoxValue, index: &LoxValue) -> Result<LoxValue, RuntimeError> {
+        match object {
+            LoxValue::List(list) => {
+                let list = list.borrow();
+                let position = lox_list::position(bracket, Self::index_number(bracket, index)?, list.len())?;
+                Ok(list[position].clone())
+            }
+            LoxValue::String(s) => {
+                let len = s.chars().count();
+                let position = lox_list::position(bracket, Self::index_number(bracket, index)?, len)?;
+                Ok(LoxValue::String(s.chars().nth(position).expect("position is in range").to_string()))
+            }
+            LoxValue::Map(map) => {
+                let key = Self::map_key(bracket, index)?;
+                let value = map.borrow().get(&key).cloned();
+                value.ok_or_else(|| RuntimeError::KeyNotFound(bracket.clone(), key.to_string()))
+            }
+            object => Err(RuntimeError::NotIndexable(bracket.clone(), object.type_name())),
+        }
+    }
+
+    fn set_index(bracket: &Token, object: LoxValue, index: &LoxValue, value: LoxValue) -> Result<LoxValue, RuntimeError> {
+        match object {
+            LoxValue::List(list) => {
+                let mut list = list.borrow_mut();
+                let position = lox_list::position(bracket, Self::index_number(bracket, index)?, list.len())?;
+                list[position] = value.clone();
+                Ok(value)
+            }
+            LoxValue::Map(map) => {
+                map.borrow_mut().insert(Self::map_key(bracket, index)?, value.clone());
+                Ok(value)
+            }
+            LoxValue::String(_) => Err(RuntimeError::ImmutableIndex(bracket.clone(), object.type_name())),
+            object => Err(RuntimeError::NotIndexable(bracket.clone(), object.type_name())),
+        }
+    }
+
+    // `old` combined with the value of an update by its operator
+    fn updated(&mut self, old: &LoxValue, operator: &Token, value: &Expr) -> Result<LoxValue, RuntimeError> {
+        let value = self.evaluate(value)?;
+        self.binary(operator, old, &value)
+    }
+
+    // Indices and slice bounds must be numbers
+    fn index_number(bracket: &Token, index: &LoxValue) -> Result<f64, RuntimeError> {
+        match Number::from_lox(index) {
+            Some(n) => Ok(n.as_f64()),
+            None => Err(RuntimeError::InvalidIndex(bracket.clone(), index.type_name().to_string())),
+        }
+    }
+
+    fn map_key(token: &Token, key: &LoxValue) -> Result<MapKey, RuntimeError> {
+        MapKey::from_lox(key).ok_or_else(|| RuntimeError::UnhashableKey(token.clone(), key.type_name()))
+    }
+
+    // Snapshots the call stack into `error` the first time it unwinds
+    // through a call
+    fn with_traceback(&self, error: RuntimeError) -> RuntimeError {
+        if matches!(error, RuntimeError::Traceback(..)) {
+            return error;
+        }
+        RuntimeError::Traceback(Box::new(error), self.call_stack.clone())
+    }
+
+    fn execute(&mut self, stmt: &Stmt) -> Result<Option<ControlFlow>, RuntimeError> {
+        stmt.accept(self)
     }
 
     fn is_equal(&self, left: &LoxValue, right: &LoxValue) -> bool {
         match (left, right) {
             (LoxValue::Nil, LoxValue::Nil) => true,
             (LoxValue::Nil, _) | (_, LoxValue::Nil) => false,
-            (LoxValue::Number(n1), LoxValue::Number(n2)) => n1 == n2,
             (LoxValue::String(s1), LoxValue::String(s2)) => s1 == s2,
             (LoxValue::Boolean(b1), LoxValue::Boolean(b2)) => b1 == b2,
+            (LoxValue::Number(_) | LoxValue::Integer(_), LoxValue::Number(_) | LoxValue::Integer(_))
+            | (LoxValue::List(_), LoxValue::List(_))
+            | (LoxValue::Map(_), LoxValue::Map(_)) => left == right,
             _ => false,
         }
     }
@@ -529,22 +840,34 @@ impl Interpreter {
     }*/
 
     pub fn is_truthy(&self, value: &LoxValue) -> bool {
-        match value {
-            LoxValue::Boolean(b) => *b,
-            LoxValue::Nil => false,
-            _ => true,
-        }
+        Element::is_truthy(value)
     }
 
-    pub fn resolve(&mut self, expr: Expr, depth: usize) {
-        self.locals.insert(expr, depth);
+    /// Records that expression `id` reads the local `depth` frames up at `slot`.
+    pub fn resolve(&mut self, id: usize, depth: usize, slot: usize) {
+        self.locals.insert(id, (depth, slot));
     }
 
-    pub fn look_up_variable(&mut self, name: &Token, expr: &Expr) -> Result<LoxValue, RuntimeError> {
-        if let Some(opt) = self.locals.get(expr) {
-            return Ok(self.environment.borrow().get_at(*opt, &name.lexeme)?);
-        } else {
-            return Ok(self.globals.get(name)?);
+    pub fn look_up_variable(&mut self, id: usize, name: &Token) -> Result<LoxValue, RuntimeError> {
+        match self.locals.get(&id) {
+            Some(&(distance, slot)) => self
+                .environment
+                .borrow()
+                .get_at(distance, slot)
+                .ok_or_else(|| RuntimeError::UndefinedVariable(name.clone())),
+            None => self.globals.borrow().get(name),
         }
     }
 }
+
+impl Host<LoxValue> for Interpreter {
+    fn call(&mut self, caller: &str, callee: &LoxValue, arguments: Vec<LoxValue>) -> Result<LoxValue, RuntimeError> {
+        self.call_value(caller, callee, arguments)
+    }
+
+    fn new_list(&mut self, items: Vec<LoxValue>) -> LoxValue {
+        let list = Rc::new(RefCell::new(items));
+        self.heap.track(&list);
+        LoxValue::List(list)
+    }
+}
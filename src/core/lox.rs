@@ -1,35 +1,61 @@
-use std::cell::RefCell;
 use std::io::Write;
 use std::path::PathBuf;
 use std::rc::Rc;
 use std::{fs, io, process};
 
-use crate::core::interpreter::Interpreter;
 use crate::utils::colors::Color;
 
-use super::environment::Environment;
+use super::diagnostics::source_map::SourceMap;
+use super::error_types::lox_error::LoxError;
+use super::error_types::scanner_error::ScannerError;
+use super::session::{Backend, LoxSession};
 use super::syntax::analysing::parser::Parser;
 use super::syntax::analysing::scanner::Scanner;
-use super::syntax::resolver::Resolver;
-use super::syntax::token::Token;
-
-
-// Macros
-macro_rules! read_line {
-    ($input:ident) => {
-        let mut $input = String::new();
-        io::stdin()
-            .read_line(&mut $input)
-            .expect("Error on read line");
-    };
-}
+use super::syntax::components::expression::LoxValue;
+use super::syntax::token_type::TokenType;
+
+const USAGE: &str = "Usage: rox [--quiet] [--vm] <command> [...]
+
+Commands:
+  run <file> [args...]  run a script, exposing args through argc()/argv(i)
+  check <file>          scan, parse and resolve without running
+  tokens <file>         print the scanned tokens
+  ast <file>            print the parsed syntax tree
+  repl                  start the interactive prompt (default)
+
+Options:
+  -q, --quiet           hide the [LOX] and RESULTADO banners
+      --vm              run on the bytecode VM instead of the tree-walker
+  -h, --help            show this message";
+
+// Exit codes, following sysexits.h
+const EX_USAGE: i32 = 64;
+const EX_DATAERR: i32 = 65;
+const EX_NOINPUT: i32 = 66;
+const EX_SOFTWARE: i32 = 70;
+
+/// Stack for the thread `rox` runs scripts on. The tree-walker recurses
+/// on the Rust stack, tens of kilobytes per Lox call in debug builds, and
+/// needs room to reach `CALL_DEPTH` and report it instead of crashing.
+pub const STACK_SIZE: usize = 256 * 1024 * 1024;
+
+/// How many calls deep scripts run by `rox` can go.
+pub const CALL_DEPTH: usize = 1024;
 
 // Lox
-pub struct Lox {}
+pub struct Lox {
+    session: LoxSession,
+    quiet: bool,
+}
 
 impl Lox {
     pub fn new() -> Self {
-        Self {}
+        let mut session = LoxSession::new();
+        session.set_max_call_depth(CALL_DEPTH);
+        Self {
+            session,
+            quiet: false,
+        }
     }
 
     pub fn print_error(msg: &str) {
@@ -45,108 +71,338 @@ impl Lox {
         Color::cprintln(&format!("[LOX]: {}", msg), Color::Green);
     }
 
+    // Banners and warnings, hidden by --quiet
+    fn message(&self, msg: &str) {
+        if !self.quiet {
+            Self::print_message(msg);
+        }
+    }
+
+    fn warn(&self, msg: &str) {
+        if !self.quiet {
+            Self::print_warn(msg);
+        }
+    }
+
     pub fn init(&mut self, args: Vec<String>) {
-        match args.len() {
-            0 => {
-                Self::print_error("No arguments provided.");
-                process::exit(64);
+        let code = self.dispatch(args);
+        if code != 0 {
+            process::exit(code);
+        }
+    }
+
+    fn dispatch(&mut self, args: Vec<String>) -> i32 {
+        let mut command: Option<String> = None;
+        let mut file: Option<String> = None;
+        let mut script_args = vec![];
+
+        for arg in args.into_iter().skip(1) {
+            if file.is_some() {
+                script_args.push(arg);
+                continue;
             }
-            1 => {
-                Self::print_message("Running prompt");
-                self.run_prompt();
+            match arg.as_str() {
+                "-q" | "--quiet" => self.quiet = true,
+                "--vm" => self.session.set_backend(Backend::Vm),
+                "-h" | "--help" => {
+                    println!("{}", USAGE);
+                    return 0;
+                }
+                "-v" => {} // Old `-v <file>` form
+                "run" | "check" | "tokens" | "ast" | "repl" if command.is_none() => command = Some(arg),
+                flag if flag.starts_with('-') => return Self::usage_error(&format!("Unknown option '{}'.", flag)),
+                _ => file = Some(arg),
             }
-            2 => {
-                Self::print_message("Running file");
+        }
+
+        match (command.as_deref(), file) {
+            (None | Some("repl"), None) => {
+                self.message("Running prompt");
+                self.run_prompt();
+                0
             }
-            3 => match args.get(1).map(String::as_str) {
-                Some("-v") => {
-                    Self::print_message("Running file");
-                    self.run_file(&args[2]);
-                }
-                _ => {
-                    Self::print_error("Invalid argument. Expected '-v' for file execution.");
-                    process::exit(64);
+            (Some("repl"), Some(_)) => Self::usage_error("'repl' takes no arguments."),
+            (Some(command), None) => Self::usage_error(&format!("'{}' expects a file.", command)),
+            (command, Some(path)) => {
+                let source = match fs::read_to_string(PathBuf::from(&path)) {
+                    Ok(source) => source,
+                    Err(e) => {
+                        Self::print_error(&format!("Can't read '{}': {}", path, e));
+                        return EX_NOINPUT;
+                    }
+                };
+
+                match command {
+                    Some("check") => self.check(&path, &source),
+                    Some("tokens") => Self::tokens(&path, &source),
+                    Some("ast") => Self::ast(&path, &source),
+                    _ => {
+                        self.message("Running file");
+                        self.session.set_args(script_args);
+                        self.run(&path, &source)
+                    }
                 }
-            },
-            _ => {
-                Self::print_error("Too many arguments.");
-                process::exit(64);
             }
         }
     }
 
-    fn run_file(&mut self, path: &String) {
-        let file = PathBuf::from(path);
-        let content = fs::read_to_string(file);
-        if let Ok(ok_content) = content {
-            self.run(ok_content)
+    fn usage_error(msg: &str) -> i32 {
+        Self::print_error(msg);
+        eprintln!("{}", USAGE);
+        EX_USAGE
+    }
+
+    fn exit_code(error: &LoxError) -> i32 {
+        if error.is_compile_error() {
+            EX_DATAERR
+        } else {
+            EX_SOFTWARE
+        }
+    }
+
+    fn check(&mut self, path: &str, source: &str) -> i32 {
+        match self.session.check_named(path, source) {
+            Ok(_) => {
+                self.message("No errors found");
+                0
+            }
+            Err(e) => {
+                Self::report(self.session.sources(), &e);
+                Self::exit_code(&e)
+            }
+        }
+    }
+
+    fn tokens(path: &str, source: &str) -> i32 {
+        let mut sources = SourceMap::new();
+        let mut scanner = Scanner::for_file(source.to_string(), sources.add(path, source));
+        for token in scanner.scan_tokens() {
+            println!("{}", token);
+        }
+        if scanner.errors().is_empty() {
+            0
         } else {
-            Self::print_error("Can't read your file.");
+            Self::report(&sources, &LoxError::Scanner(scanner.errors().to_vec()));
+            EX_DATAERR
         }
     }
 
+    fn ast(path: &str, source: &str) -> i32 {
+        let mut sources = SourceMap::new();
+        let mut scanner = Scanner::for_file(source.to_string(), sources.add(path, source));
+        let tokens = scanner.scan_tokens();
+        if !scanner.errors().is_empty() {
+            Self::report(&sources, &LoxError::Scanner(scanner.errors().to_vec()));
+            return EX_DATAERR;
+        }
+
+        let (statements, errors) = Parser::new(tokens).parse();
+        if !errors.is_empty() {
+            Self::report(&sources, &LoxError::Parse(errors));
+            return EX_DATAERR;
+        }
+        for statement in statements {
+            println!("{}", statement);
+        }
+        0
+    }
+
     fn run_prompt(&mut self) {
         // Clear stdout
         println!("Clear terminal");
 
-        // let _ = io::stdout().flush();
-        std::io::stdout().flush().unwrap();
+        let mut buffer = String::new();
 
-        // Prompt loop
+        // Prompt loop, ends on EOF (Ctrl-D)
         loop {
-            print!(" -> ");
+            print!("{}", if buffer.is_empty() { " -> " } else { " .. " });
+            io::stdout().flush().unwrap();
 
-            let _input: String = String::new();
+            let mut line = String::new();
+            match io::stdin().read_line(&mut line) {
+                Ok(0) => break,
+                Ok(_) => {}
+                Err(e) => {
+                    Self::print_error(&format!("Error on read line: {}", e));
+                    break;
+                }
+            }
 
-            read_line!(_input);
+            if buffer.is_empty() && line.trim_start().starts_with(':') {
+                if !self.run_command(line.trim()) {
+                    break;
+                }
+                continue;
+            }
 
-            if _input.trim().is_empty() {
-                break;
+            buffer.push_str(&line);
+
+            if buffer.trim().is_empty() {
+                buffer.clear();
+                continue;
             }
 
-            Self::print_message(&format!("Ejecutando: {}", _input.trim()));
+            if Self::is_incomplete(&buffer) {
+                continue;
+            }
 
-            // Ejecutar línea
-            self.run(_input);
+            let source = std::mem::take(&mut buffer);
+            match self.session.eval_named("<repl>", &source) {
+                Ok(LoxValue::Nil) => {}
+                Ok(value) => println!("{}", value),
+                Err(e) => Self::report(self.session.sources(), &e),
+            }
         }
     }
 
-    fn run(&mut self, source: String) {
-        let mut scanner: Scanner = Scanner::new(source.clone());
-        let tokens: Vec<Token> = scanner.scan_tokens();
+    /// Handles a `:command` typed at the prompt. Returns false when the
+    /// session should end.
+    fn run_command(&mut self, line: &str) -> bool {
+        let (command, argument) = match line.split_once(char::is_whitespace) {
+            Some((command, argument)) => (command, argument.trim()),
+            None => (line, ""),
+        };
 
-        
-        let mut parser = Parser::new(tokens.clone());
-        let interpreter = Rc::new(RefCell::new(Interpreter::new(Box::new(Environment::new(None)))));
-        
-        let mut resolver = Resolver::new(Rc::clone(&interpreter));
+        match command {
+            ":quit" | ":q" => return false,
+            ":help" | ":h" => {
+                println!(":env            show the current environment chain and globals");
+                println!(":heap           show heap statistics");
+                println!(":ast <expr>     print the parsed expression tree");
+                println!(":tokens <src>   print the tokens scanned from <src>");
+                println!(":load <file>    run a file in this session");
+                println!(":reset          discard every definition");
+                println!(":quit           leave the prompt");
+            }
+            ":env" => self.print_environment(),
+            ":heap" => println!("{}", self.session.heap_stats()),
+            ":ast" => {
+                let mut sources = SourceMap::new();
+                let mut scanner = Scanner::for_file(argument.to_string(), sources.add("<repl>", argument));
+                let tokens = scanner.scan_tokens();
+                if !scanner.errors().is_empty() {
+                    Self::report(&sources, &LoxError::Scanner(scanner.errors().to_vec()));
+                    return true;
+                }
+                match Parser::new(tokens).parse_expression() {
+                    Ok(expr) => println!("{}", expr),
+                    Err(e) => Self::report(&sources, &e.into()),
+                }
+            }
+            ":tokens" => {
+                let mut sources = SourceMap::new();
+                let mut scanner = Scanner::for_file(argument.to_string(), sources.add("<repl>", argument));
+                for token in scanner.scan_tokens() {
+                    println!("{}", token);
+                }
+                if !scanner.errors().is_empty() {
+                    Self::report(&sources, &LoxError::Scanner(scanner.errors().to_vec()));
+                }
+            }
+            ":load" => match fs::read_to_string(argument) {
+                Ok(source) => {
+                    if let Err(e) = self.session.eval_named(argument, &source) {
+                        Self::report(self.session.sources(), &e);
+                    }
+                }
+                Err(e) => Self::print_error(&format!("Can't read '{}': {}", argument, e)),
+            },
+            ":reset" => {
+                let backend = self.session.backend();
+                self.session = LoxSession::new();
+                self.session.set_backend(backend);
+                self.message("Session reset");
+            }
+            _ => Self::print_error(&format!("Unknown command '{}', try :help", command)),
+        }
+        true
+    }
 
+    fn print_environment(&self) {
+        if self.session.backend() == Backend::Vm {
+            // The REPL only stops between scripts, when the VM has no locals
+            Color::cprintln("-- globals --", Color::Cyan);
+            for (name, value) in self.session.vm().globals() {
+                println!("{} = {}", name, value);
+            }
+            return;
+        }
 
-        match parser.parse() {
-            Ok(statements) => {
-                Color::cprintln("========== RESULTADO ==========\n", Color::Yellow);      
+        let interpreter = self.session.interpreter();
+        let interpreter = interpreter.borrow();
 
-                if let Err(e) = resolver.resolve_statements(&statements) {
-                    Self::print_error(&format!("On resolving: {}", e));
-                    return;
-                }
+        let mut depth = 0;
+        let mut env = Some(Rc::clone(&interpreter.environment));
+        while let Some(current) = env {
+            if Rc::ptr_eq(&current, &interpreter.globals) {
+                break;
+            }
+            Color::cprintln(&format!("-- scope {} --", depth), Color::Cyan);
+            print!("{}", current.borrow());
+            env = current.borrow().enclosing.clone();
+            depth += 1;
+        }
 
-                Lox::print_warn(&format!("Unused variables {:?}", resolver.get_unused_variables()));
+        Color::cprintln("-- globals --", Color::Cyan);
+        print!("{}", interpreter.globals.borrow());
+    }
 
-                match interpreter.borrow_mut().interpret(statements) {
-                    Ok(_) => {
-                        Self::print_message("End");
-                    }
-                    Err(e) => {
-                        Self::print_error(&format!("on interpretation: {}", e));
-                    }
-                }
+    /// True while `source` has unclosed braces, parentheses, strings or
+    /// block comments, so the prompt should keep reading lines.
+    fn is_incomplete(source: &str) -> bool {
+        let mut scanner = Scanner::new(source.to_string());
+        let tokens = scanner.scan_tokens();
+
+        let unterminated = scanner.errors().iter().any(|e| {
+            matches!(
+                e,
+                ScannerError::UnterminatedString(_)
+                    | ScannerError::UnterminatedRawString(_)
+                    | ScannerError::UnfinishedMultilineComment(_)
+            )
+        });
+
+        let mut depth: i64 = 0;
+        for token in &tokens {
+            match token.t_type {
+                TokenType::LEFT_BRACE | TokenType::LEFT_PAREN | TokenType::LEFT_BRACKET => depth += 1,
+                TokenType::RIGHT_BRACE | TokenType::RIGHT_PAREN | TokenType::RIGHT_BRACKET => depth -= 1,
+                _ => {}
+            }
+        }
+
+        unterminated || depth > 0
+    }
+
+    fn run(&mut self, path: &str, source: &str) -> i32 {
+        if !self.quiet {
+            Color::cprintln("========== RESULTADO ==========\n", Color::Yellow);
+        }
+
+        match self.session.eval_named(path, source) {
+            Ok(_) => {
+                self.warn(&format!("Unused variables {:?}", self.session.unused_variables()));
+                self.message("End");
+                0
             }
             Err(e) => {
-                Self::print_error(&format!("on parsing {:?}", e));
+                Self::report(self.session.sources(), &e);
+                Self::exit_code(&e)
             }
         }
-    
+    }
+
+    /// Renders every diagnostic in `error` to stderr, quoting `sources`.
+    fn report(sources: &SourceMap, error: &LoxError) {
+        let color = Color::stderr_enabled();
+        for diagnostic in error.diagnostics() {
+            eprintln!("{}", diagnostic.render(sources, color));
+        }
+    }
+}
 
+impl Default for Lox {
+    fn default() -> Self {
+        Self::new()
     }
 }
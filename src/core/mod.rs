@@ -2,7 +2,14 @@ pub mod syntax;
 pub mod error_types;
 pub mod native_functions;
 pub mod environment;
+pub mod convert;
+pub mod number;
+pub mod gc;
+pub mod control_flow;
 pub mod lox;
 pub mod interpreter;
 pub mod fuctions;
-pub mod oop;
\ No newline at end of file
+pub mod oop;
+pub mod session;
+pub mod diagnostics;
+pub mod vm;
@@ -0,0 +1,131 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::core::{
+    error_types::runtime_error::RuntimeError, native_functions::lox_map::{LoxMap, MapKey}, number::Number, syntax::components::expression::LoxValue
+};
+
+/// What lists and maps need from the values of a backend, so the
+/// tree-walker and the VM run the same list and map code.
+pub trait Element: Clone {
+    fn nil() -> Self;
+    fn boolean(b: bool) -> Self;
+    fn integer(n: i64) -> Self;
+    fn string(s: String) -> Self;
+    fn list(list: Rc<RefCell<Vec<Self>>>) -> Self;
+    fn from_key(key: &MapKey) -> Self;
+    /// `None` for values that aren't numbers.
+    fn number(&self) -> Option<Number>;
+    /// `None` for values that aren't strings.
+    fn as_str(&self) -> Option<&str>;
+    /// `None` for values that can't be map keys.
+    fn to_key(&self) -> Option<MapKey>;
+    fn type_name(&self) -> &'static str;
+    fn text(&self) -> String;
+    fn is_truthy(&self) -> bool;
+    fn equals(&self, other: &Self) -> bool;
+}
+
+/// What list and map methods need from the backend running them.
+pub trait Host<V> {
+    /// Runs `callee` to completion on behalf of the method `caller`.
+    fn call(&mut self, caller: &str, callee: &V, arguments: Vec<V>) -> Result<V, RuntimeError>;
+    /// A new list the cycle collector knows about.
+    fn new_list(&mut self, items: Vec<V>) -> V;
+}
+
+thread_local! {
+    // Pairs of lists or maps being compared right now, to cut cycles short
+    static COMPARING: RefCell<Vec<(usize, usize)>> = const { RefCell::new(vec![]) };
+}
+
+// What `compare` says about the containers at `a` and `b`. A pair already
+// being compared further up is a cycle, which adds no difference of its
+// own: it counts as equal and the rest of the comparison decides.
+fn compare_guarded(a: usize, b: usize, compare: impl FnOnce() -> bool) -> bool {
+    if a == b || COMPARING.with(|comparing| comparing.borrow().contains(&(a, b))) {
+        return true;
+    }
+    COMPARING.with(|comparing| comparing.borrow_mut().push((a, b)));
+    let equal = compare();
+    COMPARING.with(|comparing| comparing.borrow_mut().pop());
+    equal
+}
+
+/// Lists are equal when their items are.
+pub fn lists_equal<V: Element>(l1: &Rc<RefCell<Vec<V>>>, l2: &Rc<RefCell<Vec<V>>>) -> bool {
+    compare_guarded(Rc::as_ptr(l1) as usize, Rc::as_ptr(l2) as usize, || {
+        let (l1, l2) = (l1.borrow(), l2.borrow());
+        l1.len() == l2.len() && l1.iter().zip(l2.iter()).all(|(a, b)| a.equals(b))
+    })
+}
+
+/// Maps are equal when they hold the same entries, in any order.
+pub fn maps_equal<V: Element>(m1: &Rc<RefCell<LoxMap<V>>>, m2: &Rc<RefCell<LoxMap<V>>>) -> bool {
+    compare_guarded(Rc::as_ptr(m1) as usize, Rc::as_ptr(m2) as usize, || {
+        m1.borrow().same_entries(&m2.borrow(), V::equals)
+    })
+}
+
+impl Element for LoxValue {
+    fn nil() -> Self {
+        LoxValue::Nil
+    }
+
+    fn boolean(b: bool) -> Self {
+        LoxValue::Boolean(b)
+    }
+
+    fn integer(n: i64) -> Self {
+        LoxValue::Integer(n)
+    }
+
+    fn string(s: String) -> Self {
+        LoxValue::String(s)
+    }
+
+    fn list(list: Rc<RefCell<Vec<Self>>>) -> Self {
+        LoxValue::List(list)
+    }
+
+    fn from_key(key: &MapKey) -> Self {
+        key.to_lox()
+    }
+
+    fn number(&self) -> Option<Number> {
+        Number::from_lox(self)
+    }
+
+    fn as_str(&self) -> Option<&str> {
+        match self {
+            LoxValue::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    fn to_key(&self) -> Option<MapKey> {
+        MapKey::from_lox(self)
+    }
+
+    fn type_name(&self) -> &'static str {
+        LoxValue::type_name(self)
+    }
+
+    fn text(&self) -> String {
+        LoxValue::text(self)
+    }
+
+    fn is_truthy(&self) -> bool {
+        match self {
+            LoxValue::Boolean(b) => *b,
+            LoxValue::Nil => false,
+            LoxValue::List(list) => !list.borrow().is_empty(),
+            LoxValue::Map(map) => !map.borrow().is_empty(),
+            _ => true,
+        }
+    }
+
+    fn equals(&self, other: &Self) -> bool {
+        self == other
+    }
+}
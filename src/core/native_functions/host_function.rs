@@ -0,0 +1,116 @@
+use std::rc::Rc;
+
+use crate::core::{
+    convert::{FromLox, IntoLox}, error_types::runtime_error::RuntimeError, fuctions::lox_callable::{Arity, LoxCallable}, interpreter::Interpreter, syntax::components::expression::LoxValue
+};
+
+/// What a host function may return: any `IntoLox` value, or a `Result`
+/// whose error becomes a runtime error in the script.
+pub trait HostResult {
+    fn into_result(self) -> Result<LoxValue, RuntimeError>;
+}
+
+impl<T: IntoLox> HostResult for T {
+    fn into_result(self) -> Result<LoxValue, RuntimeError> {
+        Ok(self.into_lox())
+    }
+}
+
+impl<T: IntoLox, E: ToString> HostResult for Result<T, E> {
+    fn into_result(self) -> Result<LoxValue, RuntimeError> {
+        self.map(IntoLox::into_lox)
+            .map_err(|error| RuntimeError::NativeFunctionError(error.to_string()))
+    }
+}
+
+/// A Rust closure callable from Lox. Implemented for `Fn`s of up to six
+/// `FromLox` parameters returning a `HostResult`; `Args` is the tuple of
+/// parameter types and only tells the implementations apart.
+pub trait HostFunction<Args>: 'static {
+    fn arity(&self) -> usize;
+
+    /// Converts `arguments` and calls the closure. `name` is used in
+    /// type-mismatch errors.
+    fn invoke(&self, name: &str, arguments: Vec<LoxValue>) -> Result<LoxValue, RuntimeError>;
+}
+
+pub(crate) fn argument<T: FromLox>(name: &str, index: usize, value: LoxValue) -> Result<T, RuntimeError> {
+    let found = value.type_name();
+    T::from_lox(value).ok_or_else(|| RuntimeError::ArgumentTypeMismatch(name.to_string(), index, T::EXPECTED, found))
+}
+
+// Binds each `$arg` to the next of `$arguments`, converted to the type of
+// the same name
+macro_rules! convert_arguments {
+    ($name:expr, $arguments:expr, $arity:expr; $($arg:ident),*) => {
+        let mut arguments = $arguments.into_iter().enumerate();
+        $(
+            let (index, value) = arguments.next().ok_or_else(|| {
+                RuntimeError::BadArguments(format!("'{}' expects {} arguments.", $name, $arity))
+            })?;
+            let $arg = $crate::core::native_functions::host_function::argument::<$arg>($name, index, value)?;
+        )*
+    };
+}
+
+pub(crate) use convert_arguments;
+
+macro_rules! host_function {
+    ($($arg:ident),*) => {
+        impl<F, R, $($arg: FromLox),*> HostFunction<($($arg,)*)> for F
+        where
+            F: Fn($($arg),*) -> R + 'static,
+            R: HostResult,
+        {
+            fn arity(&self) -> usize {
+                <[&str]>::len(&[$(stringify!($arg)),*])
+            }
+
+            #[allow(non_snake_case, unused_variables, unused_mut)]
+            fn invoke(&self, name: &str, arguments: Vec<LoxValue>) -> Result<LoxValue, RuntimeError> {
+                convert_arguments!(name, arguments, self.arity(); $($arg),*);
+                self($($arg),*).into_result()
+            }
+        }
+    };
+}
+
+host_function!();
+host_function!(A);
+host_function!(A, B);
+host_function!(A, B, C);
+host_function!(A, B, C, D);
+host_function!(A, B, C, D, E);
+host_function!(A, B, C, D, E, G);
+
+/// A native registered with `LoxSession::register_fn`.
+pub struct LoxHostFn {
+    name: String,
+    arity: usize,
+    function: Box<dyn Fn(Vec<LoxValue>) -> Result<LoxValue, RuntimeError>>,
+}
+
+impl LoxHostFn {
+    pub fn new<Args, F: HostFunction<Args>>(name: &str, function: Rc<F>) -> Self {
+        let owned_name = name.to_string();
+        Self {
+            name: name.to_string(),
+            arity: function.arity(),
+            function: Box::new(move |arguments| function.invoke(&owned_name, arguments)),
+        }
+    }
+}
+
+impl LoxCallable for LoxHostFn {
+    fn arity(&self) -> Arity {
+        Arity::Exact(self.arity)
+    }
+
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn call(&self, _interpreter: &mut Interpreter, arguments: Vec<LoxValue>) -> Result<LoxValue, RuntimeError> {
+        (self.function)(arguments)
+    }
+}
@@ -0,0 +1,69 @@
+use std::rc::Rc;
+
+use crate::core::{
+    error_types::runtime_error::RuntimeError, fuctions::lox_callable::{Arity, LoxCallable}, interpreter::Interpreter, syntax::components::expression::LoxValue
+};
+
+pub struct LoxArgc {
+    args: Rc<Vec<String>>,
+}
+
+impl LoxArgc {
+    pub fn new(args: Rc<Vec<String>>) -> Self {
+        Self { args }
+    }
+}
+
+impl LoxCallable for LoxArgc {
+    fn arity(&self) -> Arity {
+        Arity::Exact(0)
+    }
+
+    fn name(&self) -> String {
+        "argc".to_string()
+    }
+
+    fn call(
+        &self,
+        _interpreter: &mut Interpreter,
+        _arguments: Vec<LoxValue>,
+    ) -> Result<LoxValue, RuntimeError> {
+        Ok(LoxValue::Integer(self.args.len() as i64))
+    }
+}
+
+pub struct LoxArgv {
+    args: Rc<Vec<String>>,
+}
+
+impl LoxArgv {
+    pub fn new(args: Rc<Vec<String>>) -> Self {
+        Self { args }
+    }
+}
+
+impl LoxCallable for LoxArgv {
+    fn arity(&self) -> Arity {
+        Arity::Exact(1)
+    }
+
+    fn name(&self) -> String {
+        "argv".to_string()
+    }
+
+    fn call(
+        &self,
+        _interpreter: &mut Interpreter,
+        arguments: Vec<LoxValue>,
+    ) -> Result<LoxValue, RuntimeError> {
+        match &arguments[0] {
+            LoxValue::Integer(n) if *n >= 0 => Ok(self
+                .args
+                .get(*n as usize)
+                .map_or(LoxValue::Nil, |arg| LoxValue::String(arg.clone()))),
+            _ => Err(RuntimeError::NativeFunctionError(
+                "argv expects a non-negative integer index".to_string(),
+            )),
+        }
+    }
+}
@@ -1,10 +1,16 @@
 use crate::core::{
-    error_types::runtime_error::RuntimeError, fuctions::lox_callable::LoxCallable, interpreter::Interpreter, syntax::components::expression::LoxValue
+    error_types::runtime_error::RuntimeError, fuctions::lox_callable::{Arity, LoxCallable}, interpreter::Interpreter, syntax::components::expression::LoxValue
 };
 use std::time::SystemTime;
 
 pub struct LoxClock {}
 
+impl Default for LoxClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl LoxClock {
     pub fn new() -> Self {
         Self {}
@@ -12,9 +18,14 @@ impl LoxClock {
 }
 
 impl LoxCallable for LoxClock {
-    fn arity(&self) -> usize {
-        0
+    fn arity(&self) -> Arity {
+        Arity::Exact(0)
     }
+
+    fn name(&self) -> String {
+        "clock".to_string()
+    }
+
     fn call(
         &self,
         _interpreter: &mut Interpreter,
@@ -0,0 +1,37 @@
+use crate::core::{
+    error_types::runtime_error::RuntimeError, fuctions::lox_callable::{Arity, LoxCallable}, interpreter::Interpreter, syntax::components::expression::LoxValue
+};
+
+/// `gc()`: runs the cycle collector now and returns how many objects it
+/// freed.
+pub struct LoxGc {}
+
+impl Default for LoxGc {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LoxGc {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+impl LoxCallable for LoxGc {
+    fn arity(&self) -> Arity {
+        Arity::Exact(0)
+    }
+
+    fn name(&self) -> String {
+        "gc".to_string()
+    }
+
+    fn call(
+        &self,
+        interpreter: &mut Interpreter,
+        _arguments: Vec<LoxValue>,
+    ) -> Result<LoxValue, RuntimeError> {
+        Ok(LoxValue::Integer(interpreter.heap.collect() as i64))
+    }
+}
@@ -0,0 +1,329 @@
+use std::cell::RefCell;
+use std::cmp::Ordering;
+use std::fmt;
+use std::ops::Range;
+use std::rc::Rc;
+
+use crate::core::{
+    error_types::runtime_error::RuntimeError, fuctions::lox_callable::{Arity, LoxCallable}, gc::Tracer, interpreter::Interpreter, native_functions::collection::{Element, Host}, number::Number, syntax::{components::expression::LoxValue, token::Token}
+};
+
+/// The methods every list has, with what they accept.
+pub const METHODS: &[(&str, Arity)] = &[
+    ("push", Arity::AtLeast(1)),
+    ("pop", Arity::Exact(0)),
+    ("len", Arity::Exact(0)),
+    ("insert", Arity::Exact(2)),
+    ("remove", Arity::Exact(1)),
+    ("map", Arity::Exact(1)),
+    ("filter", Arity::Exact(1)),
+    ("reduce", Arity::Range(1, 2)),
+    ("sort", Arity::Range(0, 1)),
+    ("contains", Arity::Exact(1)),
+    ("join", Arity::Range(0, 1)),
+];
+
+/// The name and arity of the list method `name`, if there is one.
+pub fn method(name: &str) -> Option<(&'static str, Arity)> {
+    METHODS.iter().find(|(method, _)| *method == name).copied()
+}
+
+/// `List(a, b, ...)`: a new list of its arguments, the same as `[a, b, ...]`.
+/// Lists are a built-in type rather than instances, so `List` is a native
+/// function and not a class.
+pub struct LoxListNew {}
+
+impl Default for LoxListNew {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LoxListNew {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+impl LoxCallable for LoxListNew {
+    fn arity(&self) -> Arity {
+        Arity::AtLeast(0)
+    }
+
+    fn name(&self) -> String {
+        "List".to_string()
+    }
+
+    fn call(&self, interpreter: &mut Interpreter, arguments: Vec<LoxValue>) -> Result<LoxValue, RuntimeError> {
+        Ok(interpreter.new_list(arguments))
+    }
+}
+
+/// What `join` puts between items when no separator is given.
+pub const DEFAULT_SEPARATOR: &str = ", ";
+
+// Indices must be whole numbers
+fn integer(token: &Token, index: f64) -> Result<i64, RuntimeError> {
+    if index.fract() != 0.0 || !index.is_finite() {
+        return Err(RuntimeError::InvalidIndex(token.clone(), index.to_string()));
+    }
+    Ok(index as i64)
+}
+
+/// Where `index` points in a sequence of `len` items. Negative indices
+/// count from the end, so `-1` is the last item.
+pub fn position(token: &Token, index: f64, len: usize) -> Result<usize, RuntimeError> {
+    let index = integer(token, index)?;
+    let position = if index < 0 { index + len as i64 } else { index };
+    if position < 0 || position >= len as i64 {
+        return Err(RuntimeError::IndexOutOfRange(token.clone(), index, len));
+    }
+    Ok(position as usize)
+}
+
+/// The items `[start:end]` selects out of `len`. Missing bounds mean the
+/// start and the end, negative ones count from the end, and bounds past
+/// either end are clamped, so a slice never fails for being too wide.
+pub fn slice(token: &Token, start: Option<f64>, end: Option<f64>, len: usize) -> Result<Range<usize>, RuntimeError> {
+    let clamp = |bound: Option<f64>, default: usize| -> Result<usize, RuntimeError> {
+        let Some(bound) = bound else {
+            return Ok(default);
+        };
+        let bound = integer(token, bound)?;
+        let bound = if bound < 0 { bound + len as i64 } else { bound };
+        Ok(bound.clamp(0, len as i64) as usize)
+    };
+    let start = clamp(start, 0)?;
+    let end = clamp(end, len)?;
+    Ok(start..end.max(start))
+}
+
+// Errors of list methods, which have no token of their own to point at
+fn method_error(name: &str, message: String) -> RuntimeError {
+    RuntimeError::NativeFunctionError(format!("'List.{}' {}", name, message))
+}
+
+// Where `insert` (which also takes the position after the last item) and
+// `remove` find their index
+fn method_position(name: &str, index: f64, len: usize, past_end: bool) -> Result<usize, RuntimeError> {
+    if index.fract() != 0.0 || !index.is_finite() {
+        return Err(method_error(name, format!("needs a whole number index, found {}", index)));
+    }
+    let index = index as i64;
+    let position = if index < 0 { index + len as i64 } else { index };
+    let last = if past_end { len as i64 } else { len as i64 - 1 };
+    if position < 0 || position > last {
+        return Err(method_error(name, format!("index {} is out of range for a list of length {}", index, len)));
+    }
+    Ok(position as usize)
+}
+
+fn insert_position(index: f64, len: usize) -> Result<usize, RuntimeError> {
+    method_position("insert", index, len, true)
+}
+
+fn remove_position(index: f64, len: usize) -> Result<usize, RuntimeError> {
+    method_position("remove", index, len, false)
+}
+
+fn empty_pop() -> RuntimeError {
+    method_error("pop", "was called on an empty list".to_string())
+}
+
+fn empty_reduce() -> RuntimeError {
+    method_error("reduce", "was called on an empty list without an initial value".to_string())
+}
+
+// What `sort` orders without a comparator
+fn sortable<V: Element>(value: &V) -> bool {
+    value.number().is_some() || value.as_str().is_some()
+}
+
+fn unsortable(found: &str) -> RuntimeError {
+    method_error("sort", format!("can only order numbers or strings without a comparator, found {}", found))
+}
+
+/// Stable merge sort with a comparator that may fail. `std`'s sorts may
+/// panic on comparators that aren't a total order, and Lox callbacks are
+/// free to be inconsistent.
+pub fn sort_by<T: Clone, E>(items: Vec<T>, less: &mut impl FnMut(&T, &T) -> Result<bool, E>) -> Result<Vec<T>, E> {
+    if items.len() <= 1 {
+        return Ok(items);
+    }
+    let mut left = items;
+    let right = left.split_off(left.len() / 2);
+    let left = sort_by(left, less)?;
+    let right = sort_by(right, less)?;
+
+    let mut merged = Vec::with_capacity(left.len() + right.len());
+    let (mut left, mut right) = (left.into_iter().peekable(), right.into_iter().peekable());
+    while let (Some(a), Some(b)) = (left.peek(), right.peek()) {
+        // Ties keep the left item first
+        if less(b, a)? {
+            merged.extend(right.next());
+        } else {
+            merged.extend(left.next());
+        }
+    }
+    merged.extend(left);
+    merged.extend(right);
+    Ok(merged)
+}
+
+thread_local! {
+    // Lists and maps being printed right now, to cut cycles short
+    static PRINTING: RefCell<Vec<usize>> = const { RefCell::new(vec![]) };
+}
+
+/// Writes what `text` gives for the container at `address`, or `cut` when
+/// that container is already being printed further up.
+pub fn fmt_guarded(f: &mut fmt::Formatter, address: usize, cut: &str, text: impl FnOnce() -> String) -> fmt::Result {
+    if PRINTING.with(|printing| printing.borrow().contains(&address)) {
+        return f.write_str(cut);
+    }
+    PRINTING.with(|printing| printing.borrow_mut().push(address));
+    let text = text();
+    PRINTING.with(|printing| printing.borrow_mut().pop());
+    f.write_str(&text)
+}
+
+/// Writes `List[a, b]`, or `List[...]` for a list already being printed
+/// further up, which only happens when it contains itself.
+pub fn fmt_items<T: fmt::Display>(f: &mut fmt::Formatter, list: &RefCell<Vec<T>>) -> fmt::Result {
+    fmt_guarded(f, list as *const _ as usize, "List[...]", || {
+        let items: Vec<String> = list.borrow().iter().map(|item| item.to_string()).collect();
+        format!("List[{}]", items.join(", "))
+    })
+}
+
+/// A list method read off a list, like `xs.push`.
+pub struct LoxListMethod {
+    list: Rc<RefCell<Vec<LoxValue>>>,
+    name: &'static str,
+    arity: Arity,
+}
+
+impl LoxListMethod {
+    /// `None` when lists have no method called `name`.
+    pub fn new(list: Rc<RefCell<Vec<LoxValue>>>, name: &str) -> Option<Self> {
+        let (name, arity) = method(name)?;
+        Some(Self { list, name, arity })
+    }
+}
+
+impl LoxCallable for LoxListMethod {
+    fn arity(&self) -> Arity {
+        self.arity
+    }
+
+    fn name(&self) -> String {
+        format!("List.{}", self.name)
+    }
+
+    fn call(&self, interpreter: &mut Interpreter, arguments: Vec<LoxValue>) -> Result<LoxValue, RuntimeError> {
+        call_method(interpreter, &self.list, self.name, arguments)
+    }
+
+    fn trace(&self, tracer: &mut Tracer) {
+        tracer.edge(&self.list);
+    }
+}
+
+/// Runs the list method `name`, one of `METHODS`, with arguments it
+/// accepts. Both backends call methods through here.
+pub fn call_method<V: Element>(
+    host: &mut impl Host<V>,
+    list: &Rc<RefCell<Vec<V>>>,
+    name: &'static str,
+    arguments: Vec<V>,
+) -> Result<V, RuntimeError> {
+    let caller = format!("List.{}", name);
+    let number = |index: usize, value: &V| match value.number() {
+        Some(n) => Ok(n.as_f64()),
+        None => Err(RuntimeError::ArgumentTypeMismatch(caller.clone(), index, "number", value.type_name())),
+    };
+    // A copy, so callbacks are free to change the list
+    let items = || list.borrow().clone();
+    match name {
+        "push" => {
+            list.borrow_mut().extend(arguments);
+            Ok(V::nil())
+        }
+        "pop" => list.borrow_mut().pop().ok_or_else(empty_pop),
+        "len" => Ok(V::integer(list.borrow().len() as i64)),
+        "insert" => {
+            let index = number(0, &arguments[0])?;
+            let mut list = list.borrow_mut();
+            let position = insert_position(index, list.len())?;
+            list.insert(position, arguments[1].clone());
+            Ok(V::nil())
+        }
+        "remove" => {
+            let index = number(0, &arguments[0])?;
+            let mut list = list.borrow_mut();
+            let position = remove_position(index, list.len())?;
+            Ok(list.remove(position))
+        }
+        "map" => {
+            let mut mapped = vec![];
+            for item in items() {
+                mapped.push(host.call(&caller, &arguments[0], vec![item])?);
+            }
+            Ok(host.new_list(mapped))
+        }
+        "filter" => {
+            let mut kept = vec![];
+            for item in items() {
+                if host.call(&caller, &arguments[0], vec![item.clone()])?.is_truthy() {
+                    kept.push(item);
+                }
+            }
+            Ok(host.new_list(kept))
+        }
+        "reduce" => {
+            let mut items = items().into_iter();
+            let mut accumulator = match arguments.get(1) {
+                Some(initial) => initial.clone(),
+                None => items.next().ok_or_else(empty_reduce)?,
+            };
+            for item in items {
+                accumulator = host.call(&caller, &arguments[0], vec![accumulator, item])?;
+            }
+            Ok(accumulator)
+        }
+        "sort" => {
+            let sorted = match arguments.first() {
+                Some(comparator) => sort_by(items(), &mut |a, b| {
+                    let order = host.call(&caller, comparator, vec![a.clone(), b.clone()])?;
+                    match order.number() {
+                        Some(n) => Ok(n.compare(Number::Int(0)) == Some(Ordering::Less)),
+                        None => Ok(order.is_truthy()),
+                    }
+                })?,
+                None => sort_by(items(), &mut |a, b| match (a.as_str(), b.as_str()) {
+                    (Some(a), Some(b)) => Ok(a < b),
+                    _ => match (a.number(), b.number()) {
+                        (Some(a), Some(b)) => Ok(a.compare(b) == Some(Ordering::Less)),
+                        _ if sortable(a) => Err(unsortable(b.type_name())),
+                        _ => Err(unsortable(a.type_name())),
+                    },
+                })?,
+            };
+            *list.borrow_mut() = sorted;
+            Ok(V::list(Rc::clone(list)))
+        }
+        "contains" => Ok(V::boolean(list.borrow().iter().any(|item| item.equals(&arguments[0])))),
+        "join" => {
+            let separator = match arguments.first() {
+                Some(separator) => match separator.as_str() {
+                    Some(separator) => separator.to_string(),
+                    None => return Err(RuntimeError::ArgumentTypeMismatch(caller, 0, "string", separator.type_name())),
+                },
+                None => DEFAULT_SEPARATOR.to_string(),
+            };
+            let items: Vec<String> = list.borrow().iter().map(V::text).collect();
+            Ok(V::string(items.join(&separator)))
+        }
+        _ => unreachable!("list methods come from METHODS"),
+    }
+}
@@ -0,0 +1,248 @@
+use std::cell::RefCell;
+use std::fmt;
+use std::rc::Rc;
+
+use rustc_hash::FxHashMap;
+
+use crate::core::{
+    error_types::runtime_error::RuntimeError, fuctions::lox_callable::{Arity, LoxCallable}, gc::{Trace, Tracer}, interpreter::Interpreter, native_functions::{collection::{Element, Host}, lox_list}, syntax::components::expression::LoxValue
+};
+
+/// The methods every map has, with what they accept.
+pub const METHODS: &[(&str, Arity)] = &[
+    ("len", Arity::Exact(0)),
+    ("has", Arity::Exact(1)),
+    ("get", Arity::Range(1, 2)),
+    ("remove", Arity::Exact(1)),
+    ("keys", Arity::Exact(0)),
+    ("values", Arity::Exact(0)),
+    ("entries", Arity::Exact(0)),
+];
+
+/// The name and arity of the map method `name`, if there is one.
+pub fn method(name: &str) -> Option<(&'static str, Arity)> {
+    METHODS.iter().find(|(method, _)| *method == name).copied()
+}
+
+/// A value that can key a map. Only values compared by what they hold can
+/// be keys: objects either compare by identity or can change under the map.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum MapKey {
+    Nil,
+    Boolean(bool),
+    Integer(i64),
+    // The bits of a number that isn't a whole `i64`, since `1.0` must find
+    // the entry of `1`. `-0` is folded into `0`, which is whole anyway
+    Number(u64),
+    String(Rc<str>),
+}
+
+impl MapKey {
+    pub fn number(n: f64) -> Self {
+        // `i64::MAX as f64` rounds up past the range, so that bound is open
+        if n.fract() == 0.0 && n >= i64::MIN as f64 && n < i64::MAX as f64 {
+            return MapKey::Integer(n as i64);
+        }
+        MapKey::Number(n.to_bits())
+    }
+
+    /// `None` for values that can't be keys.
+    pub fn from_lox(value: &LoxValue) -> Option<Self> {
+        match value {
+            LoxValue::Nil => Some(MapKey::Nil),
+            LoxValue::Boolean(b) => Some(MapKey::Boolean(*b)),
+            LoxValue::Integer(n) => Some(MapKey::Integer(*n)),
+            LoxValue::Number(n) => Some(MapKey::number(*n)),
+            LoxValue::String(s) => Some(MapKey::String(s.as_str().into())),
+            _ => None,
+        }
+    }
+
+    pub fn to_lox(&self) -> LoxValue {
+        match self {
+            MapKey::Nil => LoxValue::Nil,
+            MapKey::Boolean(b) => LoxValue::Boolean(*b),
+            MapKey::Integer(n) => LoxValue::Integer(*n),
+            MapKey::Number(bits) => LoxValue::Number(f64::from_bits(*bits)),
+            MapKey::String(s) => LoxValue::String(s.to_string()),
+        }
+    }
+}
+
+impl fmt::Display for MapKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.to_lox())
+    }
+}
+
+/// Entries of a map, kept in the order their keys were first inserted so
+/// printing and `keys()` don't depend on hashing.
+#[derive(Clone, Debug)]
+pub struct LoxMap<V> {
+    entries: Vec<(MapKey, V)>,
+    positions: FxHashMap<MapKey, usize>,
+}
+
+impl<V> Default for LoxMap<V> {
+    fn default() -> Self {
+        Self {
+            entries: vec![],
+            positions: FxHashMap::default(),
+        }
+    }
+}
+
+impl<V> LoxMap<V> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn contains_key(&self, key: &MapKey) -> bool {
+        self.positions.contains_key(key)
+    }
+
+    pub fn get(&self, key: &MapKey) -> Option<&V> {
+        self.positions.get(key).map(|&position| &self.entries[position].1)
+    }
+
+    /// Replaces the value of a key already there, keeping its place.
+    pub fn insert(&mut self, key: MapKey, value: V) {
+        match self.positions.get(&key) {
+            Some(&position) => self.entries[position].1 = value,
+            None => {
+                self.positions.insert(key.clone(), self.entries.len());
+                self.entries.push((key, value));
+            }
+        }
+    }
+
+    pub fn remove(&mut self, key: &MapKey) -> Option<V> {
+        let position = self.positions.remove(key)?;
+        let (_, value) = self.entries.remove(position);
+        for (key, _) in &self.entries[position..] {
+            *self.positions.get_mut(key).expect("every entry has a position") -= 1;
+        }
+        Some(value)
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.positions.clear();
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&MapKey, &V)> {
+        self.entries.iter().map(|(key, value)| (key, value))
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = &MapKey> {
+        self.entries.iter().map(|(key, _)| key)
+    }
+
+    pub fn values(&self) -> impl Iterator<Item = &V> {
+        self.entries.iter().map(|(_, value)| value)
+    }
+
+    /// Whether both maps hold the same keys with values `equal` says are
+    /// the same, in any order.
+    pub fn same_entries(&self, other: &Self, equal: impl Fn(&V, &V) -> bool) -> bool {
+        self.len() == other.len()
+            && self.iter().all(|(key, value)| other.get(key).is_some_and(|other| equal(value, other)))
+    }
+}
+
+impl Trace for RefCell<LoxMap<LoxValue>> {
+    fn trace(&self, tracer: &mut Tracer) {
+        let Ok(map) = self.try_borrow() else {
+            return tracer.opaque();
+        };
+        for value in map.values() {
+            value.trace(tracer);
+        }
+    }
+
+    fn clear(&self) {
+        if let Ok(mut map) = self.try_borrow_mut() {
+            map.clear();
+        }
+    }
+}
+
+/// Writes `Map{key: value}`, or `Map{...}` for a map already being printed
+/// further up, which only happens when it contains itself.
+pub fn fmt_entries<V: fmt::Display>(f: &mut fmt::Formatter, map: &RefCell<LoxMap<V>>) -> fmt::Result {
+    lox_list::fmt_guarded(f, map as *const _ as usize, "Map{...}", || {
+        let entries: Vec<String> = map.borrow().iter().map(|(key, value)| format!("{}: {}", key, value)).collect();
+        format!("Map{{{}}}", entries.join(", "))
+    })
+}
+
+/// A map method read off a map, like `m.keys`.
+pub struct LoxMapMethod {
+    map: Rc<RefCell<LoxMap<LoxValue>>>,
+    name: &'static str,
+    arity: Arity,
+}
+
+impl LoxMapMethod {
+    /// `None` when maps have no method called `name`.
+    pub fn new(map: Rc<RefCell<LoxMap<LoxValue>>>, name: &str) -> Option<Self> {
+        let (name, arity) = method(name)?;
+        Some(Self { map, name, arity })
+    }
+}
+
+impl LoxCallable for LoxMapMethod {
+    fn arity(&self) -> Arity {
+        self.arity
+    }
+
+    fn name(&self) -> String {
+        format!("Map.{}", self.name)
+    }
+
+    fn call(&self, interpreter: &mut Interpreter, arguments: Vec<LoxValue>) -> Result<LoxValue, RuntimeError> {
+        Ok(call_method(interpreter, &self.map, self.name, arguments))
+    }
+
+    fn trace(&self, tracer: &mut Tracer) {
+        tracer.edge(&self.map);
+    }
+}
+
+/// Runs the map method `name`, one of `METHODS`, with arguments it
+/// accepts. Both backends call methods through here.
+pub fn call_method<V: Element>(host: &mut impl Host<V>, map: &RefCell<LoxMap<V>>, name: &'static str, arguments: Vec<V>) -> V {
+    // A key that can't be hashed can't be in the map either
+    let key = arguments.first().and_then(V::to_key);
+    match name {
+        "len" => V::integer(map.borrow().len() as i64),
+        "has" => V::boolean(key.is_some_and(|key| map.borrow().contains_key(&key))),
+        "get" => {
+            let found = key.and_then(|key| map.borrow().get(&key).cloned());
+            found.or_else(|| arguments.get(1).cloned()).unwrap_or_else(V::nil)
+        }
+        "remove" => key.and_then(|key| map.borrow_mut().remove(&key)).unwrap_or_else(V::nil),
+        "keys" => {
+            let keys = map.borrow().keys().map(V::from_key).collect();
+            host.new_list(keys)
+        }
+        "values" => {
+            let values = map.borrow().values().cloned().collect();
+            host.new_list(values)
+        }
+        "entries" => {
+            let pairs: Vec<Vec<V>> = map.borrow().iter().map(|(key, value)| vec![V::from_key(key), value.clone()]).collect();
+            let entries = pairs.into_iter().map(|pair| host.new_list(pair)).collect();
+            host.new_list(entries)
+        }
+        _ => unreachable!("map methods come from METHODS"),
+    }
+}
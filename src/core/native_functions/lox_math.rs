@@ -0,0 +1,86 @@
+use std::cmp::Ordering;
+
+use crate::core::{
+    error_types::runtime_error::RuntimeError, fuctions::lox_callable::{Arity, LoxCallable}, interpreter::Interpreter, number::Number, syntax::components::expression::LoxValue
+};
+
+/// The first of the numeric `arguments` that compares `wanted` to all the
+/// others, keeping its type; any other type is an error pointing at its
+/// position.
+pub fn extreme(name: &str, arguments: &[LoxValue], wanted: Ordering) -> Result<LoxValue, RuntimeError> {
+    let mut result: Option<Number> = None;
+    for (index, argument) in arguments.iter().enumerate() {
+        let Some(n) = Number::from_lox(argument) else {
+            return Err(RuntimeError::ArgumentTypeMismatch(name.to_string(), index, "number", argument.type_name()));
+        };
+        result = match result {
+            Some(current) if n.compare(current) != Some(wanted) => Some(current),
+            _ => Some(n),
+        };
+    }
+    Ok(result.map_or(LoxValue::Nil, Number::to_lox))
+}
+
+pub struct LoxMax;
+
+impl Default for LoxMax {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LoxMax {
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+impl LoxCallable for LoxMax {
+    fn arity(&self) -> Arity {
+        Arity::AtLeast(1)
+    }
+
+    fn name(&self) -> String {
+        "max".to_string()
+    }
+
+    fn call(
+        &self,
+        _interpreter: &mut Interpreter,
+        arguments: Vec<LoxValue>,
+    ) -> Result<LoxValue, RuntimeError> {
+        extreme("max", &arguments, Ordering::Greater)
+    }
+}
+
+pub struct LoxMin;
+
+impl Default for LoxMin {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LoxMin {
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+impl LoxCallable for LoxMin {
+    fn arity(&self) -> Arity {
+        Arity::AtLeast(1)
+    }
+
+    fn name(&self) -> String {
+        "min".to_string()
+    }
+
+    fn call(
+        &self,
+        _interpreter: &mut Interpreter,
+        arguments: Vec<LoxValue>,
+    ) -> Result<LoxValue, RuntimeError> {
+        extreme("min", &arguments, Ordering::Less)
+    }
+}
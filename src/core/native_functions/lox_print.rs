@@ -1,9 +1,20 @@
 use crate::{core::{
-    error_types::runtime_error::RuntimeError, fuctions::lox_callable::LoxCallable, interpreter::Interpreter, syntax::components::expression::LoxValue
+    error_types::runtime_error::RuntimeError, fuctions::lox_callable::{Arity, LoxCallable}, interpreter::Interpreter, syntax::components::expression::LoxValue
 }, debug_dbg};
 
+// `print(a, b)` and `println(a, b)` separate their arguments with a space
+fn join(arguments: &[LoxValue]) -> String {
+    arguments.iter().map(|value| value.to_string()).collect::<Vec<_>>().join(" ")
+}
+
 pub struct LoxPrint;
 
+impl Default for LoxPrint {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl LoxPrint {
     pub fn new() -> Self {
         Self
@@ -11,8 +22,12 @@ impl LoxPrint {
 }
 
 impl LoxCallable for LoxPrint {
-    fn arity(&self) -> usize {
-        1
+    fn arity(&self) -> Arity {
+        Arity::AtLeast(1)
+    }
+
+    fn name(&self) -> String {
+        "print".to_string()
     }
 
     fn call(
@@ -26,7 +41,7 @@ impl LoxCallable for LoxPrint {
             ));
         }
 
-        arguments.iter().for_each(|f| print!("{}", f));
+        print!("{}", join(&arguments));
 
         Ok(LoxValue::Nil)
     }
@@ -34,6 +49,12 @@ impl LoxCallable for LoxPrint {
 
 pub struct LoxPrintLn;
 
+impl Default for LoxPrintLn {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl LoxPrintLn {
     pub fn new() -> Self {
         Self
@@ -41,8 +62,12 @@ impl LoxPrintLn {
 }
 
 impl LoxCallable for LoxPrintLn {
-    fn arity(&self) -> usize {
-        1
+    fn arity(&self) -> Arity {
+        Arity::AtLeast(0)
+    }
+
+    fn name(&self) -> String {
+        "println".to_string()
     }
 
     fn call(
@@ -50,10 +75,7 @@ impl LoxCallable for LoxPrintLn {
         _interpreter: &mut Interpreter,
         arguments: Vec<LoxValue>,
     ) -> Result<LoxValue, RuntimeError> {
-        arguments.iter().for_each(|f| {
-            println!("{}", f);
-        });
-        
+        println!("{}", join(&arguments));
 
         Ok(LoxValue::Nil)
     }
@@ -61,6 +83,12 @@ impl LoxCallable for LoxPrintLn {
 
 pub struct LoxDbg;
 
+impl Default for LoxDbg {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl LoxDbg {
     pub fn new() -> Self {
         Self
@@ -68,8 +96,12 @@ impl LoxDbg {
 }
 
 impl LoxCallable for LoxDbg {
-    fn arity(&self) -> usize {
-        1
+    fn arity(&self) -> Arity {
+        Arity::AtLeast(1)
+    }
+
+    fn name(&self) -> String {
+        "dbg".to_string()
     }
 
     fn call(
@@ -77,8 +109,8 @@ impl LoxCallable for LoxDbg {
         _interpreter: &mut Interpreter,
         arguments: Vec<LoxValue>,
     ) -> Result<LoxValue, RuntimeError> {
-        arguments.iter().for_each(|f| {
-            debug_dbg!(f);
+        arguments.iter().for_each(|_f| {
+            debug_dbg!(_f);
         });
         
 
@@ -0,0 +1,37 @@
+use crate::core::{
+    error_types::runtime_error::RuntimeError, fuctions::lox_callable::{Arity, LoxCallable}, interpreter::Interpreter, syntax::components::expression::LoxValue
+};
+
+/// `to_string(value)`: the value as it reads inside text, the same as
+/// `"${value}"` (see `LoxValue::text`).
+pub struct LoxToString;
+
+impl Default for LoxToString {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LoxToString {
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+impl LoxCallable for LoxToString {
+    fn arity(&self) -> Arity {
+        Arity::Exact(1)
+    }
+
+    fn name(&self) -> String {
+        "to_string".to_string()
+    }
+
+    fn call(
+        &self,
+        _interpreter: &mut Interpreter,
+        arguments: Vec<LoxValue>,
+    ) -> Result<LoxValue, RuntimeError> {
+        Ok(LoxValue::String(arguments[0].text()))
+    }
+}
@@ -1,2 +1,10 @@
 pub mod lox_clock;
-pub mod lox_print;
\ No newline at end of file
+pub mod lox_print;
+pub mod lox_args;
+pub mod lox_gc;
+pub mod lox_math;
+pub mod collection;
+pub mod lox_list;
+pub mod lox_map;
+pub mod lox_string;
+pub mod host_function;
\ No newline at end of file
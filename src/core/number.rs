@@ -0,0 +1,246 @@
+use std::cmp::Ordering;
+use std::fmt;
+
+use crate::core::error_types::runtime_error::RuntimeError;
+use crate::core::syntax::components::expression::LoxValue;
+use crate::core::syntax::token::Token;
+
+/// A numeric operand, as both backends do arithmetic on it. Integers stay
+/// exact and fail on overflow; an operation with any float in it is done
+/// in floats, and so is `/`. Bitwise operations only take integers.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Number {
+    Int(i64),
+    Float(f64),
+}
+
+/// Why an operation has no result.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NumberError {
+    Overflow,
+    DivisionByZero,
+    NotInteger,
+    ShiftOutOfRange,
+}
+
+impl NumberError {
+    /// The error to report at `operator`.
+    pub fn at(self, operator: &Token) -> RuntimeError {
+        match self {
+            NumberError::Overflow => RuntimeError::IntegerOverflow(operator.clone()),
+            NumberError::DivisionByZero => RuntimeError::BadOperator(operator.clone(), "Division by zero.".to_string()),
+            NumberError::NotInteger => {
+                RuntimeError::BadOperator(operator.clone(), "Bitwise operators only take integers.".to_string())
+            }
+            NumberError::ShiftOutOfRange => {
+                RuntimeError::BadOperator(operator.clone(), "Shift amount must be between 0 and 63.".to_string())
+            }
+        }
+    }
+}
+
+impl Number {
+    /// `None` for values that aren't numbers.
+    pub fn from_lox(value: &LoxValue) -> Option<Self> {
+        match value {
+            LoxValue::Integer(n) => Some(Number::Int(*n)),
+            LoxValue::Number(n) => Some(Number::Float(*n)),
+            _ => None,
+        }
+    }
+
+    pub fn to_lox(self) -> LoxValue {
+        match self {
+            Number::Int(n) => LoxValue::Integer(n),
+            Number::Float(n) => LoxValue::Number(n),
+        }
+    }
+
+    pub fn as_f64(self) -> f64 {
+        match self {
+            Number::Int(n) => n as f64,
+            Number::Float(n) => n,
+        }
+    }
+
+    // Both integers, or else both as floats
+    fn promote(self, other: Number) -> Result<(i64, i64), (f64, f64)> {
+        match (self, other) {
+            (Number::Int(a), Number::Int(b)) => Ok((a, b)),
+            (a, b) => Err((a.as_f64(), b.as_f64())),
+        }
+    }
+
+    fn apply(
+        self,
+        other: Number,
+        int: fn(i64, i64) -> Option<i64>,
+        float: fn(f64, f64) -> f64,
+    ) -> Result<Number, NumberError> {
+        match self.promote(other) {
+            Ok((a, b)) => int(a, b).map(Number::Int).ok_or(NumberError::Overflow),
+            Err((a, b)) => Ok(Number::Float(float(a, b))),
+        }
+    }
+
+    pub fn checked_add(self, other: Number) -> Result<Number, NumberError> {
+        self.apply(other, i64::checked_add, |a, b| a + b)
+    }
+
+    pub fn checked_sub(self, other: Number) -> Result<Number, NumberError> {
+        self.apply(other, i64::checked_sub, |a, b| a - b)
+    }
+
+    pub fn checked_mul(self, other: Number) -> Result<Number, NumberError> {
+        self.apply(other, i64::checked_mul, |a, b| a * b)
+    }
+
+    /// Always a float, so `7 / 2` is `3.5`.
+    pub fn checked_div(self, other: Number) -> Result<Number, NumberError> {
+        if other.as_f64() == 0.0 {
+            return Err(NumberError::DivisionByZero);
+        }
+        Ok(Number::Float(self.as_f64() / other.as_f64()))
+    }
+
+    /// Rounds towards negative infinity, like `%` takes the divisor's
+    /// sign, so `a == (a ~/ b) * b + a % b`. A float in gives a whole float.
+    pub fn checked_floor_div(self, other: Number) -> Result<Number, NumberError> {
+        if other.as_f64() == 0.0 {
+            return Err(NumberError::DivisionByZero);
+        }
+        match self.promote(other) {
+            Ok((a, b)) => {
+                let quotient = a.checked_div(b).ok_or(NumberError::Overflow)?;
+                // Truncation rounded up when the remainder has the wrong sign
+                let borrow = a % b != 0 && (a < 0) != (b < 0);
+                Ok(Number::Int(if borrow { quotient - 1 } else { quotient }))
+            }
+            Err((a, b)) => Ok(Number::Float((a / b).floor())),
+        }
+    }
+
+    /// Has the sign of the divisor, so `-7 % 3` is `2`.
+    pub fn checked_rem(self, other: Number) -> Result<Number, NumberError> {
+        if other.as_f64() == 0.0 {
+            return Err(NumberError::DivisionByZero);
+        }
+        match self.promote(other) {
+            Ok((a, b)) => {
+                // Only `i64::MIN % -1` overflows, and its remainder is 0
+                let remainder = a.wrapping_rem(b);
+                let wrong_sign = remainder != 0 && (remainder < 0) != (b < 0);
+                Ok(Number::Int(if wrong_sign { remainder + b } else { remainder }))
+            }
+            Err((a, b)) => {
+                let remainder = a % b;
+                let wrong_sign = remainder != 0.0 && (remainder < 0.0) != (b < 0.0);
+                Ok(Number::Float(if wrong_sign { remainder + b } else { remainder }))
+            }
+        }
+    }
+
+    /// An integer to a negative power is a float, like `2 ** -1`.
+    pub fn checked_pow(self, other: Number) -> Result<Number, NumberError> {
+        match self.promote(other) {
+            Ok((a, b)) if b >= 0 => u32::try_from(b)
+                .ok()
+                .and_then(|b| a.checked_pow(b))
+                .map(Number::Int)
+                .ok_or(NumberError::Overflow),
+            Ok((a, b)) => Ok(Number::Float((a as f64).powf(b as f64))),
+            Err((a, b)) => Ok(Number::Float(a.powf(b))),
+        }
+    }
+
+    fn integers(self, other: Number) -> Result<(i64, i64), NumberError> {
+        match (self, other) {
+            (Number::Int(a), Number::Int(b)) => Ok((a, b)),
+            _ => Err(NumberError::NotInteger),
+        }
+    }
+
+    pub fn bit_and(self, other: Number) -> Result<Number, NumberError> {
+        self.integers(other).map(|(a, b)| Number::Int(a & b))
+    }
+
+    pub fn bit_or(self, other: Number) -> Result<Number, NumberError> {
+        self.integers(other).map(|(a, b)| Number::Int(a | b))
+    }
+
+    pub fn bit_xor(self, other: Number) -> Result<Number, NumberError> {
+        self.integers(other).map(|(a, b)| Number::Int(a ^ b))
+    }
+
+    /// Bits shifted past the top are dropped, so `1 << 63` is negative.
+    pub fn checked_shl(self, other: Number) -> Result<Number, NumberError> {
+        let (a, b) = self.integers(other)?;
+        let b = u32::try_from(b).map_err(|_| NumberError::ShiftOutOfRange)?;
+        a.checked_shl(b).map(Number::Int).ok_or(NumberError::ShiftOutOfRange)
+    }
+
+    /// Keeps the sign, so `-8 >> 1` is `-4`.
+    pub fn checked_shr(self, other: Number) -> Result<Number, NumberError> {
+        let (a, b) = self.integers(other)?;
+        let b = u32::try_from(b).map_err(|_| NumberError::ShiftOutOfRange)?;
+        a.checked_shr(b).map(Number::Int).ok_or(NumberError::ShiftOutOfRange)
+    }
+
+    pub fn bit_not(self) -> Result<Number, NumberError> {
+        match self {
+            Number::Int(n) => Ok(Number::Int(!n)),
+            Number::Float(_) => Err(NumberError::NotInteger),
+        }
+    }
+
+    pub fn checked_neg(self) -> Result<Number, NumberError> {
+        match self {
+            Number::Int(n) => n.checked_neg().map(Number::Int).ok_or(NumberError::Overflow),
+            Number::Float(n) => Ok(Number::Float(-n)),
+        }
+    }
+
+    /// Integers compare exactly, anything else as floats. `None` when a
+    /// NaN is involved.
+    pub fn compare(self, other: Number) -> Option<Ordering> {
+        match (self, other) {
+            (Number::Int(a), Number::Int(b)) => Some(a.cmp(&b)),
+            (Number::Int(a), Number::Float(b)) => compare_mixed(a, b),
+            (Number::Float(a), Number::Int(b)) => compare_mixed(b, a).map(Ordering::reverse),
+            (Number::Float(a), Number::Float(b)) => a.partial_cmp(&b),
+        }
+    }
+
+    /// `1 == 1.0`, like the comparisons. An integer only equals the float
+    /// with exactly its value, as map keys do.
+    pub fn equals(self, other: Number) -> bool {
+        self.compare(other) == Some(Ordering::Equal)
+    }
+}
+
+// An integer against a float, exactly: converting the integer to a float
+// could round it onto the other one
+fn compare_mixed(int: i64, float: f64) -> Option<Ordering> {
+    if float.is_nan() {
+        return None;
+    }
+    // `i64::MAX as f64` rounds up past the range, so that bound is open
+    if float >= i64::MAX as f64 {
+        return Some(Ordering::Less);
+    }
+    if float < i64::MIN as f64 {
+        return Some(Ordering::Greater);
+    }
+    let fraction = 0.0_f64.partial_cmp(&float.fract())?;
+    Some(int.cmp(&(float.trunc() as i64)).then(fraction))
+}
+
+/// As the number reads inside text, without its type.
+impl fmt::Display for Number {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Number::Int(n) => write!(f, "{}", n),
+            Number::Float(n) => write!(f, "{}", n),
+        }
+    }
+}
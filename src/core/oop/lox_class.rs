@@ -1,16 +1,19 @@
 use core::fmt;
 use std::{cell::RefCell, fmt::{Display, Formatter}, rc::Rc};
 use rustc_hash::FxHashMap;
-use crate::core::{error_types::runtime_error::RuntimeError, fuctions::{lox_callable::LoxCallable, lox_function::LoxFunction}, interpreter::Interpreter, syntax::components::expression::LoxValue};
+use crate::core::{error_types::runtime_error::RuntimeError, gc::Tracer, fuctions::{lox_callable::{Arity, LoxCallable}, lox_function::LoxFunction}, interpreter::Interpreter, syntax::components::expression::LoxValue};
 
 use super::lox_instance::LoxInstance;
+use super::native_class::NativeClassDef;
 // use std::collections::HashMap;
 #[derive(Clone)]
 pub struct LoxClass {
     pub name: String,
     methods: FxHashMap<String, LoxFunction>,
     statics: FxHashMap<String, LoxFunction>,
-    pub super_class: Option< Box<LoxClass>>
+    pub super_class: Option< Box<LoxClass>>,
+    // Set for classes registered from Rust, whose instances wrap a struct
+    pub native: Option<Rc<NativeClassDef>>
 }
 
 impl LoxClass {
@@ -35,7 +38,18 @@ impl LoxClass {
             name,
             methods: c_methods, 
             statics,
-            super_class
+            super_class,
+            native: None
+        }
+    }
+
+    pub fn native(native: Rc<NativeClassDef>) -> Self {
+        Self {
+            name: native.name.clone(),
+            methods: FxHashMap::default(),
+            statics: FxHashMap::default(),
+            super_class: None,
+            native: Some(native)
         }
     }
 
@@ -49,6 +63,14 @@ impl LoxClass {
     }
     
     
+    /// The `init` method, declared here or inherited.
+    pub fn initializer(&self) -> Option<Rc<LoxFunction>> {
+        match self.find_method("init") {
+            LoxValue::LoxFunction(init) => Some(init),
+            _ => None,
+        }
+    }
+
     pub fn find_method(&self, name: &str) -> LoxValue {
         if let Some(method) = self.methods.get(name) {
             return LoxValue::LoxFunction(Rc::new(method.clone()));
@@ -62,15 +84,40 @@ impl LoxClass {
 }
 
 impl LoxCallable for LoxClass {
-    fn arity(&self) -> usize {
-        0
+    fn arity(&self) -> Arity {
+        match &self.native {
+            Some(native) => Arity::Exact(native.arity()),
+            None => self.initializer().map_or(Arity::Exact(0), |init| init.arity()),
+        }
+    }
+
+    fn name(&self) -> String {
+        self.name.clone()
     }
     
-    fn call(&self, _interpreter: &mut Interpreter, _arguments: Vec<LoxValue>) -> Result<LoxValue, RuntimeError> {
+    fn call(&self, interpreter: &mut Interpreter, arguments: Vec<LoxValue>) -> Result<LoxValue, RuntimeError> {
+        if let Some(native) = &self.native {
+            let object = native.construct(arguments)?;
+            let loxinstance = Rc::new(RefCell::new(LoxInstance::with_native(self.clone(), object)));
+            interpreter.heap.track(&loxinstance);
+            return Ok(LoxValue::LoxInstance(loxinstance));
+        }
         let loxinstance = Rc::new(RefCell::new(LoxInstance::new(self.clone())));
-        Ok(LoxValue::LoxInstance(Rc::clone(&loxinstance)))
+        interpreter.heap.track(&loxinstance);
+        if let Some(init) = self.initializer() {
+            init.bind(Rc::clone(&loxinstance))?.call(interpreter, arguments)?;
+        }
+        Ok(LoxValue::LoxInstance(loxinstance))
+    }
+
+    fn trace(&self, tracer: &mut Tracer) {
+        for method in self.methods.values().chain(self.statics.values()) {
+            method.trace(tracer);
+        }
+        if let Some(super_class) = &self.super_class {
+            super_class.trace(tracer);
+        }
     }
-    
 }
 
 impl Display for LoxClass {
@@ -4,26 +4,51 @@ use std::rc::Rc;
 
 use rustc_hash::FxHashMap;
 use crate::core::error_types::runtime_error::RuntimeError;
+use crate::core::fuctions::lox_callable::LoxCallable;
+use crate::core::gc::{Trace, Tracer};
 use crate::core::syntax::components::expression::LoxValue;
 use crate::core::syntax::token::Token;
 
 use super::lox_class::LoxClass;
+use super::native_class::{LoxNativeMethod, NativeObject};
 use std::fmt::Display;
 use std::fmt::Formatter;
 #[derive(Clone)]
 pub struct LoxInstance {
     pub lox_class: LoxClass,
-    fields: FxHashMap<String, LoxValue>
+    fields: FxHashMap<String, LoxValue>,
+    // The struct behind an instance of a native class
+    native: Option<NativeObject>
 }
 
 impl LoxInstance {
     pub fn new(lox_class: LoxClass) -> LoxInstance {
         Self {
             lox_class,
-            fields: FxHashMap::default()
+            fields: FxHashMap::default(),
+            native: None
         }
     }
+
+    pub fn with_native(lox_class: LoxClass, object: NativeObject) -> LoxInstance {
+        Self {
+            native: Some(object),
+            ..Self::new(lox_class)
+        }
+    }
+
+    pub fn native(&self) -> Option<&NativeObject> {
+        self.native.as_ref()
+    }
     pub fn get(&self, r: Rc<RefCell<Self>>, name: &Token, is_this: bool) -> Result<LoxValue, RuntimeError> {
+        if let (Some(class), Some(object)) = (&self.lox_class.native, &self.native) {
+            if let Some(value) = class.get(object, &name.lexeme)? {
+                return Ok(value);
+            }
+            if let Some(method) = class.method(&name.lexeme) {
+                return Ok(LoxValue::Callable(Rc::new(LoxNativeMethod::new(Rc::clone(object), method))));
+            }
+        }
         if let Some(v) = self.fields.get(&name.lexeme) {
             return Ok(v.clone());
         }
@@ -33,38 +58,56 @@ impl LoxInstance {
 
         if method != LoxValue::Nil {
             if let LoxValue::LoxFunction(f) = method {
-                if f.is_public() {
-                    return Ok(
-                        LoxValue::LoxFunction(
-                            f.bind(Rc::clone(&r))?.into()
-                        )
-                    )
-                } else if is_this {
+                if f.is_public() || is_this {
                     return Ok(
                         LoxValue::LoxFunction(
                             f.bind(Rc::clone(&r))?.into()
                         )
                     )
                 } else {
-                    return Err(RuntimeError::CantAccessPrivateMethod());     
+                    return Err(RuntimeError::CantAccessPrivateMethod(name.clone()));     
                 }
             }
-            return Err(RuntimeError::UndefinedProperty());               
+            return Err(RuntimeError::UndefinedProperty(name.clone()));               
         } else if s_method != LoxValue::Nil {
-            return Err(RuntimeError::CantCallStaticMethodFromInstance())
+            return Err(RuntimeError::CantCallStaticMethodFromInstance(name.clone()))
         }
     
-        Err(RuntimeError::UndefinedProperty())
+        Err(RuntimeError::UndefinedProperty(name.clone()))
     }
     
 
-    pub fn set(&mut self, name: Token, value: LoxValue) {
+    pub fn set(&mut self, name: Token, value: LoxValue) -> Result<(), RuntimeError> {
+        if let (Some(class), Some(object)) = (&self.lox_class.native, &self.native) {
+            if class.set(object, &name, value.clone())? {
+                return Ok(());
+            }
+        }
         self.fields.insert(name.lexeme, value);
+        Ok(())
     }
 
 
 }
 
+impl Trace for RefCell<LoxInstance> {
+    fn trace(&self, tracer: &mut Tracer) {
+        let Ok(instance) = self.try_borrow() else {
+            return tracer.opaque();
+        };
+        for value in instance.fields.values() {
+            value.trace(tracer);
+        }
+        instance.lox_class.trace(tracer);
+    }
+
+    fn clear(&self) {
+        if let Ok(mut instance) = self.try_borrow_mut() {
+            instance.fields.clear();
+        }
+    }
+}
+
 impl Display for LoxInstance {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         if let Some(s) = &self.lox_class.super_class {
@@ -1,2 +1,3 @@
 pub mod lox_class;
-pub mod lox_instance;
\ No newline at end of file
+pub mod lox_instance;
+pub mod native_class;
\ No newline at end of file
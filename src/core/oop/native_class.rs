@@ -0,0 +1,291 @@
+use std::any::Any;
+use std::cell::RefCell;
+use std::fmt;
+use std::marker::PhantomData;
+use std::rc::Rc;
+
+use rustc_hash::FxHashMap;
+
+use crate::core::{
+    convert::{FromLox, IntoLox}, error_types::runtime_error::RuntimeError, fuctions::lox_callable::{Arity, LoxCallable}, interpreter::Interpreter, native_functions::host_function::{convert_arguments, HostResult}, syntax::{components::expression::LoxValue, token::Token}
+};
+
+/// The Rust value behind an instance of a native class: an `Rc<RefCell<T>>`
+/// with `T` erased.
+pub type NativeObject = Rc<dyn Any>;
+
+type Constructor = Box<dyn Fn(Vec<LoxValue>) -> Result<NativeObject, RuntimeError>>;
+type Getter = Box<dyn Fn(&dyn Any) -> Result<LoxValue, RuntimeError>>;
+type Setter = Box<dyn Fn(&dyn Any, LoxValue) -> Result<(), RuntimeError>>;
+type Method = Box<dyn Fn(&dyn Any, Vec<LoxValue>) -> Result<LoxValue, RuntimeError>>;
+
+/// A Rust closure usable as the constructor of a native class over `T`.
+/// Implemented for `Fn`s of up to six `FromLox` parameters returning `T`.
+pub trait ConstructorFn<T, Args>: 'static {
+    fn arity(&self) -> usize;
+    fn construct(&self, name: &str, arguments: Vec<LoxValue>) -> Result<T, RuntimeError>;
+}
+
+/// A Rust closure usable as a method of a native class over `T`: a `Fn`
+/// taking `&mut T` and up to six `FromLox` parameters, returning a
+/// `HostResult`.
+pub trait MethodFn<T, Args>: 'static {
+    fn arity(&self) -> usize;
+    fn invoke(&self, name: &str, object: &mut T, arguments: Vec<LoxValue>) -> Result<LoxValue, RuntimeError>;
+}
+
+macro_rules! native_class_fns {
+    ($($arg:ident),*) => {
+        impl<F, T, $($arg: FromLox),*> ConstructorFn<T, ($($arg,)*)> for F
+        where
+            F: Fn($($arg),*) -> T + 'static,
+        {
+            fn arity(&self) -> usize {
+                <[&str]>::len(&[$(stringify!($arg)),*])
+            }
+
+            #[allow(non_snake_case, unused_variables, unused_mut)]
+            fn construct(&self, name: &str, arguments: Vec<LoxValue>) -> Result<T, RuntimeError> {
+                convert_arguments!(name, arguments, self.arity(); $($arg),*);
+                Ok(self($($arg),*))
+            }
+        }
+
+        impl<F, T, R, $($arg: FromLox),*> MethodFn<T, ($($arg,)*)> for F
+        where
+            F: Fn(&mut T, $($arg),*) -> R + 'static,
+            R: HostResult,
+        {
+            fn arity(&self) -> usize {
+                <[&str]>::len(&[$(stringify!($arg)),*])
+            }
+
+            #[allow(non_snake_case, unused_variables, unused_mut)]
+            fn invoke(&self, name: &str, object: &mut T, arguments: Vec<LoxValue>) -> Result<LoxValue, RuntimeError> {
+                convert_arguments!(name, arguments, self.arity(); $($arg),*);
+                self(object, $($arg),*).into_result()
+            }
+        }
+    };
+}
+
+native_class_fns!();
+native_class_fns!(A);
+native_class_fns!(A, B);
+native_class_fns!(A, B, C);
+native_class_fns!(A, B, C, D);
+native_class_fns!(A, B, C, D, E);
+native_class_fns!(A, B, C, D, E, G);
+
+fn cell<T: 'static>(object: &dyn Any) -> &RefCell<T> {
+    object.downcast_ref::<RefCell<T>>().expect("native object of another class")
+}
+
+// The host may still hold a borrow of the struct while a script runs
+fn borrowed(class_name: &str) -> RuntimeError {
+    RuntimeError::NativeFunctionError(format!("'{}' is borrowed by the host", class_name))
+}
+
+/// A method of a native class, with its receiver type erased.
+pub struct NativeMethod {
+    name: String,
+    arity: usize,
+    function: Method,
+}
+
+impl NativeMethod {
+    /// `Class.method`, as shown in stack traces.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn arity(&self) -> usize {
+        self.arity
+    }
+
+    pub fn call(&self, object: &NativeObject, arguments: Vec<LoxValue>) -> Result<LoxValue, RuntimeError> {
+        (self.function)(&**object, arguments)
+    }
+}
+
+/// A class declared from Rust, once built by [`NativeClass`]. Shared by
+/// every backend that registers it.
+pub struct NativeClassDef {
+    pub name: String,
+    arity: usize,
+    constructor: Option<Constructor>,
+    methods: FxHashMap<String, Rc<NativeMethod>>,
+    getters: FxHashMap<String, Getter>,
+    setters: FxHashMap<String, Setter>,
+}
+
+impl fmt::Debug for NativeClassDef {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "NativeClassDef({})", self.name)
+    }
+}
+
+impl NativeClassDef {
+    pub fn arity(&self) -> usize {
+        self.arity
+    }
+
+    /// Runs the constructor with already checked-for-arity `arguments`.
+    pub fn construct(&self, arguments: Vec<LoxValue>) -> Result<NativeObject, RuntimeError> {
+        match &self.constructor {
+            Some(constructor) => constructor(arguments),
+            None => Err(RuntimeError::NativeFunctionError(format!(
+                "'{}' has no constructor and can't be created from Lox",
+                self.name
+            ))),
+        }
+    }
+
+    pub fn method(&self, name: &str) -> Option<Rc<NativeMethod>> {
+        self.methods.get(name).cloned()
+    }
+
+    /// The property `name` of `object`, `None` when there is no getter for it.
+    pub fn get(&self, object: &NativeObject, name: &str) -> Result<Option<LoxValue>, RuntimeError> {
+        self.getters.get(name).map(|getter| getter(&**object)).transpose()
+    }
+
+    /// Writes the property `name` of `object`. Returns `false` when it is
+    /// not a native property, so the value belongs in a plain field.
+    pub fn set(&self, object: &NativeObject, name: &Token, value: LoxValue) -> Result<bool, RuntimeError> {
+        match self.setters.get(&name.lexeme) {
+            Some(setter) => setter(&**object, value).map(|_| true),
+            None if self.getters.contains_key(&name.lexeme) => Err(RuntimeError::ReadOnlyProperty(name.clone())),
+            None => Ok(false),
+        }
+    }
+}
+
+/// Builder for a Lox class backed by the Rust struct `T`.
+///
+/// ```
+/// # use rust_lox_interpreter::core::oop::native_class::NativeClass;
+/// # use rust_lox_interpreter::core::session::LoxSession;
+/// struct Account { owner: String, balance: f64 }
+///
+/// let account = NativeClass::<Account>::new("Account")
+///     .constructor(|owner: String| Account { owner, balance: 0.0 })
+///     .method("deposit", |account: &mut Account, amount: f64| account.balance += amount)
+///     .getter("balance", |account: &Account| account.balance)
+///     .getter("owner", |account: &Account| account.owner.clone())
+///     .setter("owner", |account: &mut Account, owner: String| account.owner = owner);
+///
+/// let mut session = LoxSession::new();
+/// session.register_class(account);
+/// let acct = session.eval("var acct = Account(\"x\"); acct.deposit(10); acct;").unwrap();
+/// assert_eq!(acct.as_native::<Account>().unwrap().borrow().balance, 10.0);
+/// ```
+pub struct NativeClass<T> {
+    def: NativeClassDef,
+    marker: PhantomData<T>,
+}
+
+impl<T: 'static> NativeClass<T> {
+    pub fn new(name: &str) -> Self {
+        Self {
+            def: NativeClassDef {
+                name: name.to_string(),
+                arity: 0,
+                constructor: None,
+                methods: FxHashMap::default(),
+                getters: FxHashMap::default(),
+                setters: FxHashMap::default(),
+            },
+            marker: PhantomData,
+        }
+    }
+
+    /// What calling the class runs. Without one, calling it is an error.
+    pub fn constructor<Args, F: ConstructorFn<T, Args>>(mut self, constructor: F) -> Self {
+        let name = self.def.name.clone();
+        self.def.arity = constructor.arity();
+        self.def.constructor = Some(Box::new(move |arguments| {
+            let object = constructor.construct(&name, arguments)?;
+            Ok(Rc::new(RefCell::new(object)) as NativeObject)
+        }));
+        self
+    }
+
+    pub fn method<Args, F: MethodFn<T, Args>>(mut self, name: &str, method: F) -> Self {
+        let qualified_name = format!("{}.{}", self.def.name, name);
+        let class_name = self.def.name.clone();
+        let native_method = NativeMethod {
+            name: qualified_name.clone(),
+            arity: method.arity(),
+            function: Box::new(move |object, arguments| {
+                let mut object = cell::<T>(object).try_borrow_mut().map_err(|_| borrowed(&class_name))?;
+                method.invoke(&qualified_name, &mut object, arguments)
+            }),
+        };
+        self.def.methods.insert(name.to_string(), Rc::new(native_method));
+        self
+    }
+
+    /// Makes `name` a readable property.
+    pub fn getter<R: IntoLox, F: Fn(&T) -> R + 'static>(mut self, name: &str, getter: F) -> Self {
+        let class_name = self.def.name.clone();
+        self.def.getters.insert(
+            name.to_string(),
+            Box::new(move |object| {
+                let object = cell::<T>(object).try_borrow().map_err(|_| borrowed(&class_name))?;
+                Ok(getter(&object).into_lox())
+            }),
+        );
+        self
+    }
+
+    /// Makes `name` writable. Properties with a getter and no setter are
+    /// read-only.
+    pub fn setter<V: FromLox, F: Fn(&mut T, V) + 'static>(mut self, name: &str, setter: F) -> Self {
+        let class_name = self.def.name.clone();
+        let qualified_name = format!("{}.{}", self.def.name, name);
+        self.def.setters.insert(
+            name.to_string(),
+            Box::new(move |object, value| {
+                let found = value.type_name();
+                let value = V::from_lox(value).ok_or_else(|| {
+                    RuntimeError::ArgumentTypeMismatch(qualified_name.clone(), 0, V::EXPECTED, found)
+                })?;
+                let mut object = cell::<T>(object).try_borrow_mut().map_err(|_| borrowed(&class_name))?;
+                setter(&mut object, value);
+                Ok(())
+            }),
+        );
+        self
+    }
+
+    pub fn build(self) -> NativeClassDef {
+        self.def
+    }
+}
+
+/// A native method bound to the instance it was read from.
+pub struct LoxNativeMethod {
+    object: NativeObject,
+    method: Rc<NativeMethod>,
+}
+
+impl LoxNativeMethod {
+    pub fn new(object: NativeObject, method: Rc<NativeMethod>) -> Self {
+        Self { object, method }
+    }
+}
+
+impl LoxCallable for LoxNativeMethod {
+    fn arity(&self) -> Arity {
+        Arity::Exact(self.method.arity())
+    }
+
+    fn name(&self) -> String {
+        self.method.name().to_string()
+    }
+
+    fn call(&self, _interpreter: &mut Interpreter, arguments: Vec<LoxValue>) -> Result<LoxValue, RuntimeError> {
+        self.method.call(&self.object, arguments)
+    }
+}
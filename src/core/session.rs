@@ -0,0 +1,237 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use super::diagnostics::source_map::SourceMap;
+use super::environment::Environment;
+use super::error_types::lox_error::LoxError;
+use super::error_types::runtime_error::RuntimeError;
+use super::gc::HeapStats;
+use super::fuctions::lox_callable::Arity;
+use super::interpreter::Interpreter;
+use super::oop::lox_class::LoxClass;
+use super::oop::native_class::NativeClass;
+use super::native_functions::host_function::{HostFunction, LoxHostFn};
+use super::native_functions::lox_args::{LoxArgc, LoxArgv};
+use super::syntax::analysing::parser::Parser;
+use super::syntax::analysing::scanner::Scanner;
+use super::syntax::components::expression::LoxValue;
+use super::syntax::components::stmt::Stmt;
+use super::syntax::resolver::Resolver;
+use super::vm::compiler::Compiler;
+use super::vm::machine::Vm;
+use super::vm::value::Value;
+
+/// Which engine runs the resolved program.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Backend {
+    /// `Interpreter`, walking the syntax tree.
+    #[default]
+    TreeWalker,
+    /// `Compiler` to bytecode, run by the `Vm`.
+    Vm,
+}
+
+/// An embeddable interpreter instance.
+///
+/// Globals, resolved locals and class definitions survive between calls to
+/// [`LoxSession::eval`]. Nothing is written to stdout except what the script
+/// itself prints.
+pub struct LoxSession {
+    interpreter: Rc<RefCell<Interpreter>>,
+    unused_variables: Vec<String>,
+    // Every evaluated source gets its own file id so spans never collide
+    sources: SourceMap,
+    backend: Backend,
+    vm: Vm,
+}
+
+impl LoxSession {
+    pub fn new() -> Self {
+        Self {
+            interpreter: Rc::new(RefCell::new(Interpreter::new(Environment::new(None)))),
+            unused_variables: vec![],
+            sources: SourceMap::new(),
+            backend: Backend::default(),
+            vm: Vm::new(),
+        }
+    }
+
+    /// Switches the engine used by later calls to `eval`. Each backend keeps
+    /// its own globals.
+    pub fn set_backend(&mut self, backend: Backend) {
+        self.backend = backend;
+    }
+
+    pub fn backend(&self) -> Backend {
+        self.backend
+    }
+
+    /// How many calls deep scripts may go, on both backends, before failing
+    /// with a stack overflow error. The default,
+    /// [`DEFAULT_CALL_DEPTH`](crate::core::error_types::runtime_error::DEFAULT_CALL_DEPTH),
+    /// fits the stack of a spawned thread; raise it only on a thread with a
+    /// bigger stack, as the tree-walker crashes the process when it runs
+    /// out.
+    pub fn set_max_call_depth(&mut self, depth: usize) {
+        self.interpreter.borrow_mut().set_max_call_depth(depth);
+        self.vm.set_max_call_depth(depth);
+    }
+
+    /// Scans, parses, resolves and runs `source`, returning the value of the
+    /// last expression statement (or `nil`).
+    pub fn eval(&mut self, source: &str) -> Result<LoxValue, LoxError> {
+        self.eval_named("<eval>", source)
+    }
+
+    /// Like `eval`, recording `name` as the file diagnostics point at.
+    ///
+    /// On the VM, lists and maps come back as copies and instances of
+    /// native classes share their struct; other instances, classes and
+    /// functions have no `LoxValue` counterpart and come back as `nil`.
+    pub fn eval_named(&mut self, name: &str, source: &str) -> Result<LoxValue, LoxError> {
+        let statements = self.compile(name, source)?;
+        match self.backend {
+            Backend::TreeWalker => Ok(self.interpreter.borrow_mut().interpret(statements)?),
+            Backend::Vm => {
+                let script = Compiler::compile(&statements).map_err(LoxError::Compile)?;
+                Ok(self.vm.interpret(script)?.to_lox())
+            }
+        }
+    }
+
+    /// Scans, parses and resolves `source` without running it.
+    pub fn check(&mut self, source: &str) -> Result<(), LoxError> {
+        self.check_named("<eval>", source)
+    }
+
+    pub fn check_named(&mut self, name: &str, source: &str) -> Result<(), LoxError> {
+        self.compile(name, source).map(|_| ())
+    }
+
+    fn compile(&mut self, name: &str, source: &str) -> Result<Vec<Stmt>, LoxError> {
+        let file_id = self.sources.add(name, source);
+        let mut scanner = Scanner::for_file(source.to_string(), file_id);
+        let tokens = scanner.scan_tokens();
+        if !scanner.errors().is_empty() {
+            return Err(LoxError::Scanner(scanner.errors().to_vec()));
+        }
+
+        let mut parser = Parser::new(tokens);
+        let (statements, errors) = parser.parse();
+        if !errors.is_empty() {
+            return Err(LoxError::Parse(errors));
+        }
+
+        let mut resolver = Resolver::new(Rc::clone(&self.interpreter));
+        resolver.resolve_statements(&statements).map_err(LoxError::Resolve)?;
+        self.unused_variables = resolver.get_unused_variables().clone();
+
+        Ok(statements)
+    }
+
+    /// Exposes `args` to scripts through the `argc()` and `argv(i)` natives.
+    pub fn set_args(&mut self, args: Vec<String>) {
+        let args = Rc::new(args);
+        let interpreter = self.interpreter.borrow();
+        let mut globals = interpreter.globals.borrow_mut();
+        globals.redefine("argc", LoxValue::Callable(Rc::new(LoxArgc::new(Rc::clone(&args)))));
+        globals.redefine("argv", LoxValue::Callable(Rc::new(LoxArgv::new(Rc::clone(&args)))));
+
+        let count = args.len();
+        self.vm.define_native("argc", Arity::Exact(0), move |_| Ok(Value::Integer(count as i64)));
+        self.vm.define_native("argv", Arity::Exact(1), move |arguments| match &arguments[0] {
+            Value::Integer(n) if *n >= 0 => Ok(args
+                .get(*n as usize)
+                .map_or(Value::Nil, |arg| Value::String(arg.as_str().into()))),
+            _ => Err(RuntimeError::NativeFunctionError(
+                "argv expects a non-negative integer index".to_string(),
+            )),
+        });
+    }
+
+    /// Exposes a Rust closure to scripts as the global function `name`, on
+    /// both backends.
+    ///
+    /// Parameters convert from Lox through `FromLox` and the result back
+    /// through `IntoLox`; returning `Err` raises a runtime error. Calls with
+    /// the wrong number of arguments or an argument of the wrong type fail
+    /// before the closure runs.
+    ///
+    /// ```
+    /// # use rust_lox_interpreter::core::session::LoxSession;
+    /// # use rust_lox_interpreter::core::syntax::components::expression::LoxValue;
+    /// let mut session = LoxSession::new();
+    /// session.register_fn("add", |a: f64, b: f64| a + b);
+    /// assert_eq!(session.eval("add(1, 2);").unwrap(), LoxValue::Number(3.0));
+    /// ```
+    ///
+    /// On the VM, lists and maps arrive as copies and other objects as
+    /// `nil`, though type errors still name the type passed. Returning an
+    /// instance, class or function is an error.
+    pub fn register_fn<Args, F: HostFunction<Args>>(&mut self, name: &str, function: F) {
+        let function = Rc::new(function);
+        let host_fn = LoxHostFn::new(name, Rc::clone(&function));
+        self.interpreter.borrow().globals.borrow_mut().redefine(name, LoxValue::Callable(Rc::new(host_fn)));
+
+        let owned_name = name.to_string();
+        self.vm.define_native(name, Arity::Exact(function.arity()), move |arguments| {
+            let result = Value::call_host(arguments, |arguments| function.invoke(&owned_name, arguments))?;
+            Value::from_host(&owned_name, result)
+        });
+    }
+
+    /// Exposes a class backed by the Rust struct `T` to scripts, on both
+    /// backends. See [`NativeClass`] for how to declare one; the struct of
+    /// an instance comes back out through `LoxValue::as_native`.
+    ///
+    /// Lox classes can't inherit from it. Instances returned by `eval` on
+    /// either backend share the struct the script sees.
+    pub fn register_class<T: 'static>(&mut self, class: NativeClass<T>) {
+        let native = Rc::new(class.build());
+        let lox_class = LoxClass::native(Rc::clone(&native));
+        self.interpreter.borrow().globals.borrow_mut().redefine(&native.name, LoxValue::LoxClass(lox_class));
+        self.vm.define_native_class(native);
+    }
+
+    /// Variables declared but never read during the last successful resolve.
+    pub fn unused_variables(&self) -> &[String] {
+        &self.unused_variables
+    }
+
+    /// Every source evaluated so far, for rendering diagnostics.
+    pub fn sources(&self) -> &SourceMap {
+        &self.sources
+    }
+
+    /// Frees unreachable cycles on the current backend now, returning how
+    /// many objects went with them. Collections also run on their own as
+    /// objects pile up.
+    pub fn collect_garbage(&mut self) -> usize {
+        match self.backend {
+            Backend::TreeWalker => self.interpreter.borrow_mut().heap.collect(),
+            Backend::Vm => self.vm.collect_garbage(),
+        }
+    }
+
+    /// Heap counters of the current backend.
+    pub fn heap_stats(&self) -> HeapStats {
+        match self.backend {
+            Backend::TreeWalker => self.interpreter.borrow().heap.stats(),
+            Backend::Vm => self.vm.heap_stats(),
+        }
+    }
+
+    pub fn interpreter(&self) -> Rc<RefCell<Interpreter>> {
+        Rc::clone(&self.interpreter)
+    }
+
+    pub fn vm(&self) -> &Vm {
+        &self.vm
+    }
+}
+
+impl Default for LoxSession {
+    fn default() -> Self {
+        Self::new()
+    }
+}
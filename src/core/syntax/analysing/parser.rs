@@ -1,5 +1,5 @@
 
-use crate::{core::{error_types::parse_error::ParseError, syntax::{components::{expression::{Expr, LoxValue}, stmt::Stmt}, token::Token, token_type::TokenType}}, utils::colors::Color};
+use crate::core::{error_types::parse_error::ParseError, syntax::{components::{expression::{Expr, LoxValue}, stmt::{Param, Stmt}}, span::Span, token::Token, token_type::TokenType}};
 
 use crate::core::syntax::token_type::TokenType::*;
 
@@ -7,6 +7,9 @@ pub struct Parser {
     tokens: Vec<Token>,
     current: usize,
     errors: Vec<ParseError>,
+    // Blocks and class bodies currently open, so `synchronize` knows
+    // whether a `}` belongs to someone
+    block_depth: usize,
 }
 
 impl Parser {
@@ -15,30 +18,47 @@ impl Parser {
             tokens,
             current: 0,
             errors: vec![],
+            block_depth: 0,
         }
     }
 
-    pub fn parse(&mut self) -> Result<Vec<Stmt>, ParseError> {
+    /// Parses every declaration, recovering after each syntax error.
+    ///
+    /// Returns the statements that parsed cleanly together with every error
+    /// found; the program is only valid when the error list is empty.
+    pub fn parse(&mut self) -> (Vec<Stmt>, Vec<ParseError>) {
         let mut statements: Vec<Stmt> = vec![];
 
         while !self.is_at_end() {
-            match self.declaration() {
-                Ok(stmt) => statements.push(stmt),
-                Err(e) => {
-                    self.errors.push(e.clone());
-                    self.synchronize();
-                }
+            if let Some(stmt) = self.recovering_declaration() {
+                statements.push(stmt);
             }
         }
 
-        Ok(statements)
+        (statements, std::mem::take(&mut self.errors))
+    }
+
+    /// Parses `tokens` as a single expression, failing if anything but EOF
+    /// follows it.
+    pub fn parse_expression(&mut self) -> Result<Expr, ParseError> {
+        let expr = self.expression()?;
+        if !self.is_at_end() {
+            return Err(ParseError::UnexpectedToken(self.peek().clone(), self.peek().span));
+        }
+
+        match self.errors.first() {
+            Some(e) => Err(e.clone()),
+            None => Ok(expr),
+        }
     }
 
     fn declaration(&mut self) -> Result<Stmt, ParseError> {
         if self.match_tokens(&[CLASS]) {
             return self.class_declaration();
         }
-        if self.match_tokens(&[FN]) {
+        // `fn (...)` starts a function expression instead
+        if self.check(FN) && self.check_next(IDENTIFIER) {
+            self.advance();
             return self.function("function");
         }
         if self.match_tokens(&[VAR]) {
@@ -49,23 +69,29 @@ impl Parser {
     }
 
     fn class_declaration(&mut self) -> Result<Stmt, ParseError> {
-        let name = self.consume(IDENTIFIER, ParseError::ExpectClassName(self.peek().line))?;
+        let name = self.consume(IDENTIFIER, ParseError::ExpectClassName(self.peek().span))?;
 
         let mut super_class= None;
         if self.match_tokens(&[LESS]) {
-            self.consume(IDENTIFIER, ParseError::ExpectedSuperClassName(self.peek().line))?;
-            super_class = Some(Expr::Variable { id: Expr::new_id(), name: self.previous(), value: Box::new(Expr::Literal { id: Expr::new_id(), value: LoxValue::Nil })});
+            self.consume(IDENTIFIER, ParseError::ExpectedSuperClassName(self.peek().span))?;
+            super_class = Some(Expr::Variable { id: Expr::new_id(), name: self.previous() });
         }
 
-        self.consume(LEFT_BRACE, ParseError::ExpectedLeftBraceAfterClassBody(self.peek().line))?;
+        self.consume(LEFT_BRACE, ParseError::ExpectedLeftBraceAfterClassBody(self.peek().span))?;
        
         let mut methods = vec![];
 
+        self.block_depth += 1;
         while !self.check(RIGHT_BRACE) && !self.is_at_end() {
-            methods.push(self.function("method")?);
+            let start = self.current;
+            match self.function("method") {
+                Ok(method) => methods.push(method),
+                Err(e) => self.recover(e, start),
+            }
         }
-        
-        self.consume(RIGHT_BRACE, ParseError::ExpectedRightBraceAfterClassBody(self.peek().line))?;
+        self.block_depth -= 1;
+
+        self.consume(RIGHT_BRACE, ParseError::ExpectedRightBraceAfterClassBody(self.peek().span))?;
 
         Ok(Stmt::Class { name, methods, super_class })
     }
@@ -73,12 +99,13 @@ impl Parser {
     fn var_declaration(&mut self) -> Result<Stmt, ParseError> {
         let name = self.consume(
             IDENTIFIER,
-            ParseError::ExpectedVariableName(self.peek().line),
+            ParseError::ExpectedVariableName(self.peek().span),
         )?;
 
         let mut initializer = Expr::Literal {
             id: Expr::new_id(),
             value: LoxValue::Nil,
+            span: name.span,
         }; // ! ALL VARS NOT INITIALIZED ARE NULL
 
         if self.match_tokens(&[EQUAL]) {
@@ -87,7 +114,7 @@ impl Parser {
 
         self.consume(
             SEMICOLON,
-            ParseError::ExpectedVariableDeclaration(self.peek().line),
+            ParseError::ExpectedVariableDeclaration(self.after_previous()),
         )?;
 
         Ok(Stmt::Var {
@@ -112,41 +139,24 @@ impl Parser {
         
         let name = self.consume(
             IDENTIFIER,
-            ParseError::ExpectedIdentifier(self.peek().line, kind.to_string()),
+            ParseError::ExpectedIdentifier(self.peek().span, kind.to_string()),
         )?;
 
         self.consume(
             LEFT_PAREN,
             ParseError::ExpectedSomeTokenTypeAfterSomething(
                 LEFT_PAREN,
-                self.peek().line,
+                self.peek().span,
                 "function".to_string(),
             ),
         )?;
 
-        let mut params = vec![];
-
-        if !self.check(RIGHT_PAREN) {
-            loop {
-                if params.len() >= 255 {
-                    let _ = self.report_error(ParseError::TooManyArguments(self.peek().line));
-                }
-
-                params.push(self.consume(
-                    IDENTIFIER,
-                    ParseError::ExpectedParameterName(self.peek().line),
-                )?);
-
-                if !self.match_tokens(&[COMMA]) {
-                    break;
-                } // Continue if has commas
-            }
-        }
+        let params = self.parameters()?;
         self.consume(
             RIGHT_PAREN,
             ParseError::ExpectedSomeTokenTypeAfterSomething(
                 RIGHT_PAREN,
-                self.peek().line,
+                self.peek().span,
                 "function".to_string(),
             ),
         )?;
@@ -155,24 +165,70 @@ impl Parser {
             LEFT_BRACE,
             ParseError::ExpectedSomeTokenTypeAfterSomething(
                 LEFT_BRACE,
-                self.peek().line,
+                self.peek().span,
                 "function".to_string(),
             ),
         )?;
 
         let body = self.block()?;
-        return Ok(Stmt::Function {
+        Ok(Stmt::Function {
             token: name,
             params,
             body,
             public,
             is_static
-        });
+        })
+    }
+
+    // The parameters of a function, up to the closing `)`
+    fn parameters(&mut self) -> Result<Vec<Param>, ParseError> {
+        let mut params = vec![];
+
+        if !self.check(RIGHT_PAREN) {
+            loop {
+                if params.len() >= 255 {
+                    self.report_error(ParseError::TooManyArguments(self.peek().span));
+                }
+                if params.last().is_some_and(|param: &Param| param.rest) {
+                    self.report_error(ParseError::InvalidRestParameter(self.peek().span));
+                }
+
+                let rest = self.match_tokens(&[DOT_DOT_DOT]);
+                let name = self.consume(
+                    IDENTIFIER,
+                    ParseError::ExpectedParameterName(self.peek().span),
+                )?;
+
+                let default = if self.match_tokens(&[EQUAL]) {
+                    if rest {
+                        self.report_error(ParseError::InvalidRestParameter(self.previous().span));
+                    }
+                    Some(self.expression()?)
+                } else {
+                    // Arguments fill parameters left to right, so a required
+                    // one can't come after an optional one
+                    if !rest && params.iter().any(|param| param.default.is_some()) {
+                        self.report_error(ParseError::MissingParameterDefault(name.span));
+                    }
+                    None
+                };
+
+                params.push(Param { name, default, rest });
+
+                if !self.match_tokens(&[COMMA]) {
+                    break;
+                } // Continue if has commas
+            }
+        }
+        Ok(params)
     }
 
     fn statement(&mut self) -> Result<Stmt, ParseError> {
+        if self.check(IDENTIFIER) && self.check_next(COLON) {
+            return self.labeled_statement();
+        }
         if self.match_tokens(&[FOR]) {
-            return self.for_statement();
+            return self.for_statement(None);
         }
         if self.match_tokens(&[IF]) {
             return self.if_statement();
@@ -184,13 +240,13 @@ impl Parser {
             return self.return_statement()
         }
         if self.match_tokens(&[WHILE]) {
-            return self.while_statement();
+            return self.while_statement(None);
         }
         if self.match_tokens(&[LOOP]) {
-            return self.loop_statement();
+            return self.loop_statement(None);
         }
-        if self.match_tokens(&[BREAK]) {
-            return self.break_statement();
+        if self.match_tokens(&[BREAK, CONTINUE]) {
+            return self.jump_statement();
         }
 
         if self.match_tokens(&[LEFT_BRACE]) {
@@ -199,26 +255,43 @@ impl Parser {
             });
         }
 
-        return self.expression_statement();
+        self.expression_statement()
     }
 
     fn return_statement(&mut self) -> Result<Stmt, ParseError> {
         let keyword = self.previous();
-        let mut value = Expr::Literal { id: Expr::new_id(), value: LoxValue::Nil };
+        let mut value = Expr::Literal { id: Expr::new_id(), value: LoxValue::Nil, span: keyword.span };
         if !self.check(SEMICOLON) {
             value = self.expression()?;
         }
-        self.consume(SEMICOLON, ParseError::ExpectedSomeTokenTypeAfterSomething(SEMICOLON, self.peek().line, "return".to_string()))?;
+        self.consume(SEMICOLON, ParseError::ExpectedSomeTokenTypeAfterSomething(SEMICOLON, self.after_previous(), "return".to_string()))?;
         Ok(Stmt::Return { keyword, value })
     }
 
-    fn for_statement(&mut self) -> Result<Stmt, ParseError> {
+    // `name: while ...`, with the label and its colon next
+    fn labeled_statement(&mut self) -> Result<Stmt, ParseError> {
+        let label = self.advance();
+        self.advance();
+        if self.match_tokens(&[FOR]) {
+            return self.for_statement(Some(label));
+        }
+        if self.match_tokens(&[WHILE]) {
+            return self.while_statement(Some(label));
+        }
+        if self.match_tokens(&[LOOP]) {
+            return self.loop_statement(Some(label));
+        }
+        Err(ParseError::ExpectedLoopAfterLabel(self.peek().span))
+    }
+
+    fn for_statement(&mut self, label: Option<Token>) -> Result<Stmt, ParseError> {
+        let keyword = self.previous();
         // (
         self.consume(
             LEFT_PAREN,
             ParseError::ExpectedSomeTokenTypeAfterSomething(
                 LEFT_PAREN,
-                self.peek().line,
+                self.peek().span,
                 "For".to_string(),
             ),
         )?;
@@ -229,6 +302,7 @@ impl Parser {
                 expression: Expr::Literal {
                     id: Expr::new_id(),
                     value: LoxValue::Nil,
+                    span: self.previous().span,
                 },
             });
         } else if self.match_tokens(&[VAR]) {
@@ -250,46 +324,38 @@ impl Parser {
             SEMICOLON,
             ParseError::ExpectedSomeTokenTypeAfterSomething(
                 SEMICOLON,
-                self.peek().line,
+                self.after_previous(),
                 "For".to_string(),
             ),
         )?;
 
-        let increment;
+        let mut increment = None;
         if !self.check(RIGHT_PAREN) {
             increment = Some(self.expression()?);
-        } else {
-            // Incremento vacío
-            increment = Some(Expr::Literal {
-                id: Expr::new_id(),
-                value: LoxValue::Nil,
-            });
         }
 
         self.consume(
             RIGHT_PAREN,
             ParseError::ExpectedSomeTokenTypeAfterSomething(
                 RIGHT_PAREN,
-                self.peek().line,
+                self.peek().span,
                 "For".to_string(),
             ),
         )?;
 
-        let mut body = self.statement()?;
-
-        if let Some(inc) = increment {
-            body = Stmt::Block {
-                statements: vec![body, Stmt::Expression { expression: inc }],
-            };
-        }
+        let body = self.statement()?;
 
-        body = Stmt::While {
+        // The increment stays out of the body so `continue` still runs it
+        let mut body = Stmt::While {
             condition: condition.unwrap_or(Expr::Literal {
                 id: Expr::new_id(),
                 value: LoxValue::Boolean(true),
+                span: keyword.span,
             }),
             body: Box::new(body),
             else_branch: None,
+            increment: increment.map(Box::new),
+            label,
         };
 
         if let Some(ini) = initializer {
@@ -301,12 +367,12 @@ impl Parser {
         Ok(body)
     }
 
-    fn while_statement(&mut self) -> Result<Stmt, ParseError> {
+    fn while_statement(&mut self, label: Option<Token>) -> Result<Stmt, ParseError> {
         self.consume(
             LEFT_PAREN,
             ParseError::ExpectedSomeTokenTypeAfterSomething(
                 LEFT_PAREN,
-                self.peek().line,
+                self.peek().span,
                 "While".to_string(),
             ),
         )?;
@@ -317,7 +383,7 @@ impl Parser {
             RIGHT_PAREN,
             ParseError::ExpectedSomeTokenTypeAfterSomething(
                 RIGHT_PAREN,
-                self.peek().line,
+                self.peek().span,
                 "While".to_string(),
             ),
         )?;
@@ -330,11 +396,13 @@ impl Parser {
             else_branch = Some(Box::new(self.statement()?));
         }
 
-        return Ok(Stmt::While {
+        Ok(Stmt::While {
             condition,
             body: Box::new(body),
             else_branch,
-        });
+            increment: None,
+            label,
+        })
     }
 
     fn if_statement(&mut self) -> Result<Stmt, ParseError> {
@@ -342,7 +410,7 @@ impl Parser {
             LEFT_PAREN,
             ParseError::ExpectedSomeTokenTypeAfterSomething(
                 LEFT_PAREN,
-                self.peek().line,
+                self.peek().span,
                 "If".to_string(),
             ),
         )?;
@@ -353,7 +421,7 @@ impl Parser {
             RIGHT_PAREN,
             ParseError::ExpectedSomeTokenTypeAfterSomething(
                 RIGHT_PAREN,
-                self.peek().line,
+                self.peek().span,
                 "If".to_string(),
             ),
         )?;
@@ -365,50 +433,61 @@ impl Parser {
             else_branch = Some(Box::new(self.statement()?));
         }
 
-        return Ok(Stmt::If {
+        Ok(Stmt::If {
             condition: *Box::new(condition),
             then_branch: Box::new(then_branch),
             else_branch,
-        });
+        })
     }
 
-    fn loop_statement(&mut self) -> Result<Stmt, ParseError> {
+    fn loop_statement(&mut self, label: Option<Token>) -> Result<Stmt, ParseError> {
         let body = self.statement()?;
 
         Ok(Stmt::Loop {
             body: Box::new(body),
+            label,
         })
     }
 
-    fn break_statement(&mut self) -> Result<Stmt, ParseError> {
+    // `break` or `continue`, with an optional label
+    fn jump_statement(&mut self) -> Result<Stmt, ParseError> {
+        let keyword = self.previous();
+        let label = if self.check(IDENTIFIER) { Some(self.advance()) } else { None };
         self.consume(
             SEMICOLON,
             ParseError::ExpectedSomeTokenTypeAfterSomething(
                 SEMICOLON,
-                self.peek().line,
-                "break".to_string(),
+                self.after_previous(),
+                keyword.lexeme.clone(),
             ),
         )?;
-        Ok(Stmt::Break {})
+        Ok(match keyword.t_type {
+            BREAK => Stmt::Break { keyword, label },
+            _ => Stmt::Continue { keyword, label },
+        })
     }
 
     fn block(&mut self) -> Result<Vec<Stmt>, ParseError> {
         let mut statements = Vec::new();
+        self.block_depth += 1;
         while !self.check(RIGHT_BRACE) && !self.is_at_end() {
-            statements.push(self.declaration()?);
+            if let Some(stmt) = self.recovering_declaration() {
+                statements.push(stmt);
+            }
         }
+        self.block_depth -= 1;
         self.consume(
             RIGHT_BRACE,
-            ParseError::ExpectedRightBraceAfterBlock(self.peek().line),
+            ParseError::ExpectedRightBraceAfterBlock(self.peek().span),
         )?;
-        return Ok(statements);
+        Ok(statements)
     }
 
     fn print_statement(&mut self) -> Result<Stmt, ParseError> {
         let value: Expr = self.expression()?;
         self.consume(
             SEMICOLON,
-            ParseError::EspectSemicolonAfterValue(self.peek().line),
+            ParseError::EspectSemicolonAfterValue(self.after_previous()),
         )?;
         Ok(Stmt::Print {
             expression: *Box::new(value),
@@ -422,7 +501,7 @@ impl Parser {
     fn assignment(&mut self) -> Result<Expr, ParseError> {
         let expr = self.or()?;
         if self.match_tokens(&[EQUAL]) {
-            let _equals = self.previous();
+            let equals = self.previous();
             let value = self.assignment()?;
 
             match expr {
@@ -436,15 +515,47 @@ impl Parser {
                 Expr::Get { object, name } => {
                     return Ok(Expr::Set { object, name, value: Box::new(value) })
                 }
-                _ => Color::ecprintln(
-                    &ParseError::InvalidAssignmentTarget(self.current).to_string(),
-                    Color::Red,
-                ),
+                Expr::Index { object, bracket, index, .. } => {
+                    return Ok(Expr::SetIndex { id: Expr::new_id(), object, bracket, index, value: Box::new(value) })
+                }
+                _ => self.report_error(ParseError::InvalidAssignmentTarget(equals.span)),
             }
         }
+        if self.match_tokens(&[PLUS_EQUAL, MINUS_EQUAL, STAR_EQUAL, SLASH_EQUAL, PERCENT_EQUAL]) {
+            let operator = self.previous();
+            let value = self.assignment()?;
+            return Ok(self.update(expr, operator, value, false));
+        }
         Ok(expr)
     }
 
+    // `target op= value`, reporting targets that can't be assigned to but
+    // keeping the update so parsing goes on
+    fn update(&mut self, target: Expr, operator: Token, value: Expr, postfix: bool) -> Expr {
+        if !matches!(target, Expr::Variable { .. } | Expr::Get { .. } | Expr::Index { .. }) {
+            self.report_error(ParseError::InvalidAssignmentTarget(operator.span));
+        }
+        let t_type = match operator.t_type {
+            PLUS_EQUAL | PLUS_PLUS => PLUS,
+            MINUS_EQUAL | MINUS_MINUS => MINUS,
+            STAR_EQUAL => STAR,
+            SLASH_EQUAL => SLASH,
+            _ => PERCENT,
+        };
+        Expr::Update {
+            id: Expr::new_id(),
+            target: Box::new(target),
+            operator: Token { t_type, ..operator },
+            value: Box::new(value),
+            postfix,
+        }
+    }
+
+    // `++`/`--` add or take 1
+    fn one(operator: &Token) -> Expr {
+        Expr::Literal { id: Expr::new_id(), value: LoxValue::Integer(1), span: operator.span }
+    }
+
     fn or(&mut self) -> Result<Expr, ParseError> {
         let mut expr = self.and()?;
         while self.match_tokens(&[OR]) {
@@ -479,11 +590,11 @@ impl Parser {
         let expr: Expr = self.expression()?;
         self.consume(
             SEMICOLON,
-            ParseError::ExpectedSomeTokenTypeAfterSomething(SEMICOLON, self.peek().line, "expression".to_string()),
+            ParseError::ExpectedSomeTokenTypeAfterSomething(SEMICOLON, self.after_previous(), "expression".to_string()),
         )?;
-        return Ok(Stmt::Expression {
+        Ok(Stmt::Expression {
             expression: *Box::new(expr),
-        });
+        })
     }
 
     fn ternary(&mut self) -> Result<Expr, ParseError> {
@@ -495,7 +606,7 @@ impl Parser {
 
             self.consume(
                 COLON,
-                ParseError::ExpectedTernaryBranch(self.peek().line, 0),
+                ParseError::ExpectedTernaryBranch(self.peek().span, 0),
             )?;
 
             let else_branch = self.expression()?;
@@ -512,20 +623,7 @@ impl Parser {
     }
 
     fn equality(&mut self) -> Result<Expr, ParseError> {
-        let mut expr: Expr = self.comparision()?;
-
-        while self.match_tokens(&[BANG_EQUAL, EQUAL_EQUAL]) {
-            let operator = self.previous();
-            let right = self.comparision()?;
-            expr = Expr::Binary {
-                id: Expr::new_id(),
-                left: Box::new(expr),
-                operator: operator.clone(),
-                right: Box::new(right),
-            };
-        }
-
-        Ok(expr)
+        self.left_associative(&[BANG_EQUAL, EQUAL_EQUAL], Self::comparision)
     }
 
     /*fn comma(&mut self) -> Result<Expr, ParseError> {
@@ -541,16 +639,22 @@ impl Parser {
         Ok(expr)
     }*/
 
-    fn comparision(&mut self) -> Result<Expr, ParseError> {
-        let mut expr = self.term()?;
+    // One level of binary operators that group to the left, like
+    // `a - b - c`, over operands parsed by `operand`
+    fn left_associative(
+        &mut self,
+        operators: &[TokenType],
+        operand: fn(&mut Self) -> Result<Expr, ParseError>,
+    ) -> Result<Expr, ParseError> {
+        let mut expr = operand(self)?;
 
-        while self.match_tokens(&[GREATER, GREATER_EQUAL, LESS, LESS_EQUAL]) {
+        while self.match_tokens(operators) {
             let operator = self.previous();
-            let right = self.term()?;
+            let right = operand(self)?;
             expr = Expr::Binary {
                 id: Expr::new_id(),
                 left: Box::new(expr),
-                operator: operator.clone(),
+                operator,
                 right: Box::new(right),
             };
         }
@@ -558,52 +662,85 @@ impl Parser {
         Ok(expr)
     }
 
+    fn comparision(&mut self) -> Result<Expr, ParseError> {
+        self.left_associative(&[GREATER, GREATER_EQUAL, LESS, LESS_EQUAL], Self::bit_or)
+    }
+
+    // Bitwise operators bind tighter than comparisons, so `a & 1 == 0`
+    // tests the masked bits
+    fn bit_or(&mut self) -> Result<Expr, ParseError> {
+        self.left_associative(&[PIPE], Self::bit_xor)
+    }
+
+    fn bit_xor(&mut self) -> Result<Expr, ParseError> {
+        self.left_associative(&[CARET], Self::bit_and)
+    }
+
+    fn bit_and(&mut self) -> Result<Expr, ParseError> {
+        self.left_associative(&[AMPERSAND], Self::shift)
+    }
+
+    fn shift(&mut self) -> Result<Expr, ParseError> {
+        self.left_associative(&[LESS_LESS, GREATER_GREATER], Self::term)
+    }
+
     fn term(&mut self) -> Result<Expr, ParseError> {
-        let mut expr = self.factor()?;
+        self.left_associative(&[PLUS, MINUS], Self::factor)
+    }
 
-        while self.match_tokens(&[PLUS, MINUS]) {
+    fn factor(&mut self) -> Result<Expr, ParseError> {
+        self.left_associative(&[STAR, SLASH, TILDE_SLASH, PERCENT], Self::unary)
+    }
+
+    fn unary(&mut self) -> Result<Expr, ParseError> {
+        if self.match_tokens(&[PLUS_PLUS, MINUS_MINUS]) {
             let operator = self.previous();
-            let right = self.factor()?;
-            expr = Expr::Binary {
+            let target = self.unary()?;
+            let one = Self::one(&operator);
+            return Ok(self.update(target, operator, one, false));
+        }
+        if self.match_tokens(&[BANG, MINUS, TILDE]) {
+            let operator = self.previous();
+            let right = self.unary()?;
+            return Ok(Expr::Unary {
                 id: Expr::new_id(),
-                left: Box::new(expr),
                 operator: operator.clone(),
                 right: Box::new(right),
-            };
+            });
         }
 
-        Ok(expr)
+        self.power()
     }
 
-    fn factor(&mut self) -> Result<Expr, ParseError> {
-        let mut expr = self.unary()?;
+    // `**` groups to the right and binds tighter than a sign on its left,
+    // so `-2 ** 2` is `-4` and `2 ** -1` still parses
+    fn power(&mut self) -> Result<Expr, ParseError> {
+        let expr = self.postfix()?;
 
-        while self.match_tokens(&[STAR, SLASH]) {
+        if self.match_tokens(&[STAR_STAR]) {
             let operator = self.previous();
             let right = self.unary()?;
-            expr = Expr::Binary {
+            return Ok(Expr::Binary {
                 id: Expr::new_id(),
                 left: Box::new(expr),
-                operator: operator.clone(),
+                operator,
                 right: Box::new(right),
-            };
+            });
         }
 
         Ok(expr)
     }
 
-    fn unary(&mut self) -> Result<Expr, ParseError> {
-        if self.match_tokens(&[BANG, MINUS]) {
+    fn postfix(&mut self) -> Result<Expr, ParseError> {
+        let expr = self.call()?;
+
+        if self.match_tokens(&[PLUS_PLUS, MINUS_MINUS]) {
             let operator = self.previous();
-            let right = self.unary()?;
-            return Ok(Expr::Unary {
-                id: Expr::new_id(),
-                operator: operator.clone(),
-                right: Box::new(right),
-            });
+            let one = Self::one(&operator);
+            return Ok(self.update(expr, operator, one, true));
         }
 
-        self.call()
+        Ok(expr)
     }
 
     fn call(&mut self) -> Result<Expr, ParseError> {
@@ -612,8 +749,10 @@ impl Parser {
             if self.match_tokens(&[LEFT_PAREN]) {
                 expr = self.finish_call(expr)?;
             } else if self.match_tokens(&[DOT]) {
-                let name = self.consume(IDENTIFIER, ParseError::ExpectedPropertyNameAfterDot(self.peek().line))?;
-                expr = Expr::Get { object: Box::new(expr), name: name }
+                let name = self.consume(IDENTIFIER, ParseError::ExpectedPropertyNameAfterDot(self.peek().span))?;
+                expr = Expr::Get { object: Box::new(expr), name }
+            } else if self.match_tokens(&[LEFT_BRACKET]) {
+                expr = self.finish_index(expr)?;
             } else {
                 break;
             }
@@ -626,10 +765,7 @@ impl Parser {
         if !self.check(RIGHT_PAREN) {
             loop {
                 if arguments.len() >= 255 {
-                    Color::ecprintln(
-                        &ParseError::TooManyArguments(self.peek().line).to_string(),
-                        Color::Red,
-                    );
+                    self.report_error(ParseError::TooManyArguments(self.peek().span));
                 }
                 arguments.push(self.expression()?);
                 if !self.match_tokens(&[COMMA]) {
@@ -641,17 +777,34 @@ impl Parser {
             RIGHT_PAREN,
             ParseError::ExpectedSomeTokenTypeAfterSomething(
                 RIGHT_PAREN,
-                self.peek().line,
+                self.peek().span,
                 "call function".to_string(),
             ),
         )?;
 
-        return Ok(Expr::Call {
+        Ok(Expr::Call {
             id: Expr::new_id(),
             callee: Box::new(callee),
             paren,
             arguments,
-        });
+        })
+    }
+
+    // `[index]` or `[start:end]`, after the `[`
+    fn finish_index(&mut self, object: Expr) -> Result<Expr, ParseError> {
+        let start = if self.check(COLON) { None } else { Some(Box::new(self.expression()?)) };
+        let is_slice = self.match_tokens(&[COLON]);
+        let end = if is_slice && !self.check(RIGHT_BRACKET) { Some(Box::new(self.expression()?)) } else { None };
+        let bracket = self.consume(
+            RIGHT_BRACKET,
+            ParseError::ExpectedSomeTokenTypeAfterSomething(RIGHT_BRACKET, self.peek().span, "index".to_string()),
+        )?;
+
+        let object = Box::new(object);
+        Ok(match start {
+            Some(index) if !is_slice => Expr::Index { id: Expr::new_id(), object, bracket, index },
+            start => Expr::Slice { id: Expr::new_id(), object, bracket, start, end },
+        })
     }
 
     fn primary(&mut self) -> Result<Expr, ParseError> {
@@ -659,32 +812,39 @@ impl Parser {
             return Ok(Expr::Literal {
                 id: Expr::new_id(),
                 value: LoxValue::Boolean(false),
+                span: self.previous().span,
             });
         }
         if self.match_tokens(&[TRUE]) {
             return Ok(Expr::Literal {
                 id: Expr::new_id(),
                 value: LoxValue::Boolean(true),
+                span: self.previous().span,
             });
         }
         if self.match_tokens(&[NIL]) {
             return Ok(Expr::Literal {
                 id: Expr::new_id(),
                 value: LoxValue::Nil,
+                span: self.previous().span,
             });
         }
+        if self.match_tokens(&[INTERPOLATION]) {
+            return self.interpolation();
+        }
         if self.match_tokens(&[NUMBER, STRING]) {
             return Ok(Expr::Literal {
                 id: Expr::new_id(),
                 value: self.previous().literal.clone(),
+                span: self.previous().span,
             });
         }
 
         if self.match_tokens(&[SUPER]) {
             let keyword = self.previous();
-            self.consume(DOT, ParseError::ExpectDotAfterSuper(self.peek().line))?;
-            let method = self.consume(IDENTIFIER, ParseError::ExpectSuperClassMethodName(self.peek().line))?;
-            return Ok(Expr::Super { keyword, method })
+            self.consume(DOT, ParseError::ExpectDotAfterSuper(self.peek().span))?;
+            let method = self.consume(IDENTIFIER, ParseError::ExpectSuperClassMethodName(self.peek().span))?;
+            return Ok(Expr::Super { id: Expr::new_id(), keyword, method })
         }
 
         if self.match_tokens(&[THIS]) {
@@ -697,14 +857,57 @@ impl Parser {
             return Ok(Expr::Variable {
                 id: Expr::new_id(),
                 name: self.previous(),
-                value: Box::new(Expr::Literal { id: Expr::new_id(), value: LoxValue::Nil }) 
             });
         }
+        if self.match_tokens(&[FN]) {
+            return self.lambda();
+        }
+        if self.match_tokens(&[LEFT_BRACKET]) {
+            let bracket = self.previous();
+            let mut elements = vec![];
+            // A trailing comma is fine
+            while !self.check(RIGHT_BRACKET) {
+                elements.push(self.expression()?);
+                if !self.match_tokens(&[COMMA]) {
+                    break;
+                }
+            }
+            self.consume(
+                RIGHT_BRACKET,
+                ParseError::ExpectedSomeTokenTypeAfterSomething(RIGHT_BRACKET, self.peek().span, "list".to_string()),
+            )?;
+            return Ok(Expr::List { id: Expr::new_id(), bracket, elements });
+        }
+        // Statements starting with `{` are blocks, so maps only show up here
+        if self.match_tokens(&[LEFT_BRACE]) {
+            let brace = self.previous();
+            let mut entries = vec![];
+            while !self.check(RIGHT_BRACE) {
+                let key = self.expression()?;
+                self.consume(
+                    COLON,
+                    ParseError::ExpectedSomeTokenTypeAfterSomething(COLON, self.peek().span, "map key".to_string()),
+                )?;
+                entries.push((key, self.expression()?));
+                if !self.match_tokens(&[COMMA]) {
+                    break;
+                }
+            }
+            self.consume(
+                RIGHT_BRACE,
+                ParseError::ExpectedSomeTokenTypeAfterSomething(RIGHT_BRACE, self.peek().span, "map".to_string()),
+            )?;
+            return Ok(Expr::Map { id: Expr::new_id(), brace, entries });
+        }
+        if self.check(LEFT_PAREN) && self.is_arrow_function() {
+            self.advance();
+            return self.arrow_function();
+        }
         if self.match_tokens(&[LEFT_PAREN]) {
             let expr = self.expression()?;
             self.consume(
                 RIGHT_PAREN,
-                ParseError::ExpectedRightParen(self.peek().line),
+                ParseError::ExpectedRightParen(self.peek().span),
             )?;
             return Ok(Expr::Grouping {
                 id: Expr::new_id(),
@@ -712,13 +915,127 @@ impl Parser {
             });
         }
 
-        Err(self.report_error(ParseError::InvalidExpression(
+        Err(ParseError::InvalidExpression(
             format!(
                 "Expected a valid expression, found: {:?}",
                 self.peek().t_type
             ),
-            self.peek().line,
-        )))
+            self.peek().span,
+        ))
+    }
+
+    // `"a ${x} b"`, scanned as INTERPOLATION("a ") x STRING(" b"), after
+    // its first part. Becomes `"a " + ${x} + " b"`, with `${x}` an
+    // `Interpolated` node so that no variable named `to_string` is involved.
+    fn interpolation(&mut self) -> Result<Expr, ParseError> {
+        let mut expr = Self::string_part(&self.previous());
+        loop {
+            if self.is_string_continuation() {
+                return Err(ParseError::InvalidExpression(
+                    "Expected an expression inside '${}'".to_string(),
+                    self.peek().span,
+                ));
+            }
+            let value = self.expression()?;
+            let span = value.span();
+            let text = Expr::Interpolated { id: Expr::new_id(), expression: Box::new(value) };
+            expr = Self::concat(expr, text, span);
+
+            if !self.is_string_continuation() {
+                return Err(ParseError::ExpectedSomeTokenTypeAfterSomething(
+                    RIGHT_BRACE,
+                    self.peek().span,
+                    "interpolated expression".to_string(),
+                ));
+            }
+            let part = self.advance();
+            expr = Self::concat(expr, Self::string_part(&part), part.span);
+            if part.t_type == STRING {
+                return Ok(expr);
+            }
+        }
+    }
+
+    // The rest of a string after a `${ }`, which the scanner starts at the `}`
+    fn is_string_continuation(&self) -> bool {
+        (self.check(STRING) || self.check(INTERPOLATION)) && self.peek().lexeme.starts_with('}')
+    }
+
+    fn string_part(part: &Token) -> Expr {
+        Expr::Literal {
+            id: Expr::new_id(),
+            value: part.literal.clone(),
+            span: part.span,
+        }
+    }
+
+    fn concat(left: Expr, right: Expr, span: Span) -> Expr {
+        Expr::Binary {
+            id: Expr::new_id(),
+            left: Box::new(left),
+            operator: Token::new(PLUS, "+".to_string(), LoxValue::Nil, span),
+            right: Box::new(right),
+        }
+    }
+
+    // `fn (params) { body }`, after the `fn`
+    fn lambda(&mut self) -> Result<Expr, ParseError> {
+        let keyword = self.previous();
+        self.consume(
+            LEFT_PAREN,
+            ParseError::ExpectedSomeTokenTypeAfterSomething(LEFT_PAREN, self.peek().span, "fn".to_string()),
+        )?;
+        let params = self.parameters()?;
+        self.consume(
+            RIGHT_PAREN,
+            ParseError::ExpectedSomeTokenTypeAfterSomething(RIGHT_PAREN, self.peek().span, "function".to_string()),
+        )?;
+        self.consume(
+            LEFT_BRACE,
+            ParseError::ExpectedSomeTokenTypeAfterSomething(LEFT_BRACE, self.peek().span, "function".to_string()),
+        )?;
+        let body = self.block()?;
+        Ok(Expr::Lambda { id: Expr::new_id(), keyword, params, body })
+    }
+
+    // `(params) => expression` or `(params) => { body }`, after the `(`
+    fn arrow_function(&mut self) -> Result<Expr, ParseError> {
+        let params = self.parameters()?;
+        self.consume(
+            RIGHT_PAREN,
+            ParseError::ExpectedSomeTokenTypeAfterSomething(RIGHT_PAREN, self.peek().span, "function".to_string()),
+        )?;
+        let keyword = self.consume(
+            ARROW,
+            ParseError::ExpectedSomeTokenTypeAfterSomething(ARROW, self.peek().span, "parameters".to_string()),
+        )?;
+        let body = if self.match_tokens(&[LEFT_BRACE]) {
+            self.block()?
+        } else {
+            let value = self.expression()?;
+            vec![Stmt::Return { keyword: keyword.clone(), value }]
+        };
+        Ok(Expr::Lambda { id: Expr::new_id(), keyword, params, body })
+    }
+
+    // Whether the `(` at the current token closes right before a `=>`,
+    // which makes it a parameter list rather than a grouping
+    fn is_arrow_function(&self) -> bool {
+        let mut depth = 0;
+        for (offset, token) in self.tokens[self.current..].iter().enumerate() {
+            match token.t_type {
+                LEFT_PAREN => depth += 1,
+                RIGHT_PAREN => {
+                    depth -= 1;
+                    if depth == 0 {
+                        return matches!(self.tokens.get(self.current + offset + 1), Some(next) if next.t_type == ARROW);
+                    }
+                }
+                EOF => return false,
+                _ => {}
+            }
+        }
+        false
     }
 
     fn match_tokens(&mut self, types: &[TokenType]) -> bool {
@@ -738,6 +1055,10 @@ impl Parser {
         self.peek().t_type == ttype
     }
 
+    fn check_next(&self, ttype: TokenType) -> bool {
+        matches!(self.tokens.get(self.current + 1), Some(token) if token.t_type == ttype)
+    }
+
     fn advance(&mut self) -> Token {
         if !self.is_at_end() {
             self.current += 1;
@@ -757,19 +1078,71 @@ impl Parser {
         self.tokens.get(self.current - 1).unwrap().clone()
     }
 
-    fn report_error(&mut self, error: ParseError) -> ParseError {
-        self.errors.push(error.clone());
-        Color::ecprintln(&format!("{}", error), Color::Red);
-        error
+    // The position right after the previous token, where a missing `;` belongs
+    fn after_previous(&self) -> Span {
+        let previous = self.previous();
+        let span = previous.span;
+        // Multi-line strings end on a later line than they start
+        let (line, column) = match previous.lexeme.rsplit_once('\n') {
+            Some((_, last)) => (span.line + previous.lexeme.matches('\n').count(), last.chars().count() + 1),
+            None => (span.line, span.column + previous.lexeme.chars().count()),
+        };
+        Span::new(span.file_id, span.end, span.end + 1, line, column)
+    }
+
+    fn report_error(&mut self, error: ParseError) {
+        self.errors.push(error);
+    }
+
+    // A declaration, or None after recording its error and skipping to the
+    // next statement boundary
+    fn recovering_declaration(&mut self) -> Option<Stmt> {
+        let start = self.current;
+        match self.declaration() {
+            Ok(stmt) => Some(stmt),
+            Err(e) => {
+                self.recover(e, start);
+                None
+            }
+        }
     }
 
+    fn recover(&mut self, error: ParseError, start: usize) {
+        self.report_error(error);
+        // Failing on the very first token must still make progress
+        if self.current == start {
+            self.advance();
+        }
+        self.synchronize();
+    }
+
+    /// Skips tokens until the start of the next statement: after a `;` or a
+    /// `}`, before a statement keyword, or before the `}` closing the
+    /// enclosing block. Braces opened while skipping are skipped whole, so a
+    /// broken declaration doesn't leak its body into the outer scope.
     fn synchronize(&mut self) {
-        self.advance();
+        if matches!(self.previous().t_type, SEMICOLON | RIGHT_BRACE) {
+            return;
+        }
+
+        let mut depth = 0;
         while !self.is_at_end() {
+            if depth == 0 {
+                match self.peek().t_type {
+                    RIGHT_BRACE if self.block_depth > 0 => return,
+                    CLASS | FN | VAR | FOR | IF | WHILE | LOOP | BREAK | CONTINUE | PRINT | RETURN => return,
+                    _ => {}
+                }
+            }
             match self.peek().t_type {
-                CLASS | FN | VAR | FOR | IF | WHILE | PRINT | RETURN => return,
-                _ => self.advance(),
-            };
+                LEFT_BRACE => depth += 1,
+                RIGHT_BRACE if depth > 0 => depth -= 1,
+                _ => {}
+            }
+            self.advance();
+            if depth == 0 && matches!(self.previous().t_type, SEMICOLON | RIGHT_BRACE) {
+                return;
+            }
         }
     }
 
@@ -777,7 +1150,7 @@ impl Parser {
         if self.check(ttype) {
             Ok(self.advance())
         } else {
-            Err(self.report_error(message))
+            Err(message)
         }
     }
 }
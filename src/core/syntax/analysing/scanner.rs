@@ -1,30 +1,53 @@
 use std::collections::HashMap;
 
+use crate::core::error_types::scanner_error::ScannerError;
 use crate::core::syntax::components::expression::LoxValue;
+use crate::core::syntax::span::Span;
 use crate::core::syntax::token::Token;
-use crate::utils::colors::Color;
 
 use crate::core::syntax::token_type::TokenType::{self, *};
 
 pub struct Scanner {
     source: String,
     tokens: Vec<Token>,
+    errors: Vec<ScannerError>,
 
-    // Scanning control
+    // Scanning control (byte offsets into `source`)
+    file_id: usize,
     start: usize,
     current: usize,
     line: usize,
+    line_start: usize,
+
+    // Position of `start`
+    start_line: usize,
+    start_column: usize,
 
     keywords: HashMap<String, TokenType>,
+
+    // Strings waiting for the `}` that ends one of their `${`, innermost
+    // last
+    interpolations: Vec<Interpolation>,
+}
+
+struct Interpolation {
+    // Braces opened inside the `${ }` and not yet closed
+    depth: usize,
+    // The opening quote of the string, for unterminated string errors
+    opening: Span,
 }
 
 impl Scanner {
     pub fn new(source: String) -> Self {
+        Self::for_file(source, 0)
+    }
+
+    /// Like `new`, tagging every token span with `file_id`.
+    pub fn for_file(source: String, file_id: usize) -> Self {
         let mut keywords = HashMap::new();
         keywords.insert(AND.to_string(), AND);
         keywords.insert(CLASS.to_string(), CLASS);
         keywords.insert(ELSE.to_string(), ELSE);
-        keywords.insert(ELSE.to_string(), FALSE);
         keywords.insert(FOR.to_string(), FOR);
         keywords.insert(PUB.to_string(), PUB);
         keywords.insert(STATIC.to_string(), STATIC);
@@ -41,34 +64,51 @@ impl Scanner {
         keywords.insert(WHILE.to_string(), WHILE);
         keywords.insert(LOOP.to_string(), LOOP);
         keywords.insert(BREAK.to_string(), BREAK);
+        keywords.insert(CONTINUE.to_string(), CONTINUE);
         keywords.insert(RETURN.to_string(), RETURN);
 
         Self {
             source,
             tokens: Vec::new(),
+            errors: Vec::new(),
+            file_id,
             start: 0,
             current: 0,
             line: 1,
+            line_start: 0,
+            start_line: 1,
+            start_column: 1,
             keywords,
+            interpolations: Vec::new(),
         }
     }
 
     pub fn scan_tokens(&mut self) -> Vec<Token> {
         while !self.is_at_end() {
             // beginning of next lexeme
-            self.start = self.current;
+            self.mark_start();
             self.scan_token();
         }
+        if let Some(interpolation) = self.interpolations.first() {
+            self.error(ScannerError::UnterminatedString(interpolation.opening));
+            self.interpolations.clear();
+        }
+        self.mark_start();
         self.tokens.push(Token::from(
             EOF,
             "".to_string(),
             LoxValue::Nil,
-            self.line,
+            self.span(),
         ));
 
         // Return tokens
         self.tokens.clone()
     }
+
+    pub fn errors(&self) -> &[ScannerError] {
+        &self.errors
+    }
+
     fn is_at_end(&self) -> bool {
         self.current >= self.source.len()
     }
@@ -82,16 +122,83 @@ impl Scanner {
             // Normal lexemes
             '(' => self.add_token(LEFT_PAREN),
             ')' => self.add_token(RIGHT_PAREN),
-            '{' => self.add_token(LEFT_BRACE),
-            '}' => self.add_token(RIGHT_BRACE),
+            '{' => {
+                if let Some(interpolation) = self.interpolations.last_mut() {
+                    interpolation.depth += 1;
+                }
+                self.add_token(LEFT_BRACE)
+            }
+            '}' => match self.interpolations.last_mut() {
+                // Closes a `${`, so the string goes on
+                Some(interpolation) if interpolation.depth == 0 => {
+                    let opening = interpolation.opening;
+                    self.interpolations.pop();
+                    self.string(opening)
+                }
+                Some(interpolation) => {
+                    interpolation.depth -= 1;
+                    self.add_token(RIGHT_BRACE)
+                }
+                None => self.add_token(RIGHT_BRACE),
+            },
+            '[' => self.add_token(LEFT_BRACKET),
+            ']' => self.add_token(RIGHT_BRACKET),
             ',' => self.add_token(COMMA),
-            '.' => self.add_token(DOT),
-            '-' => self.add_token(MINUS),
-            '+' => self.add_token(PLUS),
+            '.' => {
+                let token_type = if self.peek() == '.' && self.peek_next() == '.' {
+                    self.advance();
+                    self.advance();
+                    DOT_DOT_DOT
+                } else {
+                    DOT
+                };
+                self.add_token(token_type);
+            }
+            '-' => {
+                let token_type = if self.char_match('=') {
+                    MINUS_EQUAL
+                } else if self.char_match('-') {
+                    MINUS_MINUS
+                } else {
+                    MINUS
+                };
+                self.add_token(token_type);
+            }
+            '+' => {
+                let token_type = if self.char_match('=') {
+                    PLUS_EQUAL
+                } else if self.char_match('+') {
+                    PLUS_PLUS
+                } else {
+                    PLUS
+                };
+                self.add_token(token_type);
+            }
             ';' => self.add_token(SEMICOLON),
             ':' => self.add_token(COLON),
             '?' => self.add_token(QUESTION_MARK),
-            '*' => self.add_token(STAR),
+            '*' => {
+                let token_type = if self.char_match('*') {
+                    STAR_STAR
+                } else if self.char_match('=') {
+                    STAR_EQUAL
+                } else {
+                    STAR
+                };
+                self.add_token(token_type);
+            }
+            '%' => {
+                let token_type = if self.char_match('=') { PERCENT_EQUAL } else { PERCENT };
+                self.add_token(token_type);
+            }
+            '&' => self.add_token(AMPERSAND),
+            '|' => self.add_token(PIPE),
+            '^' => self.add_token(CARET),
+            // `~/` divides rounding down, since `//` starts a comment
+            '~' => {
+                let token_type = if self.char_match('/') { TILDE_SLASH } else { TILDE };
+                self.add_token(token_type);
+            }
 
             // Operators
             // Combination
@@ -106,6 +213,8 @@ impl Scanner {
             '=' => {
                 let token_type = if self.char_match('=') {
                     EQUAL_EQUAL
+                } else if self.char_match('>') {
+                    ARROW
                 } else {
                     EQUAL
                 };
@@ -114,6 +223,8 @@ impl Scanner {
             '<' => {
                 let token_type = if self.char_match('=') {
                     LESS_EQUAL
+                } else if self.char_match('<') {
+                    LESS_LESS
                 } else {
                     LESS
                 };
@@ -122,6 +233,8 @@ impl Scanner {
             '>' => {
                 let token_type = if self.char_match('=') {
                     GREATER_EQUAL
+                } else if self.char_match('>') {
+                    GREATER_GREATER
                 } else {
                     GREATER
                 };
@@ -138,19 +251,21 @@ impl Scanner {
                 if self.char_match('*') {
                     // Code for handle multiline comment
                     while !self.is_at_end() {
-                        if self.peek() == '\n' {
-                            self.line += 1;
-                        } else if self.peek() == '*' && self.peek_next() == '/' {
-                            self.current += 2; // Skip two characters if they form the closing of a multiline comment
+                        if self.peek() == '*' && self.peek_next() == '/' {
+                            // Skip the two characters closing the multiline comment
+                            self.advance();
+                            self.advance();
                             return; // Finish
                         }
                         self.advance();
                     }
-                    self.error("[SCANNER][ERROR]: Unfinished multiline comment.");
+                    self.error(ScannerError::UnfinishedMultilineComment(self.opening_span(2)));
                 } else if self.char_match('/') {
                     while self.peek() != '\n' && !self.is_at_end() {
                         self.advance();
                     }
+                } else if self.char_match('=') {
+                    self.add_token(SLASH_EQUAL);
                 } else {
                     self.add_token(SLASH);
                 }
@@ -161,14 +276,20 @@ impl Scanner {
             '\r' => {}
             '\t' => {}
 
-            // Newline
-            '\n' => {
-                self.line += 1;
-            }
+            // Newline (line tracking happens in `advance`)
+            '\n' => {}
 
             // Longer Lexemes
             // Literals
-            '"' => self.string(),
+            '"' => {
+                if self.peek() == '"' && self.peek_next() == '"' {
+                    self.advance();
+                    self.advance();
+                    self.raw_string();
+                } else {
+                    self.string(self.opening_span(1));
+                }
+            }
 
             // Default
             _ => {
@@ -178,7 +299,7 @@ impl Scanner {
                 } else if Self::is_alpha(c) {
                     self.identifier()
                 } else {
-                    self.error("[SCANNER][ERROR]: Unexpected character.");
+                    self.error(ScannerError::UnexpectedCharacter(c, self.span()));
                 }
             }
         }
@@ -193,50 +314,182 @@ impl Scanner {
         self.add_token(t_type.clone())
     }
 
+    // After the first digit. A fraction or an exponent makes a float,
+    // anything else an integer, which may also be written in hex, binary
+    // or octal after `0x`, `0b` or `0o`
     fn number(&mut self) {
-        while Self::is_digit(self.peek()) {
+        let radix = match (&self.source[self.start..self.current], self.peek()) {
+            ("0", 'x' | 'X') => 16,
+            ("0", 'b' | 'B') => 2,
+            ("0", 'o' | 'O') => 8,
+            _ => 10,
+        };
+        let literal = if radix == 10 {
+            self.decimal()
+        } else {
+            self.advance();
+            let digits_start = self.current;
+            self.digits(digits_start, radix).map(|digits| (digits, false))
+        };
+
+        // Letters or digits right after a number, like `12px` or `0b102`,
+        // make the whole thing invalid rather than two tokens
+        let trailing = Self::is_alpha_numeric(self.peek());
+        while Self::is_alpha_numeric(self.peek()) {
             self.advance();
         }
-        // Look for a fractional part.
+        let Some((literal, is_float)) = literal.filter(|_| !trailing) else {
+            self.error(ScannerError::InvalidNumber(self.span()));
+            return;
+        };
+
+        let value = if is_float {
+            LoxValue::Number(literal.parse().expect("[SCANNER]: FloatError"))
+        } else {
+            match i64::from_str_radix(&literal, radix) {
+                Ok(n) => LoxValue::Integer(n),
+                Err(_) => return self.error(ScannerError::IntegerTooLarge(self.span())),
+            }
+        };
+        self.add_token_lit(NUMBER, value)
+    }
+
+    // The decimal digits, fraction and exponent of a number, without
+    // separators, and whether it has either of the last two
+    fn decimal(&mut self) -> Option<(String, bool)> {
+        let mut literal = self.digits(self.start, 10)?;
+        let mut is_float = false;
         if self.peek() == '.' && Self::is_digit(self.peek_next()) {
             self.advance();
-            while Self::is_digit(self.peek()) {
-                self.advance();
-            }
+            let fraction_start = self.current;
+            literal = format!("{}.{}", literal, self.digits(fraction_start, 10)?);
+            is_float = true;
         }
-        self.add_token_lit(
-            NUMBER,
-            LoxValue::Number(self.source[self.start..self.current].parse().expect("[SCANNER]: FloatError")),
-        )
+        let mut ahead = self.source[self.current..].chars();
+        let exponent = match (ahead.next(), ahead.next(), ahead.next()) {
+            (Some('e' | 'E'), Some(c), _) if Self::is_digit(c) => true,
+            (Some('e' | 'E'), Some('+' | '-'), Some(c)) => Self::is_digit(c),
+            _ => false,
+        };
+        if exponent {
+            self.advance();
+            let sign = if matches!(self.peek(), '+' | '-') { self.advance().to_string() } else { String::new() };
+            let exponent_start = self.current;
+            literal = format!("{}e{}{}", literal, sign, self.digits(exponent_start, 10)?);
+            is_float = true;
+        }
+        Some((literal, is_float))
     }
 
-    fn string(&mut self) {
-        // While char isn't " and is not the end of source
-        while self.peek() != '"' && !self.is_at_end() {
-            if self.peek() == '\n' {
-                self.line += 1
-            }
+    // Digits of `radix` from `start` on, without the `_`s that may go
+    // between them. `None` when there are none, or a `_` isn't between two
+    fn digits(&mut self, start: usize, radix: u32) -> Option<String> {
+        while self.peek().is_digit(radix) || self.peek() == '_' {
             self.advance();
         }
+        let text = &self.source[start..self.current];
+        let valid = !text.is_empty() && !text.starts_with('_') && !text.ends_with('_') && !text.contains("__");
+        valid.then(|| text.replace('_', ""))
+    }
 
-        // If not string closed
-        if self.is_at_end() {
-            self.error("[SCANNER][ERROR]: Unterminated String");
-            return;
+    // The rest of a string after its opening `"`, or after the `}` of one
+    // of its `${`. Text up to a `${` becomes an INTERPOLATION token, the
+    // text after the last one a STRING.
+    fn string(&mut self, opening: Span) {
+        let mut value = String::new();
+        loop {
+            if self.is_at_end() {
+                // Inside a `${` the real problem is the outer string
+                let opening = self.interpolations.first().map_or(opening, |outer| outer.opening);
+                self.interpolations.clear();
+                self.error(ScannerError::UnterminatedString(opening));
+                return;
+            }
+            match self.advance() {
+                '"' => break,
+                '\\' => {
+                    if let Some(c) = self.escape() {
+                        value.push(c);
+                    }
+                }
+                '$' if self.peek() == '{' => {
+                    self.advance();
+                    self.add_token_lit(INTERPOLATION, LoxValue::String(value));
+                    self.interpolations.push(Interpolation { depth: 0, opening });
+                    return;
+                }
+                c => value.push(c),
+            }
         }
+        self.add_token_lit(STRING, LoxValue::String(value))
+    }
 
-        // The closing "
-        self.advance();
+    // The character an escape stands for, after its `\`
+    fn escape(&mut self) -> Option<char> {
+        // Left for `string` to report as unterminated
+        if self.is_at_end() {
+            return None;
+        }
+        let start = self.current - 1;
+        let column = self.source[self.line_start..start].chars().count() + 1;
+        let line = self.line;
+        let escaped = match self.advance() {
+            'n' => Some('\n'),
+            't' => Some('\t'),
+            'r' => Some('\r'),
+            '0' => Some('\0'),
+            '\\' => Some('\\'),
+            '"' => Some('"'),
+            '$' => Some('$'),
+            'u' => self.unicode_escape(),
+            _ => None,
+        };
+        if escaped.is_none() {
+            // A `\` before a newline only points at the `\`
+            let end = if self.source[start..self.current].ends_with('\n') { start + 1 } else { self.current };
+            let span = Span::new(self.file_id, start, end, line, column);
+            self.error(ScannerError::InvalidEscapeSequence(span));
+        }
+        escaped
+    }
 
-        // Trim the surrounding quotes.
-        let value = match self.source.get(self.start + 1..self.current - 1) {
-            Some(v) => v.to_string(),
-            None => {
-                self.error("[SCANNER][ERROR]: Invalid UTF-8 range in string.");
-                return;
-            }
+    // `\u{1F600}`, after the `u`: one to six hex digits naming a Unicode
+    // scalar value
+    fn unicode_escape(&mut self) -> Option<char> {
+        if !self.char_match('{') {
+            return None;
+        }
+        let digits_start = self.current;
+        while self.peek().is_ascii_hexdigit() {
+            self.advance();
+        }
+        let digits = &self.source[digits_start..self.current];
+        let code = match digits.len() {
+            1..=6 => u32::from_str_radix(digits, 16).ok(),
+            _ => None,
         };
-                self.add_token_lit(STRING, LoxValue::String(value))
+        if !self.char_match('}') {
+            return None;
+        }
+        code.and_then(char::from_u32)
+    }
+
+    // `"""text"""`, after the opening quotes: taken as written, newlines
+    // and backslashes included
+    fn raw_string(&mut self) {
+        let content_start = self.current;
+        while !self.is_at_end() && !self.source[self.current..].starts_with("\"\"\"") {
+            self.advance();
+        }
+        if self.is_at_end() {
+            self.error(ScannerError::UnterminatedRawString(self.opening_span(3)));
+            return;
+        }
+        let value = self.source[content_start..self.current].to_string();
+        for _ in 0..3 {
+            self.advance();
+        }
+        self.add_token_lit(STRING, LoxValue::String(value))
     }
 
     fn char_match(&mut self, expected: char) -> bool {
@@ -244,68 +497,75 @@ impl Scanner {
             return false;
         }
 
-        self.source.chars().nth(self.current).map_or(false, |c| {
-            if c == expected {
-                self.current += 1;
-                true
-            } else {
-                false
-            }
-        })
+        if self.peek() == expected {
+            self.advance();
+            true
+        } else {
+            false
+        }
     }
 
     fn peek(&self) -> char {
-        if self.is_at_end() {
-            return '\0';
-        }
-        self.source.chars().nth(self.current).unwrap_or('\0')
+        self.source[self.current..].chars().next().unwrap_or('\0')
     }
 
     fn peek_next(&mut self) -> char {
-        if self.current + 1 >= self.source.len() {
-            return '\0';
-        }
-        self.source.chars().nth(self.current + 1).unwrap_or('\0')
+        self.source[self.current..].chars().nth(1).unwrap_or('\0')
     }
 
     fn is_alpha_numeric(c: char) -> bool {
-        return Self::is_alpha(c) || Self::is_digit(c);
+        Self::is_alpha(c) || Self::is_digit(c)
     }
 
     fn is_alpha(c: char) -> bool {
-        return (c >= 'a' && c <= 'z') || (c >= 'A' && c <= 'Z') || c == '_';
+        c.is_ascii_alphabetic() || c == '_'
     }
 
     fn is_digit(c: char) -> bool {
-        c >= '0' && c <= '9'
+        c.is_ascii_digit()
     }
 
     fn advance(&mut self) -> char {
-        let current_char = self.source.chars().nth(self.current).unwrap_or('\0');
-        self.current += 1;
+        let Some(current_char) = self.source[self.current..].chars().next() else {
+            return '\0';
+        };
+        self.current += current_char.len_utf8();
+        if current_char == '\n' {
+            self.line += 1;
+            self.line_start = self.current;
+        }
         current_char
     }
 
-    fn error(&mut self, message: &str) {
-        self.report(self.line, "".to_string(), message)
+    fn mark_start(&mut self) {
+        self.start = self.current;
+        self.start_line = self.line;
+        self.start_column = self.source[self.line_start..self.start].chars().count() + 1;
+    }
+
+    fn span(&self) -> Span {
+        Span::new(self.file_id, self.start, self.current, self.start_line, self.start_column)
+    }
+
+    // Just the opening delimiter of the current lexeme, for errors on
+    // strings and comments that run to the end of the file
+    fn opening_span(&self, len: usize) -> Span {
+        Span::new(self.file_id, self.start, self.start + len, self.start_line, self.start_column)
     }
 
-    fn report(&mut self, line: usize, where_is: String, message: &str) {
-        Color::ecprintln(
-            &format!("{}, {} at line {}", message, where_is, line),
-            Color::Red,
-        );
+    fn error(&mut self, error: ScannerError) {
+        self.errors.push(error);
     }
 
     fn add_token(&mut self, t_type: TokenType) {
         let lexeme = self.source[self.start..self.current].to_string();
         self.tokens
-            .push(Token::from(t_type, lexeme, LoxValue::Nil, self.line));
+            .push(Token::from(t_type, lexeme, LoxValue::Nil, self.span()));
     }
 
     fn add_token_lit(&mut self, t_type: TokenType, literal: LoxValue) {
         let lexeme = self.source[self.start..self.current].to_string();
         self.tokens
-            .push(Token::from(t_type, lexeme, literal, self.line));
+            .push(Token::from(t_type, lexeme, literal, self.span()));
     }
 }
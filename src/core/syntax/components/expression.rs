@@ -2,16 +2,19 @@ use std::cell::RefCell;
 use std::{fmt, rc::Rc, sync::atomic::Ordering};
 
 use crate::core::fuctions::lox_callable::LoxCallable;
+use crate::core::gc::{Trace, Tracer};
 use crate::core::fuctions::lox_function::LoxFunction;
+use crate::core::native_functions::{collection, lox_list};
+use crate::core::native_functions::lox_map::{self, LoxMap};
+use crate::core::number::Number;
 use crate::core::oop::lox_class::LoxClass;
 use crate::core::oop::lox_instance::LoxInstance;
-use crate::core::{error_types::runtime_error::RuntimeError, syntax::token::Token};
+use crate::core::syntax::components::stmt::{Param, Stmt};
+use crate::core::{error_types::runtime_error::RuntimeError, syntax::{span::Span, token::Token}};
 use std::sync::atomic::AtomicUsize;
 static NEXT_ID: AtomicUsize = AtomicUsize::new(2);
-use std::hash::Hasher;
-use std::hash::Hash;
 
-#[derive(Clone, Debug, Hash, Eq, PartialEq)]
+#[derive(Clone, Debug)]
 pub enum Expr {
     Binary {
         id: usize,
@@ -41,6 +44,7 @@ pub enum Expr {
         value: Box<Expr>
     },
     Super {
+        id: usize,
         keyword: Token,
         method: Token
     },
@@ -51,6 +55,7 @@ pub enum Expr {
     Literal {
         id: usize,
         value: LoxValue,
+        span: Span,
     },
     This {
         id: usize,
@@ -75,24 +80,84 @@ pub enum Expr {
     Variable {
         id: usize,
         name: Token,
-        value: Box<Expr>,
     },
     Assing {
         id: usize,
         name: Token,
         value: Box<Expr>,
     },
+    // `[a, b]`
+    List {
+        id: usize,
+        bracket: Token,
+        elements: Vec<Expr>,
+    },
+    // `{key: value}`
+    Map {
+        id: usize,
+        brace: Token,
+        entries: Vec<(Expr, Expr)>,
+    },
+    // `object[index]`; `bracket` is the closing one
+    Index {
+        id: usize,
+        object: Box<Expr>,
+        bracket: Token,
+        index: Box<Expr>,
+    },
+    SetIndex {
+        id: usize,
+        object: Box<Expr>,
+        bracket: Token,
+        index: Box<Expr>,
+        value: Box<Expr>,
+    },
+    // `target += value` and the like, or `++`/`--` as an update by 1.
+    // `target` is a `Variable`, `Get` or `Index`, whose object and index
+    // are evaluated once. `operator` is the binary one applied, keeping
+    // the lexeme as written; postfix updates evaluate to the old value
+    Update {
+        id: usize,
+        target: Box<Expr>,
+        operator: Token,
+        value: Box<Expr>,
+        postfix: bool,
+    },
+    // `object[start:end]`, either bound may be left out
+    Slice {
+        id: usize,
+        object: Box<Expr>,
+        bracket: Token,
+        start: Option<Box<Expr>>,
+        end: Option<Box<Expr>>,
+    },
+    // `${expression}` inside a string: its value as text, the way
+    // `to_string` shows it
+    Interpolated {
+        id: usize,
+        expression: Box<Expr>,
+    },
+    // `fn (x) { ... }`, or `(x) => x` with its body wrapped in a `return`
+    Lambda {
+        id: usize,
+        keyword: Token,
+        params: Vec<Param>,
+        body: Vec<Stmt>,
+    },
 }
 
 #[derive(Clone)]
 pub enum LoxValue {
     Number(f64),
+    Integer(i64),
     String(String),
     Boolean(bool),
     Callable(Rc<dyn LoxCallable>),
     LoxFunction(Rc<LoxFunction>),
     LoxInstance(Rc<RefCell<LoxInstance>>),
     LoxClass(LoxClass),
+    List(Rc<RefCell<Vec<LoxValue>>>),
+    Map(Rc<RefCell<LoxMap<LoxValue>>>),
     Nil,
 }
 
@@ -100,9 +165,14 @@ impl PartialEq for LoxValue {
     fn eq(&self, other: &Self) -> bool {
         match (self, other) {
             (LoxValue::Boolean(b1), LoxValue::Boolean(b2)) => b1 == b2,
-            (LoxValue::Number(n1), LoxValue::Number(n2)) => n1 == n2,
             (LoxValue::String(s1), LoxValue::String(s2)) => s1 == s2,
             (LoxValue::Nil, LoxValue::Nil) => true,
+            // `1 == 1.0`
+            (LoxValue::Number(_) | LoxValue::Integer(_), LoxValue::Number(_) | LoxValue::Integer(_)) => {
+                Number::from_lox(self).zip(Number::from_lox(other)).is_some_and(|(n1, n2)| n1.equals(n2))
+            }
+            (LoxValue::List(l1), LoxValue::List(l2)) => collection::lists_equal(l1, l2),
+            (LoxValue::Map(m1), LoxValue::Map(m2)) => collection::maps_equal(m1, m2),
             _ => false,
         }
     }
@@ -122,32 +192,77 @@ impl LoxValue {
             _ => None, 
         }
     }
-}
 
-impl Hash for LoxValue {
-    fn hash<H: Hasher>(&self, state: &mut H) {
+    /// The type as named in error messages.
+    pub fn type_name(&self) -> &'static str {
         match self {
-            LoxValue::Boolean(b) => b.hash(state),
-            LoxValue::Number(n) => {
-                n.to_bits().hash(state);
-            }
-            LoxValue::String(s) => s.hash(state),
-            LoxValue::Nil => state.write_u8(0), 
-            LoxValue::Callable(_) => {
-                panic!("No se puede hacer hash de un Callable");
-            }
-            LoxValue::LoxInstance(_) => {
-                panic!("No se puede hacer hash de un Instance");
-            }
-            LoxValue::LoxClass(_) => {
-                panic!("No se puede hacer hash de un Class");
-            }
-            LoxValue::LoxFunction(_) => {
-                panic!("No se puede hacer hash de un Function");
-            }
+            LoxValue::Number(_) => "number",
+            LoxValue::Integer(_) => "integer",
+            LoxValue::String(_) => "string",
+            LoxValue::Boolean(_) => "boolean",
+            LoxValue::Callable(_) | LoxValue::LoxFunction(_) => "function",
+            LoxValue::LoxInstance(_) => "instance",
+            LoxValue::LoxClass(_) => "class",
+            LoxValue::List(_) => "list",
+            LoxValue::Map(_) => "map",
+            LoxValue::Nil => "nil",
+        }
+    }
+
+    /// How the value reads inside text, as in `List.join`: strings without
+    /// quotes and numbers without their `Number()` or `Integer()` wrapper.
+    pub fn text(&self) -> String {
+        match self {
+            LoxValue::String(s) => s.clone(),
+            LoxValue::Number(n) => n.to_string(),
+            LoxValue::Integer(n) => n.to_string(),
+            LoxValue::Boolean(b) => b.to_string(),
+            value => value.to_string(),
+        }
+    }
+
+    /// The struct behind an instance of a native class registered with
+    /// `LoxSession::register_class`, when it is a `T`.
+    pub fn as_native<T: 'static>(&self) -> Option<Rc<RefCell<T>>> {
+        match self {
+            LoxValue::LoxInstance(instance) => instance.borrow().native()?.clone().downcast::<RefCell<T>>().ok(),
+            _ => None,
+        }
+    }
+
+    /// Reports the objects this value keeps alive to the cycle collector.
+    pub fn trace(&self, tracer: &mut Tracer) {
+        match self {
+            LoxValue::Callable(fun) => tracer.edge(fun),
+            LoxValue::LoxFunction(fun) => tracer.edge(&(Rc::clone(fun) as Rc<dyn LoxCallable>)),
+            LoxValue::LoxInstance(instance) => tracer.edge(instance),
+            // Classes are held by value, so their methods' scopes count as
+            // referenced from here
+            LoxValue::LoxClass(class) => class.trace(tracer),
+            LoxValue::List(list) => tracer.edge(list),
+            LoxValue::Map(map) => tracer.edge(map),
+            _ => {}
         }
     }
 }
+
+impl Trace for RefCell<Vec<LoxValue>> {
+    fn trace(&self, tracer: &mut Tracer) {
+        let Ok(items) = self.try_borrow() else {
+            return tracer.opaque();
+        };
+        for item in items.iter() {
+            item.trace(tracer);
+        }
+    }
+
+    fn clear(&self) {
+        if let Ok(mut items) = self.try_borrow_mut() {
+            items.clear();
+        }
+    }
+}
+
 pub trait Visitor<R> {
     fn visit_binary(&mut self, left: &Expr, operator: &Token, right: &Expr) -> Result<R, RuntimeError>;
     fn visit_call(&mut self, callee: &Expr, paren: &Token, arguments: &[Expr]) -> Result<R, RuntimeError>;
@@ -161,13 +276,21 @@ pub trait Visitor<R> {
         then_branch: &Expr,
         else_branch: &Expr,
     ) -> Result<R, RuntimeError>;
-    fn visit_variable(&mut self, name: &Token, value: &Expr) -> Result<R, RuntimeError>;
-    fn visit_assing(&mut self, name: &Token, value: &Expr) -> Result<R, RuntimeError>;
+    fn visit_variable(&mut self, id: usize, name: &Token) -> Result<R, RuntimeError>;
+    fn visit_assing(&mut self, id: usize, name: &Token, value: &Expr) -> Result<R, RuntimeError>;
     fn visit_logical(&mut self, left: &Expr, operator: &Token, right: &Expr) -> Result<R, RuntimeError>;
     fn visit_get(&mut self, name: &Token, object: &Expr) -> Result<R, RuntimeError>;
     fn visit_set(&mut self, object: &Expr, name: &Token, value: &Expr) -> Result<R, RuntimeError>;
-    fn visit_this(&mut self, keyword: &Token) -> Result<R, RuntimeError>;
-    fn visit_super(&mut self, keyword: &Token, keyword: &Token) -> Result<R, RuntimeError>;
+    fn visit_this(&mut self, id: usize, keyword: &Token) -> Result<R, RuntimeError>;
+    fn visit_super(&mut self, id: usize, keyword: &Token, method: &Token) -> Result<R, RuntimeError>;
+    fn visit_lambda(&mut self, keyword: &Token, params: &[Param], body: &[Stmt]) -> Result<R, RuntimeError>;
+    fn visit_list(&mut self, bracket: &Token, elements: &[Expr]) -> Result<R, RuntimeError>;
+    fn visit_map(&mut self, brace: &Token, entries: &[(Expr, Expr)]) -> Result<R, RuntimeError>;
+    fn visit_index(&mut self, object: &Expr, bracket: &Token, index: &Expr) -> Result<R, RuntimeError>;
+    fn visit_set_index(&mut self, object: &Expr, bracket: &Token, index: &Expr, value: &Expr) -> Result<R, RuntimeError>;
+    fn visit_slice(&mut self, object: &Expr, bracket: &Token, start: Option<&Expr>, end: Option<&Expr>) -> Result<R, RuntimeError>;
+    fn visit_update(&mut self, target: &Expr, operator: &Token, value: &Expr, postfix: bool) -> Result<R, RuntimeError>;
+    fn visit_interpolated(&mut self, expression: &Expr) -> Result<R, RuntimeError>;
 }
 
 impl Expr {
@@ -187,9 +310,9 @@ impl Expr {
                 then_branch,
                 else_branch, ..
             } => visitor.visit_ternary(condition, then_branch, else_branch),
-            Expr::Variable { name , value, ..} => {visitor.visit_variable(name, value)}
-            Expr::Assing { name, value, .. } => {
-                visitor.visit_assing(name, value)
+            Expr::Variable { id, name } => visitor.visit_variable(*id, name),
+            Expr::Assing { id, name, value } => {
+                visitor.visit_assing(*id, name, value)
             }
             Expr::Logical { left, operator, right, .. } => {
                 visitor.visit_logical(left, operator, right)
@@ -203,12 +326,61 @@ impl Expr {
             Expr::Set { object, name, value } => {
                 visitor.visit_set(object, name, value)
             }
-            Expr::This { keyword, .. } => {
-                visitor.visit_this(keyword)
+            Expr::This { id, keyword } => {
+                visitor.visit_this(*id, keyword)
             }
-            Expr::Super { keyword, method } => {
-                visitor.visit_super(keyword, method)
+            Expr::Super { id, keyword, method } => {
+                visitor.visit_super(*id, keyword, method)
             }
+            Expr::Lambda { keyword, params, body, .. } => {
+                visitor.visit_lambda(keyword, params, body)
+            }
+            Expr::List { bracket, elements, .. } => visitor.visit_list(bracket, elements),
+            Expr::Map { brace, entries, .. } => visitor.visit_map(brace, entries),
+            Expr::Index { object, bracket, index, .. } => visitor.visit_index(object, bracket, index),
+            Expr::SetIndex { object, bracket, index, value, .. } => {
+                visitor.visit_set_index(object, bracket, index, value)
+            }
+            Expr::Slice { object, bracket, start, end, .. } => {
+                visitor.visit_slice(object, bracket, start.as_deref(), end.as_deref())
+            }
+            Expr::Update { target, operator, value, postfix, .. } => {
+                visitor.visit_update(target, operator, value, *postfix)
+            }
+            Expr::Interpolated { expression, .. } => visitor.visit_interpolated(expression),
+        }
+    }
+
+    /// The source range covered by this expression.
+    pub fn span(&self) -> Span {
+        match self {
+            Expr::Binary { left, right, .. }
+            | Expr::Logical { left, right, .. }
+            | Expr::Comma { left, right, .. } => left.span().to(right.span()),
+            Expr::Call { callee, paren, .. } => callee.span().to(paren.span),
+            Expr::Get { object, name } => object.span().to(name.span),
+            Expr::Set { object, value, .. } => object.span().to(value.span()),
+            Expr::Super { keyword, method, .. } => keyword.span.to(method.span),
+            Expr::Grouping { expression, .. } | Expr::Interpolated { expression, .. } => expression.span(),
+            Expr::Literal { span, .. } => *span,
+            Expr::This { keyword, .. } => keyword.span,
+            Expr::Unary { operator, right, .. } => operator.span.to(right.span()),
+            Expr::Ternary { condition, else_branch, .. } => condition.span().to(else_branch.span()),
+            Expr::Variable { name, .. } => name.span,
+            Expr::Assing { name, value, .. } => name.span.to(value.span()),
+            Expr::Lambda { keyword, .. } => keyword.span,
+            Expr::List { bracket, elements, .. } => match elements.last() {
+                Some(last) => bracket.span.to(last.span()),
+                None => bracket.span,
+            },
+            Expr::Map { brace, entries, .. } => match entries.last() {
+                Some((_, last)) => brace.span.to(last.span()),
+                None => brace.span,
+            },
+            Expr::Index { object, bracket, .. } | Expr::Slice { object, bracket, .. } => object.span().to(bracket.span),
+            Expr::SetIndex { object, value, .. } => object.span().to(value.span()),
+            // The `1` of `++`/`--` has the operator's span
+            Expr::Update { target, operator, value, .. } => target.span().to(operator.span).to(value.span()),
         }
     }
 
@@ -248,28 +420,60 @@ impl fmt::Display for Expr {
                 write!(f, "({}) ? {} : {}", condition, then_branch, else_branch)
             }
             Expr::Variable { name , ..} => {
-                write!(f, "(var {} = {})", name.lexeme, "")
+                write!(f, "{}", name.lexeme)
             }
             Expr::Assing { name, value, .. } => {
-                write!(f, "({} = {})", name, value)
+                write!(f, "(= {} {})", name.lexeme, value)
             }
             Expr::Logical { left, operator, right, .. } => {
-                write!(f, "({} {} {})", left, operator, right)
+                write!(f, "({} {} {})", operator.lexeme, left, right)
             }
-            Expr::Call { callee, paren, arguments, .. } => {
-                write!(f, "{}({} {:?})", callee, paren, arguments)
+            Expr::Call { callee, arguments, .. } => {
+                write!(f, "(call {}", callee)?;
+                for argument in arguments {
+                    write!(f, " {}", argument)?;
+                }
+                write!(f, ")")
             },
             Expr::Get { object, name } => {
-                write!(f, "{}, {:?}", name, object)
+                write!(f, "(. {} {})", object, name.lexeme)
             }
             Expr::Set { object, name, value } => {
-                write!(f, "{}, {}, {:?}", value, name, object)
+                write!(f, "(= (. {} {}) {})", object, name.lexeme, value)
+            }
+            Expr::This { .. } => {
+                write!(f, "this")
+            }
+            Expr::Super { method, .. } => {
+                write!(f, "(. super {})", method.lexeme)
             }
-            Expr::This { keyword , ..} => {
-                write!(f,"This {}", keyword)
+            Expr::List { elements, .. } => {
+                let elements: Vec<String> = elements.iter().map(|e| e.to_string()).collect();
+                write!(f, "(list {})", elements.join(" "))
             }
-            Expr::Super { keyword, method } => {
-                write!(f,"Super {} {}", keyword, method)
+            Expr::Map { entries, .. } => {
+                let entries: Vec<String> = entries.iter().map(|(key, value)| format!("{} {}", key, value)).collect();
+                write!(f, "(map {})", entries.join(" "))
+            }
+            Expr::Index { object, index, .. } => write!(f, "([] {} {})", object, index),
+            Expr::SetIndex { object, index, value, .. } => write!(f, "(= ([] {} {}) {})", object, index, value),
+            Expr::Update { target, operator, postfix: true, .. } => write!(f, "({} {})", target, operator.lexeme),
+            Expr::Update { target, operator, value, .. } => match operator.lexeme.as_str() {
+                "++" | "--" => write!(f, "({} {})", operator.lexeme, target),
+                _ => write!(f, "({} {} {})", operator.lexeme, target, value),
+            },
+            Expr::Interpolated { expression, .. } => write!(f, "(${{}} {})", expression),
+            Expr::Slice { object, start, end, .. } => {
+                let bound = |bound: &Option<Box<Expr>>| bound.as_ref().map_or(String::new(), |b| b.to_string());
+                write!(f, "([:] {} {}:{})", object, bound(start), bound(end))
+            }
+            Expr::Lambda { params, body, .. } => {
+                let params: Vec<String> = params.iter().map(|p| p.to_string()).collect();
+                write!(f, "(fn ({})", params.join(", "))?;
+                for statement in body {
+                    write!(f, " {}", statement)?;
+                }
+                write!(f, ")")
             }
         }
     }
@@ -279,6 +483,7 @@ impl fmt::Debug for LoxValue {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             LoxValue::Number(n) => write!(f, "Number({})", n),
+            LoxValue::Integer(n) => write!(f, "Integer({})", n),
             LoxValue::String(s) => write!(f, "String({:?})", s),
             LoxValue::Boolean(b) => write!(f, "Boolean({})", b),
             LoxValue::Callable(_) => write!(f, "Callable()"),
@@ -290,6 +495,8 @@ impl fmt::Debug for LoxValue {
             LoxValue::LoxClass(c) => {
                 write!(f, "LoxClass({})", c.name)
             }
+            LoxValue::List(l) => f.debug_list().entries(l.borrow().iter()).finish(),
+            LoxValue::Map(m) => f.debug_map().entries(m.borrow().iter()).finish(),
         }
     }
 }
@@ -298,6 +505,7 @@ impl fmt::Display for LoxValue {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
             LoxValue::Number(n) => write!(f, "Number({})", n),
+            LoxValue::Integer(n) => write!(f, "Integer({})", n),
             LoxValue::String(s) => write!(f, "String({})", s),
             LoxValue::Boolean(b) => write!(f, "Boolean({})", b),
             LoxValue::Callable(_d) => {
@@ -317,6 +525,8 @@ impl fmt::Display for LoxValue {
             LoxValue::LoxFunction(ff) => {
                 write!(f, "LoxFunction({})" , ff)
             }
+            LoxValue::List(l) => lox_list::fmt_items(f, l),
+            LoxValue::Map(m) => lox_map::fmt_entries(f, m),
         }
     }
 }
@@ -1,8 +1,30 @@
 
-use crate::core::{error_types::runtime_error::RuntimeError, syntax::token::Token};
+use std::fmt;
 
-use super::expression::Expr;
+use crate::core::{error_types::runtime_error::RuntimeError, syntax::{token::Token, token_type::TokenType}};
 
+use super::expression::{Expr, LoxValue};
+use crate::core::syntax::span::Span;
+
+/// A parameter of a function declaration: `a`, `b = 2` or `...rest`.
+#[derive(Clone, Debug)]
+pub struct Param {
+    pub name: Token,
+    // Evaluated in the callee's scope when the argument is left out
+    pub default: Option<Expr>,
+    // Collects the remaining arguments into a list; only the last one
+    pub rest: bool,
+}
+
+impl fmt::Display for Param {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.default {
+            _ if self.rest => write!(f, "...{}", self.name.lexeme),
+            Some(default) => write!(f, "{} = {}", self.name.lexeme, default),
+            None => write!(f, "{}", self.name.lexeme),
+        }
+    }
+}
 
 #[derive(Clone, Debug)]
 pub enum Stmt {
@@ -12,10 +34,14 @@ pub enum Stmt {
     Class { name: Token, methods: Vec<Stmt>, super_class: Option<Expr>},
     Block { statements: Vec<Stmt> },
     If { condition: Expr, then_branch: Box<Stmt>, else_branch: Option<Box<Stmt>> },
-    While { condition: Expr, body: Box<Stmt>, else_branch: Option<Box<Stmt>> },
-    Loop { body: Box<Stmt> },
-    Break {},
-    Function { token: Token, params: Vec<Token>, body: Vec<Stmt>, public: bool, is_static: bool},
+    // `increment` is the last clause of a `for`, run after the body even
+    // when it ends in `continue`
+    While { condition: Expr, body: Box<Stmt>, else_branch: Option<Box<Stmt>>, increment: Option<Box<Expr>>, label: Option<Token> },
+    Loop { body: Box<Stmt>, label: Option<Token> },
+    // Without a label they leave or restart the innermost loop
+    Break { keyword: Token, label: Option<Token> },
+    Continue { keyword: Token, label: Option<Token> },
+    Function { token: Token, params: Vec<Param>, body: Vec<Stmt>, public: bool, is_static: bool},
     Return { keyword: Token, value: Expr }
 }
 
@@ -25,10 +51,18 @@ pub trait Visitor<R> {
     fn visit_var_declaration(&mut self, name: &Token, initializer: &Expr) -> Result<R, RuntimeError>;
     fn visit_block(&mut self, statements: &[Stmt]) -> Result<R, RuntimeError>;
     fn visit_if(&mut self, condition: &Expr, then_branch: &Stmt, else_branch: Option<&Stmt>) -> Result<R, RuntimeError>;
-    fn visit_while(&mut self, condition: &Expr, body: &Stmt, else_branch: Option<&Stmt>) -> Result<R, RuntimeError>;
-    fn visit_loop(&mut self, body: &Stmt) -> Result<R, RuntimeError>;
-    fn visit_break(&mut self) -> Result<R, RuntimeError>;
-    fn visit_function(&mut self, token: &Token, params: &[Token], body: &[Stmt], public: bool, is_static: bool) -> Result<R, RuntimeError>;
+    fn visit_while(
+        &mut self,
+        condition: &Expr,
+        body: &Stmt,
+        else_branch: Option<&Stmt>,
+        increment: Option<&Expr>,
+        label: Option<&Token>,
+    ) -> Result<R, RuntimeError>;
+    fn visit_loop(&mut self, body: &Stmt, label: Option<&Token>) -> Result<R, RuntimeError>;
+    fn visit_break(&mut self, keyword: &Token, label: Option<&Token>) -> Result<R, RuntimeError>;
+    fn visit_continue(&mut self, keyword: &Token, label: Option<&Token>) -> Result<R, RuntimeError>;
+    fn visit_function(&mut self, token: &Token, params: &[Param], body: &[Stmt], public: bool, is_static: bool) -> Result<R, RuntimeError>;
     fn visit_class(&mut self, name: &Token, methods: &[Stmt], super_class: &Option<Expr>) -> Result<R, RuntimeError>;
     fn visit_return(&mut self, keyword: &Token, value: &Expr) -> Result<R, RuntimeError>;
 }
@@ -41,14 +75,17 @@ impl Stmt {
             Stmt::Var { name, initializer } => visitor.visit_var_declaration(name, initializer),
             Stmt::Block { statements } => visitor.visit_block(statements),
             Stmt::If { condition, then_branch, else_branch } => visitor.visit_if(condition, then_branch, else_branch.as_deref()),
-            Stmt::While { condition, body, else_branch } => {
-                visitor.visit_while(condition, body, else_branch.as_deref())
+            Stmt::While { condition, body, else_branch, increment, label } => {
+                visitor.visit_while(condition, body, else_branch.as_deref(), increment.as_deref(), label.as_ref())
+            }
+            Stmt::Loop { body, label } => {
+                visitor.visit_loop(body, label.as_ref())
             }
-            Stmt::Loop { body } => {
-                visitor.visit_loop(body)
+            Stmt::Break { keyword, label } => {
+                visitor.visit_break(keyword, label.as_ref())
             }
-            Stmt::Break { .. } => {
-                visitor.visit_break()
+            Stmt::Continue { keyword, label } => {
+                visitor.visit_continue(keyword, label.as_ref())
             }
             Stmt::Function { token, params, body, public, is_static } => {
                 visitor.visit_function(token, params, body, *public, *is_static)
@@ -61,4 +98,119 @@ impl Stmt {
             }
         }
     }
-}
\ No newline at end of file
+}
+
+impl Stmt {
+    /// The declaration a function expression runs as: a public function
+    /// called `anonymous`, located at `keyword`.
+    pub fn anonymous_function(keyword: &Token, params: &[Param], body: &[Stmt]) -> Stmt {
+        Stmt::Function {
+            token: Token::new(TokenType::IDENTIFIER, "anonymous".to_string(), LoxValue::Nil, keyword.span),
+            params: params.to_vec(),
+            body: body.to_vec(),
+            public: true,
+            is_static: false,
+        }
+    }
+
+    /// The source range covered by this statement. Statements without any
+    /// token of their own (an empty block) get an empty default span.
+    pub fn span(&self) -> Span {
+        match self {
+            Stmt::Expression { expression } | Stmt::Print { expression } => expression.span(),
+            Stmt::Var { name, initializer } => name.span.to(initializer.span()),
+            Stmt::Class { name, .. } => name.span,
+            Stmt::Block { statements } => match (statements.first(), statements.last()) {
+                (Some(first), Some(last)) => first.span().to(last.span()),
+                _ => Span::default(),
+            },
+            Stmt::If { condition, then_branch, else_branch } => {
+                let end = else_branch.as_deref().unwrap_or(then_branch);
+                condition.span().to(end.span())
+            }
+            Stmt::While { condition, body, else_branch, .. } => {
+                let end = else_branch.as_deref().unwrap_or(body);
+                condition.span().to(end.span())
+            }
+            Stmt::Loop { body, .. } => body.span(),
+            Stmt::Break { keyword, label } | Stmt::Continue { keyword, label } => match label {
+                Some(label) => keyword.span.to(label.span),
+                None => keyword.span,
+            },
+            Stmt::Function { token, .. } => token.span,
+            Stmt::Return { keyword, value } => keyword.span.to(value.span()),
+        }
+    }
+}
+
+impl fmt::Display for Stmt {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Stmt::Expression { expression } => write!(f, "(expr {})", expression),
+            Stmt::Print { expression } => write!(f, "(print {})", expression),
+            Stmt::Var { name, initializer } => write!(f, "(var {} {})", name.lexeme, initializer),
+            Stmt::Class { name, methods, super_class } => {
+                write!(f, "(class {}", name.lexeme)?;
+                if let Some(Expr::Variable { name: super_name, .. }) = super_class {
+                    write!(f, " < {}", super_name.lexeme)?;
+                }
+                for method in methods {
+                    write!(f, " {}", method)?;
+                }
+                write!(f, ")")
+            }
+            Stmt::Block { statements } => {
+                write!(f, "(block")?;
+                for statement in statements {
+                    write!(f, " {}", statement)?;
+                }
+                write!(f, ")")
+            }
+            Stmt::If { condition, then_branch, else_branch } => match else_branch {
+                Some(else_branch) => write!(f, "(if {} {} {})", condition, then_branch, else_branch),
+                None => write!(f, "(if {} {})", condition, then_branch),
+            },
+            Stmt::While { condition, body, else_branch, increment, label } => {
+                if let Some(label) = label {
+                    write!(f, "{}: ", label.lexeme)?;
+                }
+                write!(f, "(while {} {}", condition, body)?;
+                if let Some(increment) = increment {
+                    write!(f, " (step {})", increment)?;
+                }
+                if let Some(else_branch) = else_branch {
+                    write!(f, " {}", else_branch)?;
+                }
+                write!(f, ")")
+            }
+            Stmt::Loop { body, label } => match label {
+                Some(label) => write!(f, "{}: (loop {})", label.lexeme, body),
+                None => write!(f, "(loop {})", body),
+            },
+            Stmt::Break { label, .. } => match label {
+                Some(label) => write!(f, "(break {})", label.lexeme),
+                None => write!(f, "(break)"),
+            },
+            Stmt::Continue { label, .. } => match label {
+                Some(label) => write!(f, "(continue {})", label.lexeme),
+                None => write!(f, "(continue)"),
+            },
+            Stmt::Function { token, params, body, public, is_static } => {
+                write!(f, "(fn ")?;
+                if *public {
+                    write!(f, "pub ")?;
+                }
+                if *is_static {
+                    write!(f, "static ")?;
+                }
+                let params: Vec<String> = params.iter().map(|p| p.to_string()).collect();
+                write!(f, "{}({})", token.lexeme, params.join(", "))?;
+                for statement in body {
+                    write!(f, " {}", statement)?;
+                }
+                write!(f, ")")
+            }
+            Stmt::Return { value, .. } => write!(f, "(return {})", value),
+        }
+    }
+}
@@ -2,4 +2,5 @@ pub mod components;
 pub mod analysing;
 pub mod token;
 pub mod token_type;
-pub mod resolver;
\ No newline at end of file
+pub mod resolver;
+pub mod span;
\ No newline at end of file
@@ -5,12 +5,11 @@ use std::rc::Rc;
 use rustc_hash::FxHashMap;
 
 use super::components::expression::{Expr, LoxValue, Visitor as ExpressionVisitor};
-use super::components::stmt::{Stmt, Visitor as StatementVisitor};
+use super::components::stmt::{Param, Stmt, Visitor as StatementVisitor};
+use super::span::Span;
 use super::token::Token;
 use crate::core::error_types::runtime_error::RuntimeError;
 use crate::core::interpreter::Interpreter;
-use crate::core::lox::Lox;
-use crate::utils::colors::Color;
 
 #[derive(Clone, Copy, PartialEq, Eq)]
 pub enum FunctionType {
@@ -26,33 +25,43 @@ pub enum ClassType {
     SUBCLASS
 }
 
+// A name declared in a local scope
+struct Local {
+    defined: bool,
+    span: Span,
+    // Index of the value in the scope's runtime frame
+    slot: usize,
+}
+
 pub struct Resolver {
     interpreter: Rc<RefCell<Interpreter>>,
-    scopes: Vec<FxHashMap<String, bool>>,
+    // The first scope is the global one; names found there (or nowhere)
+    // are looked up by name at runtime
+    scopes: Vec<FxHashMap<String, Local>>,
     unused_variables: Vec<String>,
     current_function: FunctionType,
-    current_class: ClassType
+    current_class: ClassType,
+    // Labels of the loops enclosing the current statement, innermost last,
+    // within the current function
+    loops: Vec<Option<String>>
 }
 
 impl ExpressionVisitor<()> for Resolver {
-    fn visit_variable(&mut self, name: &Token, expr: &Expr) -> Result<(), RuntimeError> {
+    fn visit_variable(&mut self, id: usize, name: &Token) -> Result<(), RuntimeError> {
         if let Some(scope) = self.scopes.last() {
-            if scope.get(&name.lexeme) == Some(&false) {
-                Color::ecprintln(
-                    "[RESOLVER]: Can't read local variable in its own initializer.",
-                    Color::Cyan,
-                );
+            if let Some(Local { defined: false, .. }) = scope.get(&name.lexeme) {
+                return Err(RuntimeError::ReadLocalInOwnInitializer(name.clone()));
             }
         }
         
         self.mark_as_used(&name.lexeme);
 
-        self.resolve_local(expr, name);
+        self.resolve_local(id, name);
         Ok(())
     }
-    fn visit_assing(&mut self, name: &Token, value: &Expr) -> Result<(), RuntimeError> {
+    fn visit_assing(&mut self, id: usize, name: &Token, value: &Expr) -> Result<(), RuntimeError> {
         self.resolve_expr(value)?;
-        self.resolve_local(value, name);
+        self.resolve_local(id, name);
         self.mark_as_used(&name.lexeme);
 
         Ok(())
@@ -109,26 +118,73 @@ impl ExpressionVisitor<()> for Resolver {
         self.resolve_expr(object)?;
         Ok(())
     }
-    fn visit_this(&mut self, keyword: &Token) -> Result<(), RuntimeError> {
+    fn visit_this(&mut self, id: usize, keyword: &Token) -> Result<(), RuntimeError> {
         if self.current_class == ClassType::NONE {
-            Lox::print_error("Can't use 'this' outside a class.");
-            return Ok(())
+            return Err(RuntimeError::ThisOutsideClass(keyword.clone()));
         }
-        self.resolve_local(&Expr::Literal { id: 0, value: LoxValue::Nil },keyword);
+        self.resolve_local(id, keyword);
         Ok(())
     }
-    fn visit_super(&mut self, keyword: &Token, method: &Token) -> Result<(), RuntimeError> {
+    fn visit_super(&mut self, id: usize, keyword: &Token, _method: &Token) -> Result<(), RuntimeError> {
         match self.current_class {
-            ClassType::NONE => Err(RuntimeError::SuperOutsideClass()),
-            ClassType::CLASS => Err(RuntimeError::SuperWithoutSubclass()),
+            ClassType::NONE => Err(RuntimeError::SuperOutsideClass(keyword.clone())),
+            ClassType::CLASS => Err(RuntimeError::SuperWithoutSubclass(keyword.clone())),
             ClassType::SUBCLASS => {
-                self.resolve_local(&Expr::Super { keyword: keyword.clone(), method: method.clone() }, keyword);
+                self.resolve_local(id, keyword);
                 Ok(())
             },
         }
         
        
     }
+
+    fn visit_lambda(&mut self, keyword: &Token, params: &[Param], body: &[Stmt]) -> Result<(), RuntimeError> {
+        self.resolve_function(&Stmt::anonymous_function(keyword, params, body), FunctionType::FUNCTION)
+    }
+
+    fn visit_list(&mut self, _bracket: &Token, elements: &[Expr]) -> Result<(), RuntimeError> {
+        for element in elements {
+            self.resolve_expr(element)?;
+        }
+        Ok(())
+    }
+
+    fn visit_map(&mut self, _brace: &Token, entries: &[(Expr, Expr)]) -> Result<(), RuntimeError> {
+        for (key, value) in entries {
+            self.resolve_expr(key)?;
+            self.resolve_expr(value)?;
+        }
+        Ok(())
+    }
+
+    fn visit_index(&mut self, object: &Expr, _bracket: &Token, index: &Expr) -> Result<(), RuntimeError> {
+        self.resolve_expr(object)?;
+        self.resolve_expr(index)
+    }
+
+    fn visit_set_index(&mut self, object: &Expr, _bracket: &Token, index: &Expr, value: &Expr) -> Result<(), RuntimeError> {
+        self.resolve_expr(object)?;
+        self.resolve_expr(index)?;
+        self.resolve_expr(value)
+    }
+
+    fn visit_slice(&mut self, object: &Expr, _bracket: &Token, start: Option<&Expr>, end: Option<&Expr>) -> Result<(), RuntimeError> {
+        self.resolve_expr(object)?;
+        for bound in start.into_iter().chain(end) {
+            self.resolve_expr(bound)?;
+        }
+        Ok(())
+    }
+
+    // A variable target is read and written through the same id
+    fn visit_update(&mut self, target: &Expr, _operator: &Token, value: &Expr, _postfix: bool) -> Result<(), RuntimeError> {
+        self.resolve_expr(target)?;
+        self.resolve_expr(value)
+    }
+
+    fn visit_interpolated(&mut self, expression: &Expr) -> Result<(), RuntimeError> {
+        self.resolve_expr(expression)
+    }
 }
 
 impl StatementVisitor<()> for Resolver {
@@ -139,15 +195,15 @@ impl StatementVisitor<()> for Resolver {
         Ok(())
     }
     fn visit_var_declaration(&mut self, name: &Token, initializer: &Expr) -> Result<(), RuntimeError> {
-        self.declare(name);
+        self.declare(name)?;
         self.resolve_expr(initializer)?; 
         self.define(name);
         self.unused_variables.push(name.lexeme.clone());
         Ok(())
     }
     
-    fn visit_function(&mut self, token: &Token, params: &[Token], body: &[Stmt], public: bool, is_static: bool) -> Result<(), RuntimeError> {
-        self.declare(token);
+    fn visit_function(&mut self, token: &Token, params: &[Param], body: &[Stmt], public: bool, is_static: bool) -> Result<(), RuntimeError> {
+        self.declare(token)?;
         self.define(token);
         self.resolve_function(&Stmt::Function { token: token.clone(), params: params.to_vec(), body: body.to_vec(), public, is_static }, FunctionType::FUNCTION)?;
         Ok(())
@@ -168,14 +224,15 @@ impl StatementVisitor<()> for Resolver {
         self.resolve_expr(expression)?;
         Ok(())
     }
-    fn visit_return(&mut self, _keyword: &Token, value: &Expr) -> Result<(), RuntimeError> {
+    fn visit_return(&mut self, keyword: &Token, value: &Expr) -> Result<(), RuntimeError> {
         if self.current_function == FunctionType::NONE {
-            // Lox::print_error("Can't return from top-level code.");
-            return Err(RuntimeError::Return(LoxValue::Nil));
+            return Err(RuntimeError::ReturnOutsideFunction(keyword.clone()));
         }
 
-        if self.current_function == FunctionType::INITIALIZER {
-            return Err(RuntimeError::CantReturnFromInitializer());
+        // A bare `return;` is fine, the instance is returned anyway
+        let bare = matches!(value, Expr::Literal { value: LoxValue::Nil, .. });
+        if self.current_function == FunctionType::INITIALIZER && !bare {
+            return Err(RuntimeError::CantReturnFromInitializer(keyword.clone()));
         }
 
         
@@ -184,19 +241,36 @@ impl StatementVisitor<()> for Resolver {
         self.resolve_expr(value)?;
         Ok(())
     }
-    fn visit_while(&mut self, condition: &Expr, body: &Stmt, else_branch: Option<&Stmt>) -> Result<(), RuntimeError> {
+    fn visit_while(
+        &mut self,
+        condition: &Expr,
+        body: &Stmt,
+        else_branch: Option<&Stmt>,
+        increment: Option<&Expr>,
+        label: Option<&Token>,
+    ) -> Result<(), RuntimeError> {
         self.resolve_expr(condition)?;
+        self.loops.push(label.map(|label| label.lexeme.clone()));
         self.resolve_statement(body)?;
+        if let Some(increment) = increment {
+            self.resolve_expr(increment)?;
+        }
         if let Some(else_b) = else_branch {
             self.resolve_statement(else_b)?;
         }
+        self.loops.pop();
         Ok(())
     }
-    fn visit_break(&mut self) -> Result<(), RuntimeError> {
-        Ok(())
+    fn visit_break(&mut self, keyword: &Token, label: Option<&Token>) -> Result<(), RuntimeError> {
+        self.resolve_jump(keyword, label)
+    }
+    fn visit_continue(&mut self, keyword: &Token, label: Option<&Token>) -> Result<(), RuntimeError> {
+        self.resolve_jump(keyword, label)
     }
-    fn visit_loop(&mut self, body: &Stmt) -> Result<(), RuntimeError> {
+    fn visit_loop(&mut self, body: &Stmt, label: Option<&Token>) -> Result<(), RuntimeError> {
+        self.loops.push(label.map(|label| label.lexeme.clone()));
         self.resolve_statement(body)?;
+        self.loops.pop();
         Ok(())
     }
     fn visit_class(
@@ -208,12 +282,12 @@ impl StatementVisitor<()> for Resolver {
         let enclosing_class = self.current_class;
         self.current_class = ClassType::CLASS;
     
-        self.declare(name);
+        self.declare(name)?;
         self.define(name);
     
         if let Some(Expr::Variable { name: ref super_name, .. }) = super_class {
             if name.lexeme == super_name.lexeme {
-                return Err(RuntimeError::ClassInheritFromItself());
+                return Err(RuntimeError::ClassInheritFromItself(super_name.clone()));
             }
         }
     
@@ -221,15 +295,12 @@ impl StatementVisitor<()> for Resolver {
             self.current_class = ClassType::SUBCLASS;
             self.resolve_expr(expr)?;
             self.begin_scope();
-            if let Some(scope) = self.scopes.last_mut() {
-                scope.insert("super".to_string(), true);
-            }
+            self.declare_implicit("super");
         }
     
+        // Static methods find their class in this slot instead
         self.begin_scope();
-        if let Some(scope) = self.scopes.last_mut() {
-            scope.insert("this".to_string(), true);
-        }
+        self.declare_implicit("this");
     
         for method in methods {
             if let Stmt::Function { token, .. } = method {
@@ -264,7 +335,8 @@ impl Resolver {
             scopes: vec![],
             unused_variables: vec![],
             current_function: FunctionType::NONE,
-            current_class: ClassType::CLASS
+            current_class: ClassType::NONE,
+            loops: vec![]
         };
     
         resolver.begin_scope();
@@ -293,6 +365,19 @@ impl Resolver {
         Ok(())
     }
 
+    // A `break` or `continue` needs a loop around it, with `label` if it has one
+    fn resolve_jump(&mut self, keyword: &Token, label: Option<&Token>) -> Result<(), RuntimeError> {
+        if self.loops.is_empty() {
+            return Err(RuntimeError::OutsideLoop(keyword.clone()));
+        }
+        if let Some(label) = label {
+            if !self.loops.iter().any(|name| name.as_deref() == Some(label.lexeme.as_str())) {
+                return Err(RuntimeError::UndefinedLabel(label.clone()));
+            }
+        }
+        Ok(())
+    }
+
     fn resolve_expr(&mut self, expr: &Expr) -> Result<(), RuntimeError> {
         expr.accept(self)?;
         Ok(())
@@ -300,15 +385,21 @@ impl Resolver {
     fn resolve_function(&mut self, function: &Stmt, ftype: FunctionType) -> Result<(), RuntimeError> {
         if let Stmt::Function { params, body, ..} = function {
             let enclosing_function = self.current_function;
+            let enclosing_loops = std::mem::take(&mut self.loops);
             self.current_function = ftype;
             self.begin_scope();
             for param in params {
-                self.declare(&param);
-                self.define(&param);  
+                // Defaults see the parameters before them, not their own
+                if let Some(default) = &param.default {
+                    self.resolve_expr(default)?;
+                }
+                self.declare(&param.name)?;
+                self.define(&param.name);
             }
-            self.resolve_statements(&body)?;
+            self.resolve_statements(body)?;
             self.end_scope();
             self.current_function = enclosing_function;
+            self.loops = enclosing_loops;
         }
         Ok(())
     }
@@ -321,44 +412,37 @@ impl Resolver {
         self.scopes.pop();
     }
 
-    fn declare(&mut self, name: &Token) {
-        if self.scopes.is_empty() {
-            return;  
-        }
-    
+    fn declare(&mut self, name: &Token) -> Result<(), RuntimeError> {
         if let Some(scope) = self.scopes.last_mut() {
-            if scope.contains_key(&name.lexeme) {
-                Lox::print_error(&format!(
-                    "Already exists a variable called [{}] at the current scope.",
-                    &name.lexeme
-                ));
-                return;  
+            if let Some(previous) = scope.get(&name.lexeme) {
+                return Err(RuntimeError::VariableAlreadyDeclared(name.clone(), previous.span));
             }
-    
-            scope.insert(name.lexeme.clone(), false);
-        } else {
-            Lox::print_error("Doens't exists valid scope to declare this variable.");
+            let slot = scope.len();
+            scope.insert(name.lexeme.clone(), Local { defined: false, span: name.span, slot });
         }
+        Ok(())
     }
-    
 
     fn define(&mut self, name: &Token) {
+        if let Some(local) = self.scopes.last_mut().and_then(|scope| scope.get_mut(&name.lexeme)) {
+            local.defined = true;
+        }
+    }
+
+    // `this` and `super`, bound by the interpreter rather than declared
+    fn declare_implicit(&mut self, name: &str) {
         if let Some(scope) = self.scopes.last_mut() {
-            scope.insert(name.lexeme.clone(), true);
-        } else {
-            Lox::print_error("Doens't exists a valid scope to define this variable.");
+            let slot = scope.len();
+            scope.insert(name.to_string(), Local { defined: true, span: Span::default(), slot });
         }
     }
-    
 
-    fn resolve_local(&self, expr: &Expr, name: &Token) {
-        for i in 0..self.scopes.len() {
-            if let Some(scope) = self.scopes.get(i) {
-                if scope.contains_key(&name.lexeme) {
-                    let distance = self.scopes.len() - 1 - i;
-                    self.interpreter.borrow_mut().resolve(expr.clone(), distance);
-                    return;
-                }
+    fn resolve_local(&self, id: usize, name: &Token) {
+        let innermost = self.scopes.len() - 1;
+        for (i, scope) in self.scopes.iter().enumerate().skip(1).rev() {
+            if let Some(local) = scope.get(&name.lexeme) {
+                self.interpreter.borrow_mut().resolve(id, innermost - i, local.slot);
+                return;
             }
         }
     }
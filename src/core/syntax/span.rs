@@ -0,0 +1,44 @@
+use std::fmt;
+
+/// A range of source text. `start`/`end` are byte offsets into the file
+/// identified by `file_id`; `line` and `column` (1-based, in characters)
+/// locate `start`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct Span {
+    pub file_id: usize,
+    pub start: usize,
+    pub end: usize,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Span {
+    pub fn new(file_id: usize, start: usize, end: usize, line: usize, column: usize) -> Self {
+        Self { file_id, start, end, line, column }
+    }
+
+    /// The smallest span covering both `self` and `other`.
+    pub fn to(self, other: Span) -> Span {
+        if other.start < self.start {
+            return other.to(self);
+        }
+        Span {
+            end: self.end.max(other.end),
+            ..self
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.end - self.start
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.start == self.end
+    }
+}
+
+impl fmt::Display for Span {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}", self.line, self.column)
+    }
+}
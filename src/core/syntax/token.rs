@@ -1,31 +1,28 @@
 use std::fmt::Display;
 
-use super::{components::expression::LoxValue, token_type::TokenType};
+use super::{components::expression::LoxValue, span::Span, token_type::TokenType};
 
-#[derive(Debug, Clone, PartialEq, Hash, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Token {
     pub t_type: TokenType,
     pub lexeme: String,
     pub literal: LoxValue,
     pub line: usize,
+    pub span: Span,
 }
 
 impl Token {
-    pub fn new(t_type: TokenType, lexeme: String, literal: LoxValue, line: usize) -> Token {
+    pub fn new(t_type: TokenType, lexeme: String, literal: LoxValue, span: Span) -> Token {
         Token {
             t_type,
             lexeme,
             literal,
-            line,
+            line: span.line,
+            span,
         }
     }
-    pub fn from(t_type: TokenType, lexeme: String, literal: LoxValue, line: usize) -> Token {
-        Token {
-            t_type,
-            lexeme,
-            literal,
-            line,
-        }
+    pub fn from(t_type: TokenType, lexeme: String, literal: LoxValue, span: Span) -> Token {
+        Token::new(t_type, lexeme, literal, span)
     }
 }
 
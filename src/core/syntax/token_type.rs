@@ -1,24 +1,35 @@
 use std::fmt;
+use std::hash::{Hash, Hasher};
 
 #[allow(non_camel_case_types)]
-#[derive(Debug, Clone, Eq, Hash)]
+#[derive(Debug, Clone, Eq)]
 pub enum TokenType {
     // Single-character tokens.
-    LEFT_PAREN, RIGHT_PAREN, LEFT_BRACE, RIGHT_BRACE,
+    LEFT_PAREN, RIGHT_PAREN, LEFT_BRACE, RIGHT_BRACE, LEFT_BRACKET, RIGHT_BRACKET,
     COMMA, DOT, MINUS, PLUS, SEMICOLON, SLASH, STAR, QUESTION_MARK, COLON,
+    PERCENT, AMPERSAND, PIPE, CARET,
 
     // One or two character tokens.
     BANG, BANG_EQUAL,
-    EQUAL, EQUAL_EQUAL,
+    EQUAL, EQUAL_EQUAL, ARROW,
     GREATER, GREATER_EQUAL,
     LESS, LESS_EQUAL,
+    STAR_STAR, TILDE, TILDE_SLASH,
+    LESS_LESS, GREATER_GREATER,
+    PLUS_EQUAL, MINUS_EQUAL, STAR_EQUAL, SLASH_EQUAL, PERCENT_EQUAL,
+    PLUS_PLUS, MINUS_MINUS,
+
+    // Three character tokens.
+    DOT_DOT_DOT,
 
     // Literals.
     IDENTIFIER, STRING, NUMBER,
+    // The text of a string up to a `${`
+    INTERPOLATION,
 
     // Keywords.
     AND, CLASS, ELSE, FALSE, FN, FOR, IF, NIL, OR,
-    PRINT, RETURN, SUPER, THIS, TRUE, VAR, WHILE, LOOP, BREAK,
+    PRINT, RETURN, SUPER, THIS, TRUE, VAR, WHILE, LOOP, BREAK, CONTINUE,
     STATIC, PUB,
 
     EOF
@@ -30,6 +41,12 @@ impl PartialEq for TokenType {
     }
 }
 
+impl Hash for TokenType {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        std::mem::discriminant(self).hash(state);
+    }
+}
+
 impl fmt::Display for TokenType {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         let token_str = match self {
@@ -37,6 +54,8 @@ impl fmt::Display for TokenType {
             TokenType::RIGHT_PAREN => "right_paren",
             TokenType::LEFT_BRACE => "left_brace",
             TokenType::RIGHT_BRACE => "right_brace",
+            TokenType::LEFT_BRACKET => "left_bracket",
+            TokenType::RIGHT_BRACKET => "right_bracket",
             TokenType::COMMA => "comma",
             TokenType::DOT => "dot",
             TokenType::MINUS => "minus",
@@ -46,17 +65,36 @@ impl fmt::Display for TokenType {
             TokenType::STAR => "star",
             TokenType::QUESTION_MARK => "question_mark",
             TokenType::COLON => "colon",
+            TokenType::PERCENT => "percent",
+            TokenType::AMPERSAND => "ampersand",
+            TokenType::PIPE => "pipe",
+            TokenType::CARET => "caret",
             TokenType::BANG => "bang",
             TokenType::BANG_EQUAL => "bang_equal",
             TokenType::EQUAL => "equal",
             TokenType::EQUAL_EQUAL => "equal_equal",
+            TokenType::ARROW => "arrow",
             TokenType::GREATER => "greater",
             TokenType::GREATER_EQUAL => "greater_equal",
             TokenType::LESS => "less",
             TokenType::LESS_EQUAL => "less_equal",
+            TokenType::STAR_STAR => "star_star",
+            TokenType::TILDE => "tilde",
+            TokenType::TILDE_SLASH => "tilde_slash",
+            TokenType::LESS_LESS => "less_less",
+            TokenType::GREATER_GREATER => "greater_greater",
+            TokenType::PLUS_EQUAL => "plus_equal",
+            TokenType::MINUS_EQUAL => "minus_equal",
+            TokenType::STAR_EQUAL => "star_equal",
+            TokenType::SLASH_EQUAL => "slash_equal",
+            TokenType::PERCENT_EQUAL => "percent_equal",
+            TokenType::PLUS_PLUS => "plus_plus",
+            TokenType::MINUS_MINUS => "minus_minus",
+            TokenType::DOT_DOT_DOT => "dot_dot_dot",
             TokenType::IDENTIFIER => "identifier",
             TokenType::STRING => "string",
             TokenType::NUMBER => "number",
+            TokenType::INTERPOLATION => "interpolation",
             TokenType::AND => "and",
             TokenType::CLASS => "class",
             TokenType::ELSE => "else",
@@ -76,61 +114,10 @@ impl fmt::Display for TokenType {
             TokenType::EOF => "eof",
             TokenType::LOOP => "loop",
             TokenType::BREAK => "break",
+            TokenType::CONTINUE => "continue",
             TokenType::PUB => "pub",
             TokenType::STATIC => "static"
         };
         write!(f, "{}", token_str)
     }
 }
-
-impl TokenType {
-    pub fn to_string(&self) -> String {
-        match self {
-            TokenType::LEFT_PAREN => "left_paren".to_string(),
-            TokenType::RIGHT_PAREN => "right_paren".to_string(),
-            TokenType::LEFT_BRACE => "left_brace".to_string(),
-            TokenType::RIGHT_BRACE => "right_brace".to_string(),
-            TokenType::COMMA => "comma".to_string(),
-            TokenType::DOT => "dot".to_string(),
-            TokenType::MINUS => "minus".to_string(),
-            TokenType::PLUS => "plus".to_string(),
-            TokenType::SEMICOLON => "semicolon".to_string(),
-            TokenType::SLASH => "slash".to_string(),
-            TokenType::STAR => "star".to_string(),
-            TokenType::QUESTION_MARK => "question_mark".to_string(),
-            TokenType::COLON => "colon".to_string(),
-            TokenType::BANG => "bang".to_string(),
-            TokenType::BANG_EQUAL => "bang_equal".to_string(),
-            TokenType::EQUAL => "equal".to_string(),
-            TokenType::EQUAL_EQUAL => "equal_equal".to_string(),
-            TokenType::GREATER => "greater".to_string(),
-            TokenType::GREATER_EQUAL => "greater_equal".to_string(),
-            TokenType::LESS => "less".to_string(),
-            TokenType::LESS_EQUAL => "less_equal".to_string(),
-            TokenType::IDENTIFIER => "identifier".to_string(),
-            TokenType::STRING => "string".to_string(),
-            TokenType::NUMBER => "number".to_string(),
-            TokenType::AND => "and".to_string(),
-            TokenType::CLASS => "class".to_string(),
-            TokenType::ELSE => "else".to_string(),
-            TokenType::FALSE => "false".to_string(),
-            TokenType::FN => "fun".to_string(),
-            TokenType::FOR => "for".to_string(),
-            TokenType::IF => "if".to_string(),
-            TokenType::NIL => "nil".to_string(),
-            TokenType::OR => "or".to_string(),
-            TokenType::PRINT => "print".to_string(),
-            TokenType::RETURN => "return".to_string(),
-            TokenType::SUPER => "super".to_string(),
-            TokenType::THIS => "this".to_string(),
-            TokenType::TRUE => "true".to_string(),
-            TokenType::VAR => "var".to_string(),
-            TokenType::WHILE => "while".to_string(),
-            TokenType::EOF => "eof".to_string(),
-            TokenType::LOOP => "loop".to_string(),
-            TokenType::BREAK => "break".to_string(),
-            TokenType::PUB => "pub".to_string(),
-            TokenType::STATIC => "static".to_string()
-        }
-    }
-}
@@ -0,0 +1,126 @@
+use rustc_hash::FxHashMap;
+
+use crate::core::syntax::span::Span;
+use crate::core::syntax::token::Token;
+
+use super::value::Value;
+
+/// One VM instruction. Operands index the chunk's constant table (`u16`),
+/// the current frame's stack slots or upvalues (`u8`), or the code itself
+/// (`u32` jump targets).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OpCode {
+    Constant(u16),
+    Nil,
+    True,
+    False,
+    Pop,
+    // Copy the top value, or the top two, for updates like `a[i] += 1`
+    // that read and then write the same target
+    Dup,
+    DupPair,
+    // Moves the top value under the `n` values below it
+    Bury(u8),
+    GetLocal(u8),
+    SetLocal(u8),
+    GetGlobal(u16),
+    DefineGlobal(u16),
+    SetGlobal(u16),
+    GetUpvalue(u8),
+    SetUpvalue(u8),
+    // `this` is set when the object is written as `this`, which may reach
+    // private methods
+    GetProperty { name: u16, this: bool },
+    SetProperty(u16),
+    GetSuper(u16),
+    Equal,
+    NotEqual,
+    Greater,
+    GreaterEqual,
+    Less,
+    LessEqual,
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    FloorDivide,
+    Modulo,
+    Power,
+    BitAnd,
+    BitOr,
+    BitXor,
+    ShiftLeft,
+    ShiftRight,
+    Not,
+    Negate,
+    BitNot,
+    // Replaces the value on top of the stack with its text, for `${}`
+    Stringify,
+    Print,
+    Jump(u32),
+    JumpIfFalse(u32),
+    JumpIfTrue(u32),
+    // Skips the code computing the default of a parameter the caller passed
+    JumpIfPassed { param: u8, target: u32 },
+    Call(u8),
+    // `object.name(args)` without allocating a bound method
+    Invoke { name: u16, argc: u8, this: bool },
+    SuperInvoke { name: u16, argc: u8 },
+    Closure(u16),
+    CloseUpvalue,
+    Return,
+    Class(u16),
+    Inherit,
+    // Adds the closure on top of the stack to the class under it
+    Method,
+    // Collects that many values off the stack into a new list
+    BuildList(u32),
+    // Collects that many key/value pairs off the stack into a new map
+    BuildMap(u32),
+    GetIndex,
+    SetIndex,
+    // Which bounds of `object[start:end]` were written, and so are on the stack
+    Slice { start: bool, end: bool },
+}
+
+/// Compiled code of one function.
+#[derive(Debug, Clone, Default)]
+pub struct Chunk {
+    pub code: Vec<OpCode>,
+    pub constants: Vec<Value>,
+    // Source location of every instruction, for tracebacks
+    pub spans: Vec<Span>,
+    // The token behind each instruction that can fail, for error messages
+    tokens: FxHashMap<usize, Token>,
+}
+
+impl Chunk {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Appends `op` and returns its offset.
+    pub fn write(&mut self, op: OpCode, span: Span) -> usize {
+        self.code.push(op);
+        self.spans.push(span);
+        self.code.len() - 1
+    }
+
+    /// Like `write`, remembering `token` for errors raised by `op`.
+    pub fn write_with_token(&mut self, op: OpCode, span: Span, token: &Token) -> usize {
+        let offset = self.write(op, span);
+        self.tokens.insert(offset, token.clone());
+        offset
+    }
+
+    pub fn token(&self, offset: usize) -> Option<&Token> {
+        self.tokens.get(&offset)
+    }
+
+    /// Adds `value` to the constant table, or `None` once it is full.
+    pub fn add_constant(&mut self, value: Value) -> Option<u16> {
+        let index = u16::try_from(self.constants.len()).ok()?;
+        self.constants.push(value);
+        Some(index)
+    }
+}
@@ -0,0 +1,814 @@
+use std::rc::Rc;
+
+use rustc_hash::FxHashMap;
+
+use super::chunk::OpCode;
+use super::value::{Function, FunctionKind, UpvalueRef, Value};
+use crate::core::error_types::runtime_error::RuntimeError;
+use crate::core::syntax::components::expression::{Expr, LoxValue, Visitor as ExpressionVisitor};
+use crate::core::syntax::components::stmt::{Param, Stmt, Visitor as StatementVisitor};
+use crate::core::syntax::span::Span;
+use crate::core::syntax::token::Token;
+use crate::core::syntax::token_type::TokenType;
+
+// A local variable living in a stack slot of the function being compiled
+struct Local {
+    name: String,
+    depth: usize,
+    // Closed over by a nested function, so leaving its scope must move it
+    // off the stack
+    captured: bool,
+}
+
+struct Loop {
+    label: Option<String>,
+    // Scope depth outside the loop body; `break` and `continue` pop every
+    // local deeper
+    depth: usize,
+    breaks: Vec<usize>,
+    // Jumps to the end of the current iteration, patched once it is known
+    continues: Vec<usize>,
+}
+
+struct FunctionState {
+    function: Function,
+    locals: Vec<Local>,
+    scope_depth: usize,
+    loops: Vec<Loop>,
+    // Name constants already in the chunk
+    identifiers: FxHashMap<String, u16>,
+}
+
+/// Lowers resolved statements into bytecode for the `Vm`.
+///
+/// The resolver has already rejected invalid programs, so the only errors
+/// raised here are the per-function limits of the bytecode format.
+pub struct Compiler {
+    functions: Vec<FunctionState>,
+    // Location given to instructions without a token of their own
+    span: Span,
+}
+
+impl Compiler {
+    /// Compiles a script into the function the VM starts from. It returns
+    /// the value of the last statement when that is an expression
+    /// statement, `nil` otherwise, like `Interpreter::interpret`.
+    pub fn compile(statements: &[Stmt]) -> Result<Function, RuntimeError> {
+        let mut compiler = Self {
+            functions: vec![],
+            span: Span::default(),
+        };
+        compiler.begin_function("script", FunctionKind::Script);
+
+        for (i, statement) in statements.iter().enumerate() {
+            match statement {
+                Stmt::Expression { expression } if i == statements.len() - 1 => {
+                    compiler.span = statement.span();
+                    compiler.compile_expr(expression)?;
+                    compiler.emit(OpCode::Return);
+                }
+                _ => compiler.compile_stmt(statement)?,
+            }
+        }
+
+        Ok(compiler.end_function())
+    }
+
+    fn compile_stmt(&mut self, statement: &Stmt) -> Result<(), RuntimeError> {
+        self.span = statement.span();
+        statement.accept(self)
+    }
+
+    fn compile_expr(&mut self, expr: &Expr) -> Result<(), RuntimeError> {
+        expr.accept(self)
+    }
+
+    fn current(&mut self) -> &mut FunctionState {
+        self.functions.last_mut().expect("no function being compiled")
+    }
+
+    fn emit(&mut self, op: OpCode) -> usize {
+        let span = self.span;
+        self.current().function.chunk.write(op, span)
+    }
+
+    fn emit_with(&mut self, op: OpCode, token: &Token) -> usize {
+        self.emit_at(op, token.span, token)
+    }
+
+    fn emit_at(&mut self, op: OpCode, span: Span, token: &Token) -> usize {
+        self.current().function.chunk.write_with_token(op, span, token)
+    }
+
+    fn make_constant(&mut self, value: Value) -> Result<u16, RuntimeError> {
+        let span = self.span;
+        self.current()
+            .function
+            .chunk
+            .add_constant(value)
+            .ok_or(RuntimeError::TooManyConstants(span))
+    }
+
+    fn identifier(&mut self, name: &str) -> Result<u16, RuntimeError> {
+        if let Some(index) = self.current().identifiers.get(name) {
+            return Ok(*index);
+        }
+        let index = self.make_constant(Value::String(name.into()))?;
+        self.current().identifiers.insert(name.to_string(), index);
+        Ok(index)
+    }
+
+    // The current offset, as a jump target
+    fn here(&mut self) -> u32 {
+        self.current().function.chunk.code.len() as u32
+    }
+
+    /// Points the jump at `offset` to the next instruction.
+    fn patch_jump(&mut self, offset: usize) {
+        let target = self.here();
+        let code = &mut self.current().function.chunk.code;
+        code[offset] = match code[offset] {
+            OpCode::Jump(_) => OpCode::Jump(target),
+            OpCode::JumpIfFalse(_) => OpCode::JumpIfFalse(target),
+            OpCode::JumpIfTrue(_) => OpCode::JumpIfTrue(target),
+            OpCode::JumpIfPassed { param, .. } => OpCode::JumpIfPassed { param, target },
+            op => op,
+        };
+    }
+
+    fn begin_function(&mut self, name: &str, kind: FunctionKind) {
+        // Slot 0 holds the callee, or the receiver in methods
+        let receiver = match kind {
+            FunctionKind::Method | FunctionKind::Initializer => "this",
+            FunctionKind::Function | FunctionKind::Script => "",
+        };
+        self.functions.push(FunctionState {
+            function: Function::new(name, kind),
+            locals: vec![Local { name: receiver.to_string(), depth: 0, captured: false }],
+            scope_depth: 0,
+            loops: vec![],
+            identifiers: FxHashMap::default(),
+        });
+    }
+
+    fn end_function(&mut self) -> Function {
+        self.emit_implicit_return();
+        self.functions.pop().expect("no function being compiled").function
+    }
+
+    fn emit_implicit_return(&mut self) {
+        if self.current().function.kind == FunctionKind::Initializer {
+            self.emit(OpCode::GetLocal(0));
+        } else {
+            self.emit(OpCode::Nil);
+        }
+        self.emit(OpCode::Return);
+    }
+
+    fn begin_scope(&mut self) {
+        self.current().scope_depth += 1;
+    }
+
+    fn end_scope(&mut self) {
+        let state = self.current();
+        state.scope_depth -= 1;
+        let depth = state.scope_depth;
+
+        while let Some(local) = self.current().locals.last() {
+            if local.depth <= depth {
+                break;
+            }
+            let op = if local.captured { OpCode::CloseUpvalue } else { OpCode::Pop };
+            self.emit(op);
+            self.current().locals.pop();
+        }
+    }
+
+    fn add_local(&mut self, name: &Token) -> Result<(), RuntimeError> {
+        let state = self.current();
+        if state.locals.len() > u8::MAX as usize {
+            return Err(RuntimeError::TooManyLocals(name.span));
+        }
+        let depth = state.scope_depth;
+        state.locals.push(Local { name: name.lexeme.clone(), depth, captured: false });
+        Ok(())
+    }
+
+    /// Binds the value on top of the stack to `name`: a new local inside a
+    /// scope, a global otherwise.
+    fn define_variable(&mut self, name: &Token) -> Result<(), RuntimeError> {
+        if self.current().scope_depth > 0 {
+            return self.add_local(name);
+        }
+        let index = self.identifier(&name.lexeme)?;
+        self.emit_with(OpCode::DefineGlobal(index), name);
+        Ok(())
+    }
+
+    fn resolve_local(state: &FunctionState, name: &str) -> Option<u8> {
+        state
+            .locals
+            .iter()
+            .rposition(|local| local.name == name)
+            .map(|slot| slot as u8)
+    }
+
+    // Finds `name` in the functions enclosing `level`, capturing it on the way
+    fn resolve_upvalue(&mut self, level: usize, name: &Token) -> Result<Option<u8>, RuntimeError> {
+        if level == 0 {
+            return Ok(None);
+        }
+        let enclosing = level - 1;
+
+        if let Some(slot) = Self::resolve_local(&self.functions[enclosing], &name.lexeme) {
+            self.functions[enclosing].locals[slot as usize].captured = true;
+            return self.add_upvalue(level, slot, true, name).map(Some);
+        }
+        if let Some(index) = self.resolve_upvalue(enclosing, name)? {
+            return self.add_upvalue(level, index, false, name).map(Some);
+        }
+        Ok(None)
+    }
+
+    fn add_upvalue(&mut self, level: usize, index: u8, is_local: bool, name: &Token) -> Result<u8, RuntimeError> {
+        let upvalues = &mut self.functions[level].function.upvalues;
+        if let Some(existing) = upvalues.iter().position(|u| u.index == index && u.is_local == is_local) {
+            return Ok(existing as u8);
+        }
+        if upvalues.len() > u8::MAX as usize {
+            return Err(RuntimeError::TooManyUpvalues(name.span));
+        }
+        upvalues.push(UpvalueRef { index, is_local });
+        Ok((upvalues.len() - 1) as u8)
+    }
+
+    // Reads (or, with `assign`, writes the value on top of the stack to)
+    // the variable called `name`
+    fn binary_op(operator: &Token) -> Result<OpCode, RuntimeError> {
+        Ok(match operator.t_type {
+            TokenType::PLUS => OpCode::Add,
+            TokenType::MINUS => OpCode::Subtract,
+            TokenType::STAR => OpCode::Multiply,
+            TokenType::SLASH => OpCode::Divide,
+            TokenType::TILDE_SLASH => OpCode::FloorDivide,
+            TokenType::PERCENT => OpCode::Modulo,
+            TokenType::STAR_STAR => OpCode::Power,
+            TokenType::AMPERSAND => OpCode::BitAnd,
+            TokenType::PIPE => OpCode::BitOr,
+            TokenType::CARET => OpCode::BitXor,
+            TokenType::LESS_LESS => OpCode::ShiftLeft,
+            TokenType::GREATER_GREATER => OpCode::ShiftRight,
+            TokenType::GREATER => OpCode::Greater,
+            TokenType::GREATER_EQUAL => OpCode::GreaterEqual,
+            TokenType::LESS => OpCode::Less,
+            TokenType::LESS_EQUAL => OpCode::LessEqual,
+            TokenType::EQUAL_EQUAL => OpCode::Equal,
+            TokenType::BANG_EQUAL => OpCode::NotEqual,
+            _ => {
+                return Err(RuntimeError::BadOperator(
+                    operator.clone(),
+                    "Invalid binary operation.".to_string(),
+                ))
+            }
+        })
+    }
+
+    // With the old value of an update's target on top and the `depth`
+    // values its store needs under it, leaves the new value there instead.
+    // A postfix update first buries a copy of the old value under all of
+    // them, as its result once the store is popped.
+    fn update_value(&mut self, operator: &Token, value: &Expr, postfix: bool, depth: u8) -> Result<(), RuntimeError> {
+        if postfix {
+            self.emit(OpCode::Dup);
+            self.emit(OpCode::Bury(depth + 1));
+        }
+        self.compile_expr(value)?;
+        let op = Self::binary_op(operator)?;
+        self.emit_with(op, operator);
+        Ok(())
+    }
+
+    fn named_variable(&mut self, name: &Token, assign: bool) -> Result<(), RuntimeError> {
+        let level = self.functions.len() - 1;
+        let op = if let Some(slot) = Self::resolve_local(&self.functions[level], &name.lexeme) {
+            if assign { OpCode::SetLocal(slot) } else { OpCode::GetLocal(slot) }
+        } else if let Some(index) = self.resolve_upvalue(level, name)? {
+            if assign { OpCode::SetUpvalue(index) } else { OpCode::GetUpvalue(index) }
+        } else {
+            let index = self.identifier(&name.lexeme)?;
+            if assign { OpCode::SetGlobal(index) } else { OpCode::GetGlobal(index) }
+        };
+        self.emit_with(op, name);
+        Ok(())
+    }
+
+    // Leaves a closure for the function on the stack
+    fn function(&mut self, declaration: &Stmt, kind: FunctionKind, class_name: Option<&str>) -> Result<(), RuntimeError> {
+        let Stmt::Function { token, params, body, public, is_static } = declaration else {
+            return Err(RuntimeError::InvalidClassMember());
+        };
+
+        self.begin_function(&token.lexeme, kind);
+        let function = &mut self.current().function;
+        function.params = params.iter().filter(|param| !param.rest).count();
+        function.required = params.iter().filter(|param| param.default.is_none() && !param.rest).count();
+        function.rest = params.last().is_some_and(|param| param.rest);
+        function.is_public = *public;
+        function.is_static = *is_static;
+        function.class_name = class_name.map(str::to_string);
+
+        self.begin_scope();
+        // The VM leaves missing arguments as `nil` and the extra ones in a
+        // list, so only defaults need code. Each is compiled before its own
+        // parameter is declared, like the resolver sees it.
+        for (index, param) in params.iter().enumerate() {
+            if let Some(default) = &param.default {
+                let skip = self.emit(OpCode::JumpIfPassed { param: index as u8, target: 0 });
+                self.compile_expr(default)?;
+                self.emit(OpCode::SetLocal(index as u8 + 1));
+                self.emit(OpCode::Pop);
+                self.patch_jump(skip);
+            }
+            self.add_local(&param.name)?;
+        }
+        for statement in body {
+            self.compile_stmt(statement)?;
+        }
+        let function = self.end_function();
+
+        self.span = token.span;
+        let index = self.make_constant(Value::Function(Rc::new(function)))?;
+        self.emit_with(OpCode::Closure(index), token);
+        Ok(())
+    }
+
+    // `this` and `super` are locals named after their keyword
+    fn keyword(keyword: &Token, t_type: TokenType, lexeme: &str) -> Token {
+        Token::new(t_type, lexeme.to_string(), LoxValue::Nil, keyword.span)
+    }
+}
+
+impl ExpressionVisitor<()> for Compiler {
+    fn visit_binary(&mut self, left: &Expr, operator: &Token, right: &Expr) -> Result<(), RuntimeError> {
+        self.compile_expr(left)?;
+        self.compile_expr(right)?;
+        let op = Self::binary_op(operator)?;
+        self.emit_with(op, operator);
+        Ok(())
+    }
+
+    fn visit_call(&mut self, callee: &Expr, paren: &Token, arguments: &[Expr]) -> Result<(), RuntimeError> {
+        let argc = arguments.len() as u8;
+        match callee {
+            Expr::Get { object, name } => {
+                self.compile_expr(object)?;
+                for argument in arguments {
+                    self.compile_expr(argument)?;
+                }
+                let index = self.identifier(&name.lexeme)?;
+                let this = matches!(**object, Expr::This { .. });
+                self.emit_at(OpCode::Invoke { name: index, argc, this }, paren.span, name);
+            }
+            Expr::Super { keyword, method, .. } => {
+                self.named_variable(&Self::keyword(keyword, TokenType::THIS, "this"), false)?;
+                for argument in arguments {
+                    self.compile_expr(argument)?;
+                }
+                self.named_variable(keyword, false)?;
+                let index = self.identifier(&method.lexeme)?;
+                self.emit_at(OpCode::SuperInvoke { name: index, argc }, paren.span, method);
+            }
+            _ => {
+                self.compile_expr(callee)?;
+                for argument in arguments {
+                    self.compile_expr(argument)?;
+                }
+                self.emit_with(OpCode::Call(argc), paren);
+            }
+        }
+        Ok(())
+    }
+
+    fn visit_grouping(&mut self, expression: &Expr) -> Result<(), RuntimeError> {
+        self.compile_expr(expression)
+    }
+
+    fn visit_literal(&mut self, value: &LoxValue) -> Result<(), RuntimeError> {
+        match value {
+            LoxValue::Boolean(true) => self.emit(OpCode::True),
+            LoxValue::Boolean(false) => self.emit(OpCode::False),
+            LoxValue::Number(n) => {
+                let index = self.make_constant(Value::Number(*n))?;
+                self.emit(OpCode::Constant(index))
+            }
+            LoxValue::Integer(n) => {
+                let index = self.make_constant(Value::Integer(*n))?;
+                self.emit(OpCode::Constant(index))
+            }
+            LoxValue::String(s) => {
+                let index = self.make_constant(Value::String(s.as_str().into()))?;
+                self.emit(OpCode::Constant(index))
+            }
+            _ => self.emit(OpCode::Nil),
+        };
+        Ok(())
+    }
+
+    // The tree-walker never evaluates the left operand
+    fn visit_comma(&mut self, _left: &Expr, right: &Expr) -> Result<(), RuntimeError> {
+        self.compile_expr(right)
+    }
+
+    fn visit_unary(&mut self, operator: &Token, right: &Expr) -> Result<(), RuntimeError> {
+        self.compile_expr(right)?;
+        let op = match operator.t_type {
+            TokenType::MINUS => OpCode::Negate,
+            TokenType::BANG => OpCode::Not,
+            TokenType::TILDE => OpCode::BitNot,
+            _ => {
+                return Err(RuntimeError::BadOperator(
+                    operator.clone(),
+                    "Invalid unary operator.".to_string(),
+                ))
+            }
+        };
+        self.emit_with(op, operator);
+        Ok(())
+    }
+
+    fn visit_ternary(&mut self, condition: &Expr, then_branch: &Expr, else_branch: &Expr) -> Result<(), RuntimeError> {
+        self.compile_expr(condition)?;
+        let else_jump = self.emit(OpCode::JumpIfFalse(0));
+        self.emit(OpCode::Pop);
+        self.compile_expr(then_branch)?;
+        let end_jump = self.emit(OpCode::Jump(0));
+        self.patch_jump(else_jump);
+        self.emit(OpCode::Pop);
+        self.compile_expr(else_branch)?;
+        self.patch_jump(end_jump);
+        Ok(())
+    }
+
+    fn visit_variable(&mut self, _id: usize, name: &Token) -> Result<(), RuntimeError> {
+        self.named_variable(name, false)
+    }
+
+    fn visit_assing(&mut self, _id: usize, name: &Token, value: &Expr) -> Result<(), RuntimeError> {
+        self.compile_expr(value)?;
+        self.named_variable(name, true)
+    }
+
+    // Leaves the deciding operand on the stack when it short-circuits
+    fn visit_logical(&mut self, left: &Expr, operator: &Token, right: &Expr) -> Result<(), RuntimeError> {
+        self.compile_expr(left)?;
+        let jump = if operator.t_type == TokenType::OR {
+            self.emit(OpCode::JumpIfTrue(0))
+        } else {
+            self.emit(OpCode::JumpIfFalse(0))
+        };
+        self.emit(OpCode::Pop);
+        self.compile_expr(right)?;
+        self.patch_jump(jump);
+        Ok(())
+    }
+
+    fn visit_get(&mut self, name: &Token, object: &Expr) -> Result<(), RuntimeError> {
+        self.compile_expr(object)?;
+        let index = self.identifier(&name.lexeme)?;
+        let this = matches!(object, Expr::This { .. });
+        self.emit_with(OpCode::GetProperty { name: index, this }, name);
+        Ok(())
+    }
+
+    fn visit_set(&mut self, object: &Expr, name: &Token, value: &Expr) -> Result<(), RuntimeError> {
+        self.compile_expr(object)?;
+        self.compile_expr(value)?;
+        let index = self.identifier(&name.lexeme)?;
+        self.emit_with(OpCode::SetProperty(index), name);
+        Ok(())
+    }
+
+    fn visit_this(&mut self, _id: usize, keyword: &Token) -> Result<(), RuntimeError> {
+        self.named_variable(keyword, false)
+    }
+
+    fn visit_super(&mut self, _id: usize, keyword: &Token, method: &Token) -> Result<(), RuntimeError> {
+        self.named_variable(&Self::keyword(keyword, TokenType::THIS, "this"), false)?;
+        self.named_variable(keyword, false)?;
+        let index = self.identifier(&method.lexeme)?;
+        self.emit_with(OpCode::GetSuper(index), method);
+        Ok(())
+    }
+
+    fn visit_lambda(&mut self, keyword: &Token, params: &[Param], body: &[Stmt]) -> Result<(), RuntimeError> {
+        self.function(&Stmt::anonymous_function(keyword, params, body), FunctionKind::Function, None)
+    }
+
+    fn visit_list(&mut self, bracket: &Token, elements: &[Expr]) -> Result<(), RuntimeError> {
+        for element in elements {
+            self.compile_expr(element)?;
+        }
+        self.emit_with(OpCode::BuildList(elements.len() as u32), bracket);
+        Ok(())
+    }
+
+    fn visit_map(&mut self, brace: &Token, entries: &[(Expr, Expr)]) -> Result<(), RuntimeError> {
+        for (key, value) in entries {
+            self.compile_expr(key)?;
+            self.compile_expr(value)?;
+        }
+        self.emit_with(OpCode::BuildMap(entries.len() as u32), brace);
+        Ok(())
+    }
+
+    fn visit_index(&mut self, object: &Expr, bracket: &Token, index: &Expr) -> Result<(), RuntimeError> {
+        self.compile_expr(object)?;
+        self.compile_expr(index)?;
+        self.emit_with(OpCode::GetIndex, bracket);
+        Ok(())
+    }
+
+    fn visit_set_index(&mut self, object: &Expr, bracket: &Token, index: &Expr, value: &Expr) -> Result<(), RuntimeError> {
+        self.compile_expr(object)?;
+        self.compile_expr(index)?;
+        self.compile_expr(value)?;
+        self.emit_with(OpCode::SetIndex, bracket);
+        Ok(())
+    }
+
+    fn visit_slice(&mut self, object: &Expr, bracket: &Token, start: Option<&Expr>, end: Option<&Expr>) -> Result<(), RuntimeError> {
+        self.compile_expr(object)?;
+        for bound in start.iter().chain(end.iter()) {
+            self.compile_expr(bound)?;
+        }
+        self.emit_with(OpCode::Slice { start: start.is_some(), end: end.is_some() }, bracket);
+        Ok(())
+    }
+
+    fn visit_update(&mut self, target: &Expr, operator: &Token, value: &Expr, postfix: bool) -> Result<(), RuntimeError> {
+        match target {
+            Expr::Variable { name, .. } => {
+                self.named_variable(name, false)?;
+                self.update_value(operator, value, postfix, 0)?;
+                self.named_variable(name, true)?;
+            }
+            Expr::Get { object, name } => {
+                self.compile_expr(object)?;
+                self.emit(OpCode::Dup);
+                let index = self.identifier(&name.lexeme)?;
+                let this = matches!(**object, Expr::This { .. });
+                self.emit_with(OpCode::GetProperty { name: index, this }, name);
+                self.update_value(operator, value, postfix, 1)?;
+                self.emit_with(OpCode::SetProperty(index), name);
+            }
+            Expr::Index { object, bracket, index, .. } => {
+                self.compile_expr(object)?;
+                self.compile_expr(index)?;
+                self.emit(OpCode::DupPair);
+                self.emit_with(OpCode::GetIndex, bracket);
+                self.update_value(operator, value, postfix, 2)?;
+                self.emit_with(OpCode::SetIndex, bracket);
+            }
+            _ => unreachable!("the parser only builds updates of assignable targets"),
+        }
+        if postfix {
+            self.emit(OpCode::Pop);
+        }
+        Ok(())
+    }
+
+    fn visit_interpolated(&mut self, expression: &Expr) -> Result<(), RuntimeError> {
+        self.compile_expr(expression)?;
+        self.emit(OpCode::Stringify);
+        Ok(())
+    }
+}
+
+impl StatementVisitor<()> for Compiler {
+    fn visit_expression(&mut self, expression: &Expr) -> Result<(), RuntimeError> {
+        self.compile_expr(expression)?;
+        self.emit(OpCode::Pop);
+        Ok(())
+    }
+
+    fn visit_print(&mut self, expression: &Expr) -> Result<(), RuntimeError> {
+        self.compile_expr(expression)?;
+        self.emit(OpCode::Print);
+        Ok(())
+    }
+
+    fn visit_var_declaration(&mut self, name: &Token, initializer: &Expr) -> Result<(), RuntimeError> {
+        self.compile_expr(initializer)?;
+        self.define_variable(name)
+    }
+
+    fn visit_block(&mut self, statements: &[Stmt]) -> Result<(), RuntimeError> {
+        self.begin_scope();
+        for statement in statements {
+            self.compile_stmt(statement)?;
+        }
+        self.end_scope();
+        Ok(())
+    }
+
+    fn visit_if(&mut self, condition: &Expr, then_branch: &Stmt, else_branch: Option<&Stmt>) -> Result<(), RuntimeError> {
+        self.compile_expr(condition)?;
+        let else_jump = self.emit(OpCode::JumpIfFalse(0));
+        self.emit(OpCode::Pop);
+        self.compile_stmt(then_branch)?;
+        let end_jump = self.emit(OpCode::Jump(0));
+        self.patch_jump(else_jump);
+        self.emit(OpCode::Pop);
+        if let Some(else_branch) = else_branch {
+            self.compile_stmt(else_branch)?;
+        }
+        self.patch_jump(end_jump);
+        Ok(())
+    }
+
+    // The `else` branch runs each time the condition is false and the loop
+    // goes on until a `break`, as in the tree-walker. A `continue` in the
+    // body goes to the increment, one in the `else` branch to the condition
+    fn visit_while(
+        &mut self,
+        condition: &Expr,
+        body: &Stmt,
+        else_branch: Option<&Stmt>,
+        increment: Option<&Expr>,
+        label: Option<&Token>,
+    ) -> Result<(), RuntimeError> {
+        let start = self.here();
+        self.begin_loop(label);
+
+        self.compile_expr(condition)?;
+        let exit_jump = self.emit(OpCode::JumpIfFalse(0));
+        self.emit(OpCode::Pop);
+        self.compile_stmt(body)?;
+        self.patch_continues();
+        if let Some(increment) = increment {
+            self.compile_expr(increment)?;
+            self.emit(OpCode::Pop);
+        }
+        self.emit(OpCode::Jump(start));
+
+        self.patch_jump(exit_jump);
+        self.emit(OpCode::Pop);
+        if let Some(else_branch) = else_branch {
+            self.compile_stmt(else_branch)?;
+            self.patch_continues();
+            self.emit(OpCode::Jump(start));
+        }
+
+        self.end_loop();
+        Ok(())
+    }
+
+    fn visit_loop(&mut self, body: &Stmt, label: Option<&Token>) -> Result<(), RuntimeError> {
+        let start = self.here();
+        self.begin_loop(label);
+
+        self.compile_stmt(body)?;
+        self.patch_continues();
+        self.emit(OpCode::Jump(start));
+
+        self.end_loop();
+        Ok(())
+    }
+
+    fn visit_break(&mut self, keyword: &Token, label: Option<&Token>) -> Result<(), RuntimeError> {
+        let (index, jump) = self.jump_out(keyword, label)?;
+        self.current().loops[index].breaks.push(jump);
+        Ok(())
+    }
+
+    fn visit_continue(&mut self, keyword: &Token, label: Option<&Token>) -> Result<(), RuntimeError> {
+        let (index, jump) = self.jump_out(keyword, label)?;
+        self.current().loops[index].continues.push(jump);
+        Ok(())
+    }
+
+    fn visit_function(&mut self, token: &Token, params: &[Param], body: &[Stmt], public: bool, is_static: bool) -> Result<(), RuntimeError> {
+        let declaration = Stmt::Function {
+            token: token.clone(),
+            params: params.to_vec(),
+            body: body.to_vec(),
+            public,
+            is_static,
+        };
+
+        // Locals are declared first so the body can call itself
+        if self.current().scope_depth > 0 {
+            self.add_local(token)?;
+            return self.function(&declaration, FunctionKind::Function, None);
+        }
+        self.function(&declaration, FunctionKind::Function, None)?;
+        self.define_variable(token)
+    }
+
+    fn visit_class(&mut self, name: &Token, methods: &[Stmt], super_class: &Option<Expr>) -> Result<(), RuntimeError> {
+        let index = self.identifier(&name.lexeme)?;
+        self.emit_with(OpCode::Class(index), name);
+        self.define_variable(name)?;
+
+        if let Some(super_class) = super_class {
+            self.compile_expr(super_class)?;
+            self.begin_scope();
+            self.add_local(&Self::keyword(name, TokenType::SUPER, "super"))?;
+            self.named_variable(name, false)?;
+            let super_name = match super_class {
+                Expr::Variable { name, .. } => name,
+                _ => name,
+            };
+            self.emit_with(OpCode::Inherit, super_name);
+        }
+
+        self.named_variable(name, false)?;
+        for method in methods {
+            let Stmt::Function { token, .. } = method else {
+                return Err(RuntimeError::InvalidClassMember());
+            };
+            let kind = if token.lexeme == "init" {
+                FunctionKind::Initializer
+            } else {
+                FunctionKind::Method
+            };
+            self.function(method, kind, Some(&name.lexeme))?;
+            self.emit(OpCode::Method);
+        }
+        self.emit(OpCode::Pop);
+
+        if super_class.is_some() {
+            self.end_scope();
+        }
+        Ok(())
+    }
+
+    fn visit_return(&mut self, keyword: &Token, value: &Expr) -> Result<(), RuntimeError> {
+        // The resolver only lets a bare `return;` into `init`
+        if self.current().function.kind == FunctionKind::Initializer {
+            self.emit(OpCode::GetLocal(0));
+        } else {
+            self.compile_expr(value)?;
+        }
+        self.emit_with(OpCode::Return, keyword);
+        Ok(())
+    }
+}
+
+impl Compiler {
+    fn begin_loop(&mut self, label: Option<&Token>) {
+        let depth = self.current().scope_depth;
+        self.current().loops.push(Loop {
+            label: label.map(|label| label.lexeme.clone()),
+            depth,
+            breaks: vec![],
+            continues: vec![],
+        });
+    }
+
+    /// Leaves the scopes inside the loop `label`, or the innermost one, and
+    /// emits a jump for the caller to record there. Returns the loop's index
+    /// and the jump's offset.
+    fn jump_out(&mut self, keyword: &Token, label: Option<&Token>) -> Result<(usize, usize), RuntimeError> {
+        let state = self.current();
+        let index = match label {
+            Some(label) => state
+                .loops
+                .iter()
+                .rposition(|l| l.label.as_deref() == Some(label.lexeme.as_str()))
+                .ok_or_else(|| RuntimeError::UndefinedLabel(label.clone()))?,
+            None => state.loops.len().checked_sub(1).ok_or_else(|| RuntimeError::OutsideLoop(keyword.clone()))?,
+        };
+        let depth = state.loops[index].depth;
+
+        // Drop the locals of the scopes being left, without forgetting them
+        let exits: Vec<OpCode> = state
+            .locals
+            .iter()
+            .rev()
+            .take_while(|local| local.depth > depth)
+            .map(|local| if local.captured { OpCode::CloseUpvalue } else { OpCode::Pop })
+            .collect();
+        for op in exits {
+            self.emit(op);
+        }
+
+        Ok((index, self.emit_with(OpCode::Jump(0), keyword)))
+    }
+
+    // Points the pending `continue`s of the innermost loop here
+    fn patch_continues(&mut self) {
+        let continues = self.current().loops.last_mut().map(|l| std::mem::take(&mut l.continues)).unwrap_or_default();
+        for jump in continues {
+            self.patch_jump(jump);
+        }
+    }
+
+    fn end_loop(&mut self) {
+        if let Some(finished) = self.current().loops.pop() {
+            for jump in finished.breaks {
+                self.patch_jump(jump);
+            }
+        }
+    }
+}
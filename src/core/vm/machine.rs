@@ -0,0 +1,1039 @@
+use std::cell::RefCell;
+use std::cmp::Ordering;
+use std::rc::Rc;
+use std::time::SystemTime;
+
+use rustc_hash::FxHashMap;
+
+use super::chunk::OpCode;
+use super::value::{BoundMethod, Class, Closure, Function, Instance, ListMethod, MapMethod, NativeFunction, Upvalue, Value};
+use crate::core::error_types::runtime_error::{CallFrame, RuntimeError, DEFAULT_CALL_DEPTH};
+use crate::core::fuctions::lox_callable::Arity;
+use crate::core::gc::{Heap, HeapStats};
+use crate::core::native_functions::collection::Host;
+use crate::core::native_functions::lox_list;
+use crate::core::native_functions::lox_map::{self, LoxMap, MapKey};
+use crate::core::native_functions::lox_math::extreme;
+use crate::core::number::{Number, NumberError};
+use crate::core::oop::native_class::NativeClassDef;
+use crate::core::syntax::components::expression::LoxValue;
+use crate::core::syntax::span::Span;
+use crate::core::syntax::token::Token;
+use crate::core::syntax::token_type::TokenType;
+use crate::debug_dbg;
+
+// Arguments of `print` and `println`, like the tree-walker's
+fn join(arguments: &[Value]) -> String {
+    arguments.iter().map(|value| value.to_string()).collect::<Vec<_>>().join(" ")
+}
+
+// One active call
+struct Frame {
+    closure: Rc<Closure>,
+    ip: usize,
+    // Stack index of slot 0
+    base: usize,
+    // Arguments the caller gave, for `OpCode::JumpIfPassed`
+    passed: usize,
+    call_site: Span,
+    // Set when the frame runs `init` for a call to this class, which is the
+    // name the traceback shows
+    class: Option<Rc<Class>>,
+}
+
+impl Frame {
+    fn name(&self) -> String {
+        match &self.class {
+            Some(class) => class.name.clone(),
+            None => self.closure.function.qualified_name(),
+        }
+    }
+}
+
+/// Stack-based virtual machine running code from the `Compiler`.
+///
+/// Globals survive between calls to [`Vm::interpret`], like the
+/// tree-walker's global environment.
+pub struct Vm {
+    stack: Vec<Value>,
+    frames: Vec<Frame>,
+    globals: FxHashMap<Rc<str>, Value>,
+    // Upvalues still pointing into the stack, by ascending slot
+    open_upvalues: Vec<Rc<RefCell<Upvalue>>>,
+    // Tracks instances, classes and upvalues so cycles between them get
+    // freed. Shared with the `gc` native.
+    heap: Rc<RefCell<Heap>>,
+    // List methods still running, which count as calls but have no frame
+    native_calls: usize,
+    max_call_depth: usize,
+}
+
+impl Default for Vm {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Vm {
+    pub fn new() -> Self {
+        let mut vm = Self {
+            stack: Vec::with_capacity(256),
+            frames: vec![],
+            globals: FxHashMap::default(),
+            open_upvalues: vec![],
+            heap: Rc::new(RefCell::new(Heap::new())),
+            native_calls: 0,
+            max_call_depth: DEFAULT_CALL_DEPTH,
+        };
+
+        vm.define_native("clock", Arity::Exact(0), |_| {
+            let duration = SystemTime::now()
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .expect("Time went backwards");
+            Ok(Value::Number(duration.as_secs() as f64 + duration.subsec_nanos() as f64 / 1_000_000_000.0))
+        });
+        vm.define_native("print", Arity::AtLeast(1), |arguments| {
+            print!("{}", join(arguments));
+            Ok(Value::Nil)
+        });
+        vm.define_native("println", Arity::AtLeast(0), |arguments| {
+            println!("{}", join(arguments));
+            Ok(Value::Nil)
+        });
+        vm.define_native("dbg", Arity::AtLeast(1), |arguments| {
+            arguments.iter().for_each(|_value| {
+                debug_dbg!(_value);
+            });
+            Ok(Value::Nil)
+        });
+        for (name, wanted) in [("max", Ordering::Greater), ("min", Ordering::Less)] {
+            vm.define_native(name, Arity::AtLeast(1), move |arguments| {
+                let result = Value::call_host(arguments, |arguments| extreme(name, &arguments, wanted))?;
+                Value::from_host(name, result)
+            });
+        }
+        vm.define_native("to_string", Arity::Exact(1), |arguments| Ok(Value::String(arguments[0].text().into())));
+        let heap = Rc::clone(&vm.heap);
+        vm.define_native("gc", Arity::Exact(0), move |_| Ok(Value::Integer(heap.borrow_mut().collect() as i64)));
+        let heap = Rc::clone(&vm.heap);
+        vm.define_native("List", Arity::AtLeast(0), move |arguments| {
+            let list = Rc::new(RefCell::new(arguments.to_vec()));
+            heap.borrow_mut().track(&list);
+            Ok(Value::List(list))
+        });
+        vm
+    }
+
+    /// Runs the cycle collector now and returns how many objects it freed.
+    pub fn collect_garbage(&mut self) -> usize {
+        self.heap.borrow_mut().collect()
+    }
+
+    pub fn heap_stats(&self) -> HeapStats {
+        self.heap.borrow().stats()
+    }
+
+    /// How many calls deep scripts may go before failing with a stack
+    /// overflow error.
+    pub fn set_max_call_depth(&mut self, depth: usize) {
+        self.max_call_depth = depth;
+    }
+
+    /// The global variables, sorted by name.
+    pub fn globals(&self) -> Vec<(&str, &Value)> {
+        let mut globals: Vec<(&str, &Value)> = self.globals.iter().map(|(name, value)| (&**name, value)).collect();
+        globals.sort_by_key(|(name, _)| *name);
+        globals
+    }
+
+    /// Adds (or replaces) a global native function.
+    pub fn define_native(
+        &mut self,
+        name: &str,
+        arity: Arity,
+        function: impl Fn(&[Value]) -> Result<Value, RuntimeError> + 'static,
+    ) {
+        let native = NativeFunction {
+            name: name.to_string(),
+            arity,
+            function: Box::new(function),
+        };
+        self.globals.insert(name.into(), Value::Native(Rc::new(native)));
+    }
+
+    /// Adds (or replaces) a global class backed by a Rust struct.
+    pub fn define_native_class(&mut self, native: Rc<NativeClassDef>) {
+        let name: Rc<str> = native.name.as_str().into();
+        self.globals.insert(name, Value::Class(Rc::new(Class::native(native))));
+    }
+
+    /// Runs a compiled script. Errors come back wrapped in
+    /// `RuntimeError::Traceback`, and leave the VM ready for the next script.
+    pub fn interpret(&mut self, script: Function) -> Result<Value, RuntimeError> {
+        let closure = Rc::new(Closure {
+            function: Rc::new(script),
+            upvalues: vec![],
+        });
+        self.stack.push(Value::Closure(Rc::clone(&closure)));
+        self.frames.push(Frame {
+            closure,
+            ip: 0,
+            base: 0,
+            passed: 0,
+            call_site: Span::default(),
+            class: None,
+        });
+
+        let result = self.run(0).map_err(|error| self.traceback(error));
+        if result.is_err() {
+            // Closures that escaped before the error keep their values
+            self.close_upvalues(0);
+            self.stack.clear();
+            self.frames.clear();
+        }
+        result
+    }
+
+    // The call stack below the script, outermost call first
+    fn traceback(&self, error: RuntimeError) -> RuntimeError {
+        if matches!(error, RuntimeError::Traceback(..)) {
+            return error;
+        }
+        let frames = self
+            .frames
+            .iter()
+            .skip(1)
+            .map(|frame| CallFrame {
+                function: frame.name(),
+                call_site: frame.call_site,
+            })
+            .collect();
+        RuntimeError::Traceback(Box::new(error), frames)
+    }
+
+    // Runs until the frame count drops back to `depth`, and returns what
+    // that last frame returned
+    fn run(&mut self, depth: usize) -> Result<Value, RuntimeError> {
+        loop {
+            let frame = self.frames.last_mut().expect("no frame to run");
+            let op = frame.closure.function.chunk.code[frame.ip];
+            frame.ip += 1;
+
+            match op {
+                OpCode::Constant(index) => {
+                    let value = self.constant(index);
+                    self.push(value);
+                }
+                OpCode::Nil => self.push(Value::Nil),
+                OpCode::True => self.push(Value::Boolean(true)),
+                OpCode::False => self.push(Value::Boolean(false)),
+                OpCode::Pop => {
+                    self.pop();
+                }
+                OpCode::Dup => self.push(self.peek(0).clone()),
+                OpCode::DupPair => {
+                    let (below, top) = (self.peek(1).clone(), self.peek(0).clone());
+                    self.push(below);
+                    self.push(top);
+                }
+                OpCode::Bury(n) => {
+                    let value = self.pop();
+                    let at = self.stack.len() - n as usize;
+                    self.stack.insert(at, value);
+                }
+                OpCode::GetLocal(slot) => {
+                    let value = self.stack[self.frame().base + slot as usize].clone();
+                    self.push(value);
+                }
+                OpCode::SetLocal(slot) => {
+                    let index = self.frame().base + slot as usize;
+                    self.stack[index] = self.peek(0).clone();
+                }
+                OpCode::GetGlobal(index) => {
+                    let name = self.string_constant(index);
+                    match self.globals.get(&name) {
+                        Some(value) => {
+                            let value = value.clone();
+                            self.push(value);
+                        }
+                        None => return Err(RuntimeError::UndefinedVariable(self.token())),
+                    }
+                }
+                OpCode::DefineGlobal(index) => {
+                    let name = self.string_constant(index);
+                    let value = self.pop();
+                    self.globals.insert(name, value);
+                }
+                OpCode::SetGlobal(index) => {
+                    let name = self.string_constant(index);
+                    let value = self.peek(0).clone();
+                    match self.globals.get_mut(&name) {
+                        Some(global) => *global = value,
+                        None => return Err(RuntimeError::UndefinedVariable(self.token())),
+                    }
+                }
+                OpCode::GetUpvalue(index) => {
+                    let upvalue = Rc::clone(&self.frame().closure.upvalues[index as usize]);
+                    let value = match &*upvalue.borrow() {
+                        Upvalue::Open(slot) => self.stack[*slot].clone(),
+                        Upvalue::Closed(value) => value.clone(),
+                    };
+                    self.push(value);
+                }
+                OpCode::SetUpvalue(index) => {
+                    let upvalue = Rc::clone(&self.frame().closure.upvalues[index as usize]);
+                    let value = self.peek(0).clone();
+                    let mut upvalue = upvalue.borrow_mut();
+                    match &mut *upvalue {
+                        Upvalue::Open(slot) => self.stack[*slot] = value,
+                        Upvalue::Closed(closed) => *closed = value,
+                    }
+                }
+                OpCode::GetProperty { name, this } => {
+                    let name = self.string_constant(name);
+                    let object = self.pop();
+                    let value = self.get_property(object, &name, this)?;
+                    self.push(value);
+                }
+                OpCode::SetProperty(name) => {
+                    let name = self.string_constant(name);
+                    let value = self.pop();
+                    match self.pop() {
+                        Value::Instance(instance) => {
+                            let mut instance = instance.borrow_mut();
+                            let is_native = match (&instance.class.native, &instance.native) {
+                                (Some(class), Some(object)) => {
+                                    let token = self.token();
+                                    Value::call_host(std::slice::from_ref(&value), |mut values| {
+                                        class.set(object, &token, values.remove(0))
+                                    })?
+                                }
+                                _ => false,
+                            };
+                            if !is_native {
+                                instance.fields.insert(name.to_string(), value.clone());
+                            }
+                            drop(instance);
+                            self.push(value);
+                        }
+                        _ => return Err(RuntimeError::OnlyInstancesHaveProperties(self.token())),
+                    }
+                }
+                OpCode::GetSuper(name) => {
+                    let name = self.string_constant(name);
+                    let super_class = self.pop();
+                    let receiver = self.pop();
+                    let method = self.super_method(&super_class, &name)?;
+                    self.push(Value::BoundMethod(Rc::new(BoundMethod { receiver, method })));
+                }
+                OpCode::Equal => {
+                    let (left, right) = self.pop_pair();
+                    self.push(Value::Boolean(left.equals(&right)));
+                }
+                OpCode::NotEqual => {
+                    let (left, right) = self.pop_pair();
+                    self.push(Value::Boolean(!left.equals(&right)));
+                }
+                OpCode::Greater => self.compare(Ordering::is_gt)?,
+                OpCode::GreaterEqual => self.compare(Ordering::is_ge)?,
+                OpCode::Less => self.compare(Ordering::is_lt)?,
+                OpCode::LessEqual => self.compare(Ordering::is_le)?,
+                OpCode::Add => {
+                    let value = match self.pop_pair() {
+                        (Value::String(s1), Value::String(s2)) => Value::String(format!("{}{}", s1, s2).into()),
+                        (Value::String(s1), n @ (Value::Number(_) | Value::Integer(_))) => {
+                            Value::String(format!("{}{}", s1, n.text()).into())
+                        }
+                        (left, right) => self.arithmetic(&left, &right, Number::checked_add)?,
+                    };
+                    self.push(value);
+                }
+                OpCode::Subtract => {
+                    let value = match self.pop_pair() {
+                        (Value::String(s1), Value::String(s2)) => Value::String(s1.replacen(&*s2, "", 1).into()),
+                        (left, right) => self.arithmetic(&left, &right, Number::checked_sub)?,
+                    };
+                    self.push(value);
+                }
+                OpCode::Multiply => self.binary_number(Number::checked_mul)?,
+                OpCode::Divide => self.binary_number(Number::checked_div)?,
+                OpCode::FloorDivide => self.binary_number(Number::checked_floor_div)?,
+                OpCode::Modulo => self.binary_number(Number::checked_rem)?,
+                OpCode::Power => self.binary_number(Number::checked_pow)?,
+                OpCode::BitAnd => self.binary_number(Number::bit_and)?,
+                OpCode::BitOr => self.binary_number(Number::bit_or)?,
+                OpCode::BitXor => self.binary_number(Number::bit_xor)?,
+                OpCode::ShiftLeft => self.binary_number(Number::checked_shl)?,
+                OpCode::ShiftRight => self.binary_number(Number::checked_shr)?,
+                OpCode::Not => {
+                    let value = self.pop();
+                    self.push(Value::Boolean(!value.is_truthy()));
+                }
+                OpCode::Negate => match self.pop().to_number() {
+                    Some(n) => {
+                        let value = n.checked_neg().map_err(|error| error.at(&self.token()))?;
+                        self.push(Value::from_number(value));
+                    }
+                    None => return Err(self.bad_operator("Operand must be a number.")),
+                },
+                OpCode::BitNot => match self.pop().to_number() {
+                    Some(n) => {
+                        let value = n.bit_not().map_err(|error| error.at(&self.token()))?;
+                        self.push(Value::from_number(value));
+                    }
+                    None => return Err(self.bad_operator("Operand must be an integer.")),
+                },
+                OpCode::Stringify => {
+                    let value = self.pop();
+                    self.push(Value::String(value.text().into()));
+                }
+                OpCode::Print => {
+                    let value = self.pop();
+                    println!("{}", value);
+                }
+                OpCode::Jump(target) => self.frame_mut().ip = target as usize,
+                OpCode::JumpIfFalse(target) => {
+                    if !self.peek(0).is_truthy() {
+                        self.frame_mut().ip = target as usize;
+                    }
+                }
+                OpCode::JumpIfTrue(target) => {
+                    if self.peek(0).is_truthy() {
+                        self.frame_mut().ip = target as usize;
+                    }
+                }
+                OpCode::JumpIfPassed { param, target } => {
+                    if self.frame().passed > param as usize {
+                        self.frame_mut().ip = target as usize;
+                    }
+                }
+                OpCode::Call(argc) => {
+                    let callee = self.peek(argc as usize).clone();
+                    self.call_value(callee, argc as usize)?;
+                }
+                OpCode::Invoke { name, argc, this } => {
+                    let name = self.string_constant(name);
+                    self.invoke(&name, argc as usize, this)?;
+                }
+                OpCode::SuperInvoke { name, argc } => {
+                    let name = self.string_constant(name);
+                    let super_class = self.pop();
+                    let method = self.super_method(&super_class, &name)?;
+                    self.call_closure(method, argc as usize, None)?;
+                }
+                OpCode::Closure(index) => {
+                    let Value::Function(function) = self.constant(index) else {
+                        return Err(RuntimeError::InvalidFunction("closure constant".to_string()));
+                    };
+                    let upvalues = function
+                        .upvalues
+                        .iter()
+                        .map(|upvalue| {
+                            if upvalue.is_local {
+                                self.capture_upvalue(self.frame().base + upvalue.index as usize)
+                            } else {
+                                Rc::clone(&self.frame().closure.upvalues[upvalue.index as usize])
+                            }
+                        })
+                        .collect();
+                    self.push(Value::Closure(Rc::new(Closure { function, upvalues })));
+                }
+                OpCode::CloseUpvalue => {
+                    self.close_upvalues(self.stack.len() - 1);
+                    self.pop();
+                }
+                OpCode::Return => {
+                    let result = self.pop();
+                    let frame = self.frames.pop().expect("no frame to return from");
+                    self.close_upvalues(frame.base);
+                    self.stack.truncate(frame.base);
+                    if self.frames.len() == depth {
+                        return Ok(result);
+                    }
+                    self.push(result);
+                }
+                OpCode::Class(name) => {
+                    let name = self.string_constant(name);
+                    let class = Rc::new(Class::new(&name));
+                    self.heap.borrow_mut().track(&class);
+                    self.push(Value::Class(class));
+                }
+                OpCode::Inherit => {
+                    let Value::Class(class) = self.pop() else {
+                        return Err(RuntimeError::InvalidClassMember());
+                    };
+                    let Value::Class(super_class) = self.peek(0).clone() else {
+                        return Err(RuntimeError::SuperClassMustBeSuperAClass(self.token()));
+                    };
+                    if super_class.native.is_some() {
+                        return Err(RuntimeError::NativeSuperclass(self.token()));
+                    }
+                    class.methods.borrow_mut().extend(
+                        super_class.methods.borrow().iter().map(|(name, method)| (name.clone(), Rc::clone(method))),
+                    );
+                    *class.super_class.borrow_mut() = Some(super_class);
+                }
+                OpCode::Method => {
+                    let Value::Closure(method) = self.pop() else {
+                        return Err(RuntimeError::InvalidClassMember());
+                    };
+                    let Value::Class(class) = self.peek(0) else {
+                        return Err(RuntimeError::InvalidClassMember());
+                    };
+                    let name = method.function.name.clone();
+                    if method.function.is_static {
+                        class.statics.borrow_mut().insert(name, method);
+                    } else {
+                        class.methods.borrow_mut().insert(name, method);
+                    }
+                }
+                OpCode::BuildList(count) => {
+                    let items = self.stack.split_off(self.stack.len() - count as usize);
+                    let list = self.new_list(items);
+                    self.push(list);
+                }
+                OpCode::BuildMap(count) => {
+                    let mut pairs = self.stack.split_off(self.stack.len() - 2 * count as usize).into_iter();
+                    let mut map = LoxMap::new();
+                    while let (Some(key), Some(value)) = (pairs.next(), pairs.next()) {
+                        map.insert(self.map_key(&key)?, value);
+                    }
+                    let map = Rc::new(RefCell::new(map));
+                    self.heap.borrow_mut().track(&map);
+                    self.push(Value::Map(map));
+                }
+                OpCode::GetIndex => {
+                    let (object, index) = self.pop_pair();
+                    let value = self.get_index(object, &index)?;
+                    self.push(value);
+                }
+                OpCode::SetIndex => {
+                    let value = self.pop();
+                    let (object, index) = self.pop_pair();
+                    self.set_index(object, &index, value.clone())?;
+                    self.push(value);
+                }
+                OpCode::Slice { start, end } => {
+                    let end = if end { Some(self.pop()) } else { None };
+                    let start = if start { Some(self.pop()) } else { None };
+                    let object = self.pop();
+                    let value = self.slice(object, start, end)?;
+                    self.push(value);
+                }
+            }
+        }
+    }
+
+    fn frame(&self) -> &Frame {
+        self.frames.last().expect("no active frame")
+    }
+
+    fn frame_mut(&mut self) -> &mut Frame {
+        self.frames.last_mut().expect("no active frame")
+    }
+
+    fn push(&mut self, value: Value) {
+        self.stack.push(value);
+    }
+
+    fn pop(&mut self) -> Value {
+        self.stack.pop().expect("stack underflow")
+    }
+
+    // (left, right) operands of a binary instruction
+    fn pop_pair(&mut self) -> (Value, Value) {
+        let right = self.pop();
+        let left = self.pop();
+        (left, right)
+    }
+
+    fn peek(&self, distance: usize) -> &Value {
+        &self.stack[self.stack.len() - 1 - distance]
+    }
+
+    fn constant(&self, index: u16) -> Value {
+        self.frame().closure.function.chunk.constants[index as usize].clone()
+    }
+
+    fn string_constant(&self, index: u16) -> Rc<str> {
+        match &self.frame().closure.function.chunk.constants[index as usize] {
+            Value::String(name) => Rc::clone(name),
+            _ => "".into(),
+        }
+    }
+
+    // The token behind the instruction being run, for error messages
+    fn token(&self) -> Token {
+        let frame = self.frame();
+        let offset = frame.ip - 1;
+        let chunk = &frame.closure.function.chunk;
+        chunk.token(offset).cloned().unwrap_or_else(|| {
+            Token::new(TokenType::IDENTIFIER, String::new(), LoxValue::Nil, chunk.spans[offset])
+        })
+    }
+
+    // Calls report their errors at the closing parenthesis
+    fn paren(&self) -> Token {
+        let frame = self.frame();
+        let span = frame.closure.function.chunk.spans[frame.ip - 1];
+        Token::new(TokenType::RIGHT_PAREN, ")".to_string(), LoxValue::Nil, span)
+    }
+
+    fn bad_operator(&self, message: &str) -> RuntimeError {
+        RuntimeError::BadOperator(self.token(), message.to_string())
+    }
+
+    // Both operands must be numbers, like `Interpreter::arithmetic`
+    fn arithmetic(
+        &self,
+        left: &Value,
+        right: &Value,
+        op: fn(Number, Number) -> Result<Number, NumberError>,
+    ) -> Result<Value, RuntimeError> {
+        match (left.to_number(), right.to_number()) {
+            (Some(n1), Some(n2)) => op(n1, n2).map(Value::from_number).map_err(|error| error.at(&self.token())),
+            _ => Err(self.bad_operator("Invalid binary operation.")),
+        }
+    }
+
+    fn binary_number(&mut self, op: fn(Number, Number) -> Result<Number, NumberError>) -> Result<(), RuntimeError> {
+        let (left, right) = self.pop_pair();
+        let value = self.arithmetic(&left, &right, op)?;
+        self.push(value);
+        Ok(())
+    }
+
+    // NaN is neither smaller nor bigger than anything
+    fn compare(&mut self, test: fn(Ordering) -> bool) -> Result<(), RuntimeError> {
+        let (left, right) = self.pop_pair();
+        match (left.to_number(), right.to_number()) {
+            (Some(n1), Some(n2)) => {
+                self.push(Value::Boolean(n1.compare(n2).is_some_and(test)));
+                Ok(())
+            }
+            _ => Err(self.bad_operator("Invalid binary operation.")),
+        }
+    }
+
+    /// Calls `callee`, which sits under its `argc` arguments on the stack.
+    fn call_value(&mut self, callee: Value, argc: usize) -> Result<(), RuntimeError> {
+        match callee {
+            Value::Closure(closure) => self.call_closure(closure, argc, None),
+            Value::BoundMethod(bound) => {
+                let slot = self.stack.len() - argc - 1;
+                self.stack[slot] = bound.receiver.clone();
+                self.call_closure(Rc::clone(&bound.method), argc, None)
+            }
+            Value::Class(class) if class.native.is_some() => {
+                let native = class.native.as_ref().expect("checked by the guard");
+                if argc != native.arity() {
+                    return Err(RuntimeError::ArityMismatch(self.paren(), class.name.clone(), Arity::Exact(native.arity()), argc));
+                }
+                self.enter_call()?;
+                let first = self.stack.len() - argc;
+                let mut instance = Instance::new(Rc::clone(&class));
+                instance.native = Some(Value::call_host(&self.stack[first..], |arguments| native.construct(arguments))?);
+                let instance = Rc::new(RefCell::new(instance));
+                self.heap.borrow_mut().track(&instance);
+                self.stack.truncate(first - 1);
+                self.push(Value::Instance(instance));
+                Ok(())
+            }
+            Value::Class(class) => {
+                let slot = self.stack.len() - argc - 1;
+                let instance = Rc::new(RefCell::new(Instance::new(Rc::clone(&class))));
+                self.heap.borrow_mut().track(&instance);
+                self.stack[slot] = Value::Instance(instance);
+                match class.find_method("init") {
+                    Some(init) => self.call_closure(init, argc, Some(class)),
+                    None if argc != 0 => Err(RuntimeError::ArityMismatch(self.paren(), class.name.clone(), Arity::Exact(0), argc)),
+                    None => self.enter_call(),
+                }
+            }
+            Value::ListMethod(method) => self.call_list_method(Rc::clone(&method.list), method.name, argc),
+            Value::MapMethod(method) => self.call_map_method(Rc::clone(&method.map), method.name, argc),
+            Value::Native(native) => {
+                if !native.arity.accepts(argc) {
+                    return Err(RuntimeError::ArityMismatch(self.paren(), native.name.clone(), native.arity, argc));
+                }
+                self.enter_call()?;
+                let first = self.stack.len() - argc;
+                let result = (native.function)(&self.stack[first..]).map_err(|error| {
+                    // The native's own frame shows up in the traceback too
+                    match self.traceback(error) {
+                        RuntimeError::Traceback(error, mut frames) => {
+                            frames.push(CallFrame {
+                                function: native.name.clone(),
+                                call_site: self.paren().span,
+                            });
+                            RuntimeError::Traceback(error, frames)
+                        }
+                        error => error,
+                    }
+                })?;
+                self.stack.truncate(first - 1);
+                self.push(result);
+                Ok(())
+            }
+            _ => Err(RuntimeError::BadCallable(self.paren())),
+        }
+    }
+
+    // Fails when one more call would go past `max_call_depth`. The script's
+    // own frame doesn't count, as in the tree-walker
+    fn enter_call(&self) -> Result<(), RuntimeError> {
+        if self.frames.len() - 1 + self.native_calls >= self.max_call_depth {
+            return Err(RuntimeError::StackOverflow(self.paren().span, self.max_call_depth));
+        }
+        Ok(())
+    }
+
+    fn call_closure(&mut self, closure: Rc<Closure>, argc: usize, class: Option<Rc<Class>>) -> Result<(), RuntimeError> {
+        let base = self.stack.len() - argc - 1;
+        // Every parameter gets its slot: defaults are filled in by the
+        // function itself, extras move into the rest list
+        let function = &closure.function;
+        if argc != function.params || function.rest {
+            if !function.arity().accepts(argc) {
+                let name = match &class {
+                    Some(class) => class.name.clone(),
+                    None => function.qualified_name(),
+                };
+                return Err(RuntimeError::ArityMismatch(self.paren(), name, function.arity(), argc));
+            }
+            let rest: Vec<Value> = self.stack.drain(base + 1 + function.params.min(argc)..).collect();
+            self.stack.resize(base + 1 + function.params, Value::Nil);
+            if function.rest {
+                let rest = Rc::new(RefCell::new(rest));
+                self.heap.borrow_mut().track(&rest);
+                self.push(Value::List(rest));
+            }
+        }
+        self.enter_call()?;
+        let call_site = self.paren().span;
+        self.frames.push(Frame {
+            closure,
+            ip: 0,
+            base,
+            passed: argc,
+            call_site,
+            class,
+        });
+        Ok(())
+    }
+
+    // Property lookup, following `LoxInstance::get` and `LoxClass::find_static`
+    fn get_property(&self, object: Value, name: &str, this: bool) -> Result<Value, RuntimeError> {
+        match object {
+            Value::Instance(instance) => {
+                if let Some(value) = Self::native_property(&instance, name)? {
+                    return Ok(value);
+                }
+                if let Some(value) = instance.borrow().fields.get(name) {
+                    return Ok(value.clone());
+                }
+                let method = self.instance_method(&instance, name, this)?;
+                Ok(Value::BoundMethod(Rc::new(BoundMethod {
+                    receiver: Value::Instance(instance),
+                    method,
+                })))
+            }
+            Value::Class(class) => Ok(match class.find_static(name) {
+                Some(method) => Value::BoundMethod(Rc::new(BoundMethod {
+                    receiver: Value::Class(Rc::clone(&class)),
+                    method,
+                })),
+                None => Value::Nil,
+            }),
+            Value::List(list) => match lox_list::method(name) {
+                Some((name, _)) => Ok(Value::ListMethod(Rc::new(ListMethod { list, name }))),
+                None => Err(RuntimeError::UndefinedProperty(self.token())),
+            },
+            Value::Map(map) => match lox_map::method(name) {
+                Some((name, _)) => Ok(Value::MapMethod(Rc::new(MapMethod { map, name }))),
+                None => Err(RuntimeError::UndefinedProperty(self.token())),
+            },
+            _ => Err(RuntimeError::OnlyInstancesHaveProperties(self.token())),
+        }
+    }
+
+    // A property or method of the struct behind a native instance, the way
+    // `LoxInstance::get` finds them
+    fn native_property(instance: &Rc<RefCell<Instance>>, name: &str) -> Result<Option<Value>, RuntimeError> {
+        let instance = instance.borrow();
+        let (Some(class), Some(object)) = (&instance.class.native, &instance.native) else {
+            return Ok(None);
+        };
+        if let Some(value) = class.get(object, name)? {
+            return Value::from_host(name, value).map(Some);
+        }
+        Ok(class.method(name).map(|method| {
+            let object = Rc::clone(object);
+            let native = NativeFunction {
+                name: method.name().to_string(),
+                arity: Arity::Exact(method.arity()),
+                function: Box::new(move |arguments| {
+                    let result = Value::call_host(arguments, |arguments| method.call(&object, arguments))?;
+                    Value::from_host(method.name(), result)
+                }),
+            };
+            Value::Native(Rc::new(native))
+        }))
+    }
+
+    fn instance_method(&self, instance: &Rc<RefCell<Instance>>, name: &str, this: bool) -> Result<Rc<Closure>, RuntimeError> {
+        let class = Rc::clone(&instance.borrow().class);
+        match class.find_method(name) {
+            Some(method) if method.function.is_public || this => Ok(method),
+            Some(_) => Err(RuntimeError::CantAccessPrivateMethod(self.token())),
+            None if class.find_static(name).is_some() => {
+                Err(RuntimeError::CantCallStaticMethodFromInstance(self.token()))
+            }
+            None => Err(RuntimeError::UndefinedProperty(self.token())),
+        }
+    }
+
+    // `receiver.name(args)` in one step
+    fn invoke(&mut self, name: &str, argc: usize, this: bool) -> Result<(), RuntimeError> {
+        let receiver = self.peek(argc).clone();
+        match receiver {
+            Value::Instance(instance) => {
+                let field = match Self::native_property(&instance, name)? {
+                    Some(value) => Some(value),
+                    None => instance.borrow().fields.get(name).cloned(),
+                };
+                if let Some(field) = field {
+                    let slot = self.stack.len() - argc - 1;
+                    self.stack[slot] = field.clone();
+                    return self.call_value(field, argc);
+                }
+                let method = self.instance_method(&instance, name, this)?;
+                self.call_closure(method, argc, None)
+            }
+            // The class stays in slot 0 of its static methods
+            Value::Class(class) => match class.find_static(name) {
+                Some(method) => self.call_closure(method, argc, None),
+                None => Err(RuntimeError::BadCallable(self.paren())),
+            },
+            Value::List(list) => self.call_list_method(list, name, argc),
+            Value::Map(map) => self.call_map_method(map, name, argc),
+            _ => Err(RuntimeError::OnlyInstancesHaveProperties(self.token())),
+        }
+    }
+
+    // Indices and slice bounds must be numbers, like `Interpreter::index_number`
+    fn index_number(&self, index: &Value) -> Result<f64, RuntimeError> {
+        match index.to_number() {
+            Some(n) => Ok(n.as_f64()),
+            None => Err(RuntimeError::InvalidIndex(self.token(), index.type_name().to_string())),
+        }
+    }
+
+    fn map_key(&self, key: &Value) -> Result<MapKey, RuntimeError> {
+        key.to_key().ok_or_else(|| RuntimeError::UnhashableKey(self.token(), key.type_name()))
+    }
+
+    fn get_index(&self, object: Value, index: &Value) -> Result<Value, RuntimeError> {
+        match object {
+            Value::List(list) => {
+                let list = list.borrow();
+                let position = lox_list::position(&self.token(), self.index_number(index)?, list.len())?;
+                Ok(list[position].clone())
+            }
+            Value::String(s) => {
+                let position = lox_list::position(&self.token(), self.index_number(index)?, s.chars().count())?;
+                Ok(Value::String(s.chars().nth(position).expect("position is in range").to_string().into()))
+            }
+            Value::Map(map) => {
+                let key = self.map_key(index)?;
+                let value = map.borrow().get(&key).cloned();
+                value.ok_or_else(|| RuntimeError::KeyNotFound(self.token(), key.to_string()))
+            }
+            object => Err(RuntimeError::NotIndexable(self.token(), object.type_name())),
+        }
+    }
+
+    fn set_index(&self, object: Value, index: &Value, value: Value) -> Result<(), RuntimeError> {
+        match object {
+            Value::List(list) => {
+                let mut list = list.borrow_mut();
+                let position = lox_list::position(&self.token(), self.index_number(index)?, list.len())?;
+                list[position] = value;
+                Ok(())
+            }
+            Value::Map(map) => {
+                map.borrow_mut().insert(self.map_key(index)?, value);
+                Ok(())
+            }
+            Value::String(_) => Err(RuntimeError::ImmutableIndex(self.token(), object.type_name())),
+            object => Err(RuntimeError::NotIndexable(self.token(), object.type_name())),
+        }
+    }
+
+    fn slice(&mut self, object: Value, start: Option<Value>, end: Option<Value>) -> Result<Value, RuntimeError> {
+        let start = start.map(|start| self.index_number(&start)).transpose()?;
+        let end = end.map(|end| self.index_number(&end)).transpose()?;
+        match object {
+            Value::List(list) => {
+                let items = {
+                    let list = list.borrow();
+                    list[lox_list::slice(&self.token(), start, end, list.len())?].to_vec()
+                };
+                Ok(self.new_list(items))
+            }
+            Value::String(s) => {
+                let range = lox_list::slice(&self.token(), start, end, s.chars().count())?;
+                Ok(Value::String(s.chars().skip(range.start).take(range.len()).collect::<String>().into()))
+            }
+            object => Err(RuntimeError::NotSliceable(self.token(), object.type_name())),
+        }
+    }
+
+    // `list.name(args)`, with the list under its `argc` arguments. Its
+    // frame goes into tracebacks right above the caller, below any
+    // callback it was running.
+    fn call_list_method(&mut self, list: Rc<RefCell<Vec<Value>>>, name: &str, argc: usize) -> Result<(), RuntimeError> {
+        let Some((name, arity)) = lox_list::method(name) else {
+            return Err(RuntimeError::UndefinedProperty(self.token()));
+        };
+        if !arity.accepts(argc) {
+            return Err(RuntimeError::ArityMismatch(self.paren(), format!("List.{}", name), arity, argc));
+        }
+        self.enter_call()?;
+        let arguments = self.stack.split_off(self.stack.len() - argc);
+        let depth = self.frames.len();
+        let call_site = self.paren().span;
+        self.native_calls += 1;
+        let result = lox_list::call_method(self, &list, name, arguments);
+        self.native_calls -= 1;
+        let result = result.map_err(|error| match self.traceback(error) {
+            RuntimeError::Traceback(error, mut frames) => {
+                let function = format!("List.{}", name);
+                frames.insert(depth - 1, CallFrame { function, call_site });
+                RuntimeError::Traceback(error, frames)
+            }
+            error => error,
+        })?;
+        self.pop();
+        self.push(result);
+        Ok(())
+    }
+
+    // `map.name(args)`, with the map under its arguments
+    fn call_map_method(&mut self, map: Rc<RefCell<LoxMap<Value>>>, name: &str, argc: usize) -> Result<(), RuntimeError> {
+        let Some((name, arity)) = lox_map::method(name) else {
+            return Err(RuntimeError::UndefinedProperty(self.token()));
+        };
+        if !arity.accepts(argc) {
+            return Err(RuntimeError::ArityMismatch(self.paren(), format!("Map.{}", name), arity, argc));
+        }
+        self.enter_call()?;
+        let arguments = self.stack.split_off(self.stack.len() - argc);
+        let result = lox_map::call_method(self, &map, name, arguments);
+        self.pop();
+        self.push(result);
+        Ok(())
+    }
+
+    // Calls `callee` on behalf of the list method `caller` and runs it to
+    // completion, like `Interpreter::call_value`
+    fn call_sync(&mut self, caller: &str, callee: &Value, arguments: Vec<Value>) -> Result<Value, RuntimeError> {
+        let Some((name, arity)) = Self::signature(callee) else {
+            return Err(RuntimeError::ArgumentTypeMismatch(caller.to_string(), 0, "function", callee.type_name()));
+        };
+        if !arity.accepts(arguments.len()) {
+            return Err(RuntimeError::BadArguments(format!(
+                "'{}' passes {} to its callback, but '{}' expects {}.",
+                caller,
+                Arity::Exact(arguments.len()),
+                name,
+                arity
+            )));
+        }
+        let argc = arguments.len();
+        let depth = self.frames.len();
+        self.push(callee.clone());
+        self.stack.extend(arguments);
+        self.call_value(callee.clone(), argc)?;
+        if self.frames.len() > depth {
+            return self.run(depth);
+        }
+        Ok(self.pop())
+    }
+
+    // Name and arity of anything callable
+    fn signature(callee: &Value) -> Option<(String, Arity)> {
+        match callee {
+            Value::Closure(closure) => Some((closure.function.qualified_name(), closure.function.arity())),
+            Value::BoundMethod(bound) => Some((bound.method.function.qualified_name(), bound.method.function.arity())),
+            Value::Native(native) => Some((native.name.clone(), native.arity)),
+            Value::Class(class) => {
+                let arity = match &class.native {
+                    Some(native) => Arity::Exact(native.arity()),
+                    None => class.find_method("init").map_or(Arity::Exact(0), |init| init.function.arity()),
+                };
+                Some((class.name.clone(), arity))
+            }
+            Value::ListMethod(method) => {
+                lox_list::method(method.name).map(|(name, arity)| (format!("List.{}", name), arity))
+            }
+            Value::MapMethod(method) => {
+                lox_map::method(method.name).map(|(name, arity)| (format!("Map.{}", name), arity))
+            }
+            _ => None,
+        }
+    }
+
+    fn super_method(&self, super_class: &Value, name: &str) -> Result<Rc<Closure>, RuntimeError> {
+        let Value::Class(super_class) = super_class else {
+            return Err(RuntimeError::InvalidSuperclass(self.token()));
+        };
+        super_class
+            .find_method(name)
+            .ok_or_else(|| RuntimeError::UndefinedProperty(self.token()))
+    }
+
+    fn capture_upvalue(&mut self, slot: usize) -> Rc<RefCell<Upvalue>> {
+        let position = self
+            .open_upvalues
+            .iter()
+            .rposition(|upvalue| matches!(*upvalue.borrow(), Upvalue::Open(open) if open <= slot));
+
+        if let Some(position) = position {
+            let upvalue = &self.open_upvalues[position];
+            if matches!(*upvalue.borrow(), Upvalue::Open(open) if open == slot) {
+                return Rc::clone(upvalue);
+            }
+        }
+
+        let upvalue = Rc::new(RefCell::new(Upvalue::Open(slot)));
+        self.heap.borrow_mut().track(&upvalue);
+        let index = position.map_or(0, |position| position + 1);
+        self.open_upvalues.insert(index, Rc::clone(&upvalue));
+        upvalue
+    }
+
+    // Moves every variable at or above `from` off the stack
+    fn close_upvalues(&mut self, from: usize) {
+        while let Some(upvalue) = self.open_upvalues.last() {
+            let slot = match *upvalue.borrow() {
+                Upvalue::Open(slot) if slot >= from => slot,
+                _ => break,
+            };
+            *upvalue.borrow_mut() = Upvalue::Closed(self.stack[slot].clone());
+            self.open_upvalues.pop();
+        }
+    }
+}
+
+impl Host<Value> for Vm {
+    fn call(&mut self, caller: &str, callee: &Value, arguments: Vec<Value>) -> Result<Value, RuntimeError> {
+        self.call_sync(caller, callee, arguments)
+    }
+
+    fn new_list(&mut self, items: Vec<Value>) -> Value {
+        let list = Rc::new(RefCell::new(items));
+        self.heap.borrow_mut().track(&list);
+        Value::List(list)
+    }
+}
@@ -0,0 +1,4 @@
+pub mod chunk;
+pub mod value;
+pub mod compiler;
+pub mod machine;
@@ -0,0 +1,660 @@
+use std::cell::RefCell;
+use std::fmt;
+use std::rc::Rc;
+
+use rustc_hash::FxHashMap;
+
+use crate::core::error_types::runtime_error::RuntimeError;
+use crate::core::fuctions::lox_callable::Arity;
+use crate::core::gc::{Trace, Tracer};
+use crate::core::native_functions::collection::{self, Element};
+use crate::core::native_functions::lox_list;
+use crate::core::native_functions::lox_map::{self, LoxMap, MapKey};
+use crate::core::number::Number;
+use crate::core::oop::lox_class::LoxClass;
+use crate::core::oop::lox_instance::LoxInstance;
+use crate::core::oop::native_class::{NativeClassDef, NativeObject};
+use crate::core::syntax::components::expression::LoxValue;
+
+use super::chunk::Chunk;
+
+/// A value on the VM stack. Prints exactly like the matching `LoxValue`, so
+/// scripts behave the same on both backends.
+#[derive(Clone)]
+pub enum Value {
+    Nil,
+    Boolean(bool),
+    Number(f64),
+    Integer(i64),
+    String(Rc<str>),
+    Native(Rc<NativeFunction>),
+    // Only found in constant tables; `OpCode::Closure` wraps it at runtime
+    Function(Rc<Function>),
+    Closure(Rc<Closure>),
+    Class(Rc<Class>),
+    Instance(Rc<RefCell<Instance>>),
+    BoundMethod(Rc<BoundMethod>),
+    List(Rc<RefCell<Vec<Value>>>),
+    ListMethod(Rc<ListMethod>),
+    Map(Rc<RefCell<LoxMap<Value>>>),
+    MapMethod(Rc<MapMethod>),
+}
+
+impl Value {
+    pub fn is_truthy(&self) -> bool {
+        match self {
+            Value::Boolean(b) => *b,
+            Value::Nil => false,
+            Value::List(list) => !list.borrow().is_empty(),
+            Value::Map(map) => !map.borrow().is_empty(),
+            _ => true,
+        }
+    }
+
+    /// `None` for values that aren't numbers, as in `Number::from_lox`.
+    pub fn to_number(&self) -> Option<Number> {
+        match self {
+            Value::Integer(n) => Some(Number::Int(*n)),
+            Value::Number(n) => Some(Number::Float(*n)),
+            _ => None,
+        }
+    }
+
+    pub fn from_number(n: Number) -> Value {
+        match n {
+            Number::Int(n) => Value::Integer(n),
+            Number::Float(n) => Value::Number(n),
+        }
+    }
+
+    /// Same rules as `Interpreter::is_equal`: lists are equal when their
+    /// items are, other objects never compare equal.
+    pub fn equals(&self, other: &Value) -> bool {
+        if let (Some(n1), Some(n2)) = (self.to_number(), other.to_number()) {
+            return n1.equals(n2);
+        }
+        match (self, other) {
+            (Value::Nil, Value::Nil) => true,
+            (Value::String(s1), Value::String(s2)) => s1 == s2,
+            (Value::Boolean(b1), Value::Boolean(b2)) => b1 == b2,
+            (Value::List(l1), Value::List(l2)) => collection::lists_equal(l1, l2),
+            (Value::Map(m1), Value::Map(m2)) => collection::maps_equal(m1, m2),
+            _ => false,
+        }
+    }
+
+    /// Same names as `LoxValue::type_name`.
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::Nil => "nil",
+            Value::Boolean(_) => "boolean",
+            Value::Number(_) => "number",
+            Value::Integer(_) => "integer",
+            Value::String(_) => "string",
+            Value::Native(_) | Value::Function(_) | Value::Closure(_) | Value::BoundMethod(_) | Value::ListMethod(_) | Value::MapMethod(_) => {
+                "function"
+            }
+            Value::Class(_) => "class",
+            Value::Instance(_) => "instance",
+            Value::List(_) => "list",
+            Value::Map(_) => "map",
+        }
+    }
+
+    /// Same as `LoxValue::text`.
+    pub fn text(&self) -> String {
+        match self {
+            Value::String(s) => s.to_string(),
+            Value::Number(n) => n.to_string(),
+            Value::Integer(n) => n.to_string(),
+            Value::Boolean(b) => b.to_string(),
+            value => value.to_string(),
+        }
+    }
+
+    /// `None` for values that can't be map keys, as in `MapKey::from_lox`.
+    pub fn to_key(&self) -> Option<MapKey> {
+        match self {
+            Value::Nil => Some(MapKey::Nil),
+            Value::Boolean(b) => Some(MapKey::Boolean(*b)),
+            Value::Number(n) => Some(MapKey::number(*n)),
+            Value::Integer(n) => Some(MapKey::Integer(*n)),
+            Value::String(s) => Some(MapKey::String(Rc::clone(s))),
+            _ => None,
+        }
+    }
+
+    pub fn from_key(key: &MapKey) -> Value {
+        match key {
+            MapKey::Nil => Value::Nil,
+            MapKey::Boolean(b) => Value::Boolean(*b),
+            MapKey::Integer(n) => Value::Integer(*n),
+            MapKey::Number(bits) => Value::Number(f64::from_bits(*bits)),
+            MapKey::String(s) => Value::String(Rc::clone(s)),
+        }
+    }
+
+    /// The tree-walker equivalent of a value. Lists and maps are copied,
+    /// keeping the values they share and their cycles. Instances of native
+    /// classes share their struct with the VM. Other instances, classes and
+    /// functions only exist inside the VM and come back as `nil`.
+    pub fn to_lox(&self) -> LoxValue {
+        self.to_lox_in(&mut FxHashMap::default())
+    }
+
+    // `copies` holds the lists and maps already copied, by address
+    fn to_lox_in(&self, copies: &mut FxHashMap<usize, LoxValue>) -> LoxValue {
+        match self {
+            Value::Boolean(b) => LoxValue::Boolean(*b),
+            Value::Number(n) => LoxValue::Number(*n),
+            Value::Integer(n) => LoxValue::Integer(*n),
+            Value::String(s) => LoxValue::String(s.to_string()),
+            Value::List(list) => {
+                if let Some(copy) = copies.get(&(Rc::as_ptr(list) as usize)) {
+                    return copy.clone();
+                }
+                let copy = Rc::new(RefCell::new(vec![]));
+                copies.insert(Rc::as_ptr(list) as usize, LoxValue::List(Rc::clone(&copy)));
+                let items = list.borrow().iter().map(|item| item.to_lox_in(copies)).collect();
+                *copy.borrow_mut() = items;
+                LoxValue::List(copy)
+            }
+            Value::Map(map) => {
+                if let Some(copy) = copies.get(&(Rc::as_ptr(map) as usize)) {
+                    return copy.clone();
+                }
+                let copy = Rc::new(RefCell::new(LoxMap::new()));
+                copies.insert(Rc::as_ptr(map) as usize, LoxValue::Map(Rc::clone(&copy)));
+                let entries: Vec<(MapKey, LoxValue)> =
+                    map.borrow().iter().map(|(key, value)| (key.clone(), value.to_lox_in(copies))).collect();
+                for (key, value) in entries {
+                    copy.borrow_mut().insert(key, value);
+                }
+                LoxValue::Map(copy)
+            }
+            Value::Instance(instance) => {
+                let instance = instance.borrow();
+                match (&instance.class.native, &instance.native) {
+                    (Some(class), Some(object)) => LoxValue::LoxInstance(Rc::new(RefCell::new(
+                        LoxInstance::with_native(LoxClass::native(Rc::clone(class)), Rc::clone(object)),
+                    ))),
+                    _ => LoxValue::Nil,
+                }
+            }
+            _ => LoxValue::Nil,
+        }
+    }
+
+    /// The VM equivalent of a tree-walker value, copying lists and maps
+    /// like `to_lox`. `None` for the other objects, and for lists and maps
+    /// holding one.
+    pub fn from_lox(value: &LoxValue) -> Option<Value> {
+        Value::from_lox_in(value, &mut FxHashMap::default())
+    }
+
+    fn from_lox_in(value: &LoxValue, copies: &mut FxHashMap<usize, Value>) -> Option<Value> {
+        match value {
+            LoxValue::Nil => Some(Value::Nil),
+            LoxValue::Boolean(b) => Some(Value::Boolean(*b)),
+            LoxValue::Number(n) => Some(Value::Number(*n)),
+            LoxValue::Integer(n) => Some(Value::Integer(*n)),
+            LoxValue::String(s) => Some(Value::String(s.as_str().into())),
+            LoxValue::List(list) => {
+                if let Some(copy) = copies.get(&(Rc::as_ptr(list) as usize)) {
+                    return Some(copy.clone());
+                }
+                let copy = Rc::new(RefCell::new(vec![]));
+                copies.insert(Rc::as_ptr(list) as usize, Value::List(Rc::clone(&copy)));
+                let items = list.borrow().iter().map(|item| Value::from_lox_in(item, copies)).collect::<Option<_>>()?;
+                *copy.borrow_mut() = items;
+                Some(Value::List(copy))
+            }
+            LoxValue::Map(map) => {
+                if let Some(copy) = copies.get(&(Rc::as_ptr(map) as usize)) {
+                    return Some(copy.clone());
+                }
+                let copy = Rc::new(RefCell::new(LoxMap::new()));
+                copies.insert(Rc::as_ptr(map) as usize, Value::Map(Rc::clone(&copy)));
+                let entries = map
+                    .borrow()
+                    .iter()
+                    .map(|(key, value)| Some((key.clone(), Value::from_lox_in(value, copies)?)))
+                    .collect::<Option<Vec<_>>>()?;
+                for (key, value) in entries {
+                    copy.borrow_mut().insert(key, value);
+                }
+                Some(Value::Map(copy))
+            }
+            _ => None,
+        }
+    }
+
+    /// Runs host code on `arguments`, converted with `to_lox`. What the
+    /// host can't see arrives as `nil`, so type errors get the type of the
+    /// value really passed back.
+    pub fn call_host<R>(
+        arguments: &[Value],
+        call: impl FnOnce(Vec<LoxValue>) -> Result<R, RuntimeError>,
+    ) -> Result<R, RuntimeError> {
+        call(arguments.iter().map(Value::to_lox).collect()).map_err(|error| match error {
+            RuntimeError::ArgumentTypeMismatch(name, index, expected, _) if index < arguments.len() => {
+                RuntimeError::ArgumentTypeMismatch(name, index, expected, arguments[index].type_name())
+            }
+            error => error,
+        })
+    }
+
+    /// Converts what the host function `name` returned, failing for
+    /// objects other than lists and maps.
+    pub fn from_host(name: &str, value: LoxValue) -> Result<Value, RuntimeError> {
+        Value::from_lox(&value).ok_or_else(|| {
+            RuntimeError::NativeFunctionError(format!("'{}' returned a {}, which the VM can't hold", name, value.type_name()))
+        })
+    }
+
+    /// Reports the objects this value keeps alive to the cycle collector.
+    pub fn trace(&self, tracer: &mut Tracer) {
+        match self {
+            Value::Closure(closure) => tracer.edge(closure),
+            Value::Class(class) => tracer.edge(class),
+            Value::Instance(instance) => tracer.edge(instance),
+            Value::BoundMethod(bound) => tracer.edge(bound),
+            Value::List(list) => tracer.edge(list),
+            Value::ListMethod(method) => tracer.edge(&method.list),
+            Value::Map(map) => tracer.edge(map),
+            Value::MapMethod(method) => tracer.edge(&method.map),
+            _ => {}
+        }
+    }
+}
+
+impl Element for Value {
+    fn nil() -> Self {
+        Value::Nil
+    }
+
+    fn boolean(b: bool) -> Self {
+        Value::Boolean(b)
+    }
+
+    fn integer(n: i64) -> Self {
+        Value::Integer(n)
+    }
+
+    fn string(s: String) -> Self {
+        Value::String(s.into())
+    }
+
+    fn list(list: Rc<RefCell<Vec<Self>>>) -> Self {
+        Value::List(list)
+    }
+
+    fn from_key(key: &MapKey) -> Self {
+        Value::from_key(key)
+    }
+
+    fn number(&self) -> Option<Number> {
+        self.to_number()
+    }
+
+    fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    fn to_key(&self) -> Option<MapKey> {
+        Value::to_key(self)
+    }
+
+    fn type_name(&self) -> &'static str {
+        Value::type_name(self)
+    }
+
+    fn text(&self) -> String {
+        Value::text(self)
+    }
+
+    fn is_truthy(&self) -> bool {
+        Value::is_truthy(self)
+    }
+
+    fn equals(&self, other: &Self) -> bool {
+        Value::equals(self, other)
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Value::Nil => write!(f, "nil"),
+            Value::Boolean(b) => write!(f, "Boolean({})", b),
+            Value::Number(n) => write!(f, "Number({})", n),
+            Value::Integer(n) => write!(f, "Integer({})", n),
+            Value::String(s) => write!(f, "String({})", s),
+            // Function declarations are plain callables in the tree-walker
+            Value::Native(_) | Value::Function(_) | Value::Closure(_) | Value::ListMethod(_) | Value::MapMethod(_) => {
+                write!(f, "LoxCallable()")
+            }
+            Value::Class(class) => match &*class.super_class.borrow() {
+                Some(super_class) => write!(f, "LoxClass({}) <- LoxSuper({})", class.name, super_class.name),
+                None => write!(f, "LoxClass({})", class.name),
+            },
+            Value::Instance(instance) => write!(f, "LoxInstance({})", instance.borrow().class.name),
+            Value::BoundMethod(bound) => write!(f, "LoxFunction({})", bound.method.function),
+            Value::List(list) => lox_list::fmt_items(f, list),
+            Value::Map(map) => lox_map::fmt_entries(f, map),
+        }
+    }
+}
+
+impl fmt::Debug for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::String(s) => write!(f, "String({:?})", s),
+            Value::Native(_) | Value::Function(_) | Value::Closure(_) | Value::ListMethod(_) | Value::MapMethod(_) => {
+                write!(f, "Callable()")
+            }
+            Value::BoundMethod(_) => write!(f, "Callable(<function>)"),
+            Value::Nil => write!(f, "Nil"),
+            Value::Class(class) => write!(f, "LoxClass({})", class.name),
+            Value::List(list) => f.debug_list().entries(list.borrow().iter()).finish(),
+            Value::Map(map) => f.debug_map().entries(map.borrow().iter()).finish(),
+            _ => write!(f, "{}", self),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FunctionKind {
+    Script,
+    Function,
+    Method,
+    Initializer,
+}
+
+/// Where a closure finds a captured variable when it is created: a slot of
+/// the enclosing frame, or one of the enclosing closure's own upvalues.
+#[derive(Debug, Clone, Copy)]
+pub struct UpvalueRef {
+    pub index: u8,
+    pub is_local: bool,
+}
+
+#[derive(Debug)]
+pub struct Function {
+    pub name: String,
+    // Set for methods, so stack traces read `Class.method`
+    pub class_name: Option<String>,
+    // Parameters before the rest one, if any, and how many of those have
+    // no default
+    pub params: usize,
+    pub required: usize,
+    pub rest: bool,
+    pub kind: FunctionKind,
+    pub is_public: bool,
+    pub is_static: bool,
+    pub upvalues: Vec<UpvalueRef>,
+    pub chunk: Chunk,
+}
+
+impl Function {
+    pub fn new(name: &str, kind: FunctionKind) -> Self {
+        Self {
+            name: name.to_string(),
+            class_name: None,
+            params: 0,
+            required: 0,
+            rest: false,
+            kind,
+            is_public: true,
+            is_static: false,
+            upvalues: vec![],
+            chunk: Chunk::new(),
+        }
+    }
+
+    /// Same as `Arity::of` for the declaration this was compiled from.
+    pub fn arity(&self) -> Arity {
+        match (self.rest, self.params > self.required) {
+            (true, _) => Arity::AtLeast(self.required),
+            (false, true) => Arity::Range(self.required, self.params),
+            (false, false) => Arity::Exact(self.required),
+        }
+    }
+
+    /// Shown in stack traces.
+    pub fn qualified_name(&self) -> String {
+        match &self.class_name {
+            Some(class_name) => format!("{}.{}", class_name, self.name),
+            None => self.name.clone(),
+        }
+    }
+}
+
+// Same wording as `LoxFunction`'s Display
+impl fmt::Display for Function {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.is_public, self.is_static) {
+            (true, true) => write!(f, "Static Public Function({})", self.name),
+            (true, false) => write!(f, "Public Function({})", self.name),
+            (false, true) => write!(f, "Private Static Function({})", self.name),
+            (false, false) => write!(f, "Private Function({})", self.name),
+        }
+    }
+}
+
+impl Trace for RefCell<Vec<Value>> {
+    fn trace(&self, tracer: &mut Tracer) {
+        let Ok(items) = self.try_borrow() else {
+            return tracer.opaque();
+        };
+        items.iter().for_each(|item| item.trace(tracer));
+    }
+
+    fn clear(&self) {
+        if let Ok(mut items) = self.try_borrow_mut() {
+            items.clear();
+        }
+    }
+}
+
+impl Trace for RefCell<LoxMap<Value>> {
+    fn trace(&self, tracer: &mut Tracer) {
+        let Ok(map) = self.try_borrow() else {
+            return tracer.opaque();
+        };
+        map.values().for_each(|value| value.trace(tracer));
+    }
+
+    fn clear(&self) {
+        if let Ok(mut map) = self.try_borrow_mut() {
+            map.clear();
+        }
+    }
+}
+
+/// A captured variable. It points at a stack slot while the variable's
+/// frame is alive and owns the value once that frame returns.
+#[derive(Debug)]
+pub enum Upvalue {
+    Open(usize),
+    Closed(Value),
+}
+
+impl Trace for RefCell<Upvalue> {
+    fn trace(&self, tracer: &mut Tracer) {
+        match self.try_borrow().as_deref() {
+            Ok(Upvalue::Closed(value)) => value.trace(tracer),
+            // The stack slot is a root already
+            Ok(Upvalue::Open(_)) => {}
+            Err(_) => tracer.opaque(),
+        }
+    }
+
+    fn clear(&self) {
+        if let Ok(mut upvalue) = self.try_borrow_mut() {
+            *upvalue = Upvalue::Closed(Value::Nil);
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Closure {
+    pub function: Rc<Function>,
+    pub upvalues: Vec<Rc<RefCell<Upvalue>>>,
+}
+
+impl Trace for Closure {
+    fn trace(&self, tracer: &mut Tracer) {
+        self.upvalues.iter().for_each(|upvalue| tracer.edge(upvalue));
+    }
+}
+
+#[derive(Debug)]
+pub struct Class {
+    pub name: String,
+    // Inherited methods are copied in by `OpCode::Inherit`
+    pub methods: RefCell<FxHashMap<String, Rc<Closure>>>,
+    pub statics: RefCell<FxHashMap<String, Rc<Closure>>>,
+    pub super_class: RefCell<Option<Rc<Class>>>,
+    // Set for classes registered from Rust, like `LoxClass::native`
+    pub native: Option<Rc<NativeClassDef>>,
+}
+
+impl Class {
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            methods: RefCell::new(FxHashMap::default()),
+            statics: RefCell::new(FxHashMap::default()),
+            super_class: RefCell::new(None),
+            native: None,
+        }
+    }
+
+    pub fn native(native: Rc<NativeClassDef>) -> Self {
+        Self {
+            native: Some(Rc::clone(&native)),
+            ..Self::new(&native.name)
+        }
+    }
+
+    pub fn find_method(&self, name: &str) -> Option<Rc<Closure>> {
+        self.methods.borrow().get(name).cloned()
+    }
+
+    /// Static methods are only reachable while public, like `LoxClass::find_static`.
+    pub fn find_static(&self, name: &str) -> Option<Rc<Closure>> {
+        self.statics
+            .borrow()
+            .get(name)
+            .filter(|method| method.function.is_public)
+            .cloned()
+    }
+}
+
+impl Trace for Class {
+    fn trace(&self, tracer: &mut Tracer) {
+        let (Ok(methods), Ok(statics), Ok(super_class)) =
+            (self.methods.try_borrow(), self.statics.try_borrow(), self.super_class.try_borrow())
+        else {
+            return tracer.opaque();
+        };
+        methods.values().chain(statics.values()).for_each(|method| tracer.edge(method));
+        if let Some(super_class) = &*super_class {
+            tracer.edge(super_class);
+        }
+    }
+
+    fn clear(&self) {
+        if let Ok(mut methods) = self.methods.try_borrow_mut() {
+            methods.clear();
+        }
+        if let Ok(mut statics) = self.statics.try_borrow_mut() {
+            statics.clear();
+        }
+        if let Ok(mut super_class) = self.super_class.try_borrow_mut() {
+            *super_class = None;
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Instance {
+    pub class: Rc<Class>,
+    pub fields: FxHashMap<String, Value>,
+    // The struct behind an instance of a native class
+    pub native: Option<NativeObject>,
+}
+
+impl Instance {
+    pub fn new(class: Rc<Class>) -> Self {
+        Self {
+            class,
+            fields: FxHashMap::default(),
+            native: None,
+        }
+    }
+}
+
+impl Trace for RefCell<Instance> {
+    fn trace(&self, tracer: &mut Tracer) {
+        let Ok(instance) = self.try_borrow() else {
+            return tracer.opaque();
+        };
+        tracer.edge(&instance.class);
+        instance.fields.values().for_each(|value| value.trace(tracer));
+    }
+
+    fn clear(&self) {
+        if let Ok(mut instance) = self.try_borrow_mut() {
+            instance.fields.clear();
+        }
+    }
+}
+
+/// A method together with the value its `this` slot holds: an instance, or
+/// the class itself for static methods.
+#[derive(Debug)]
+pub struct BoundMethod {
+    pub receiver: Value,
+    pub method: Rc<Closure>,
+}
+
+impl Trace for BoundMethod {
+    fn trace(&self, tracer: &mut Tracer) {
+        self.receiver.trace(tracer);
+        tracer.edge(&self.method);
+    }
+}
+
+/// A list method read off a list, like `LoxListMethod` in the
+/// tree-walker. The VM runs it itself, since callbacks need the VM.
+#[derive(Debug)]
+pub struct ListMethod {
+    pub list: Rc<RefCell<Vec<Value>>>,
+    pub name: &'static str,
+}
+
+/// A map method read off a map, like `LoxMapMethod` in the tree-walker.
+#[derive(Debug)]
+pub struct MapMethod {
+    pub map: Rc<RefCell<LoxMap<Value>>>,
+    pub name: &'static str,
+}
+
+pub type NativeFn = dyn Fn(&[Value]) -> Result<Value, RuntimeError>;
+
+pub struct NativeFunction {
+    pub name: String,
+    pub arity: Arity,
+    pub function: Box<NativeFn>,
+}
+
+impl fmt::Debug for NativeFunction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "NativeFunction({})", self.name)
+    }
+}
@@ -1,3 +1,5 @@
+#![allow(clippy::result_large_err)]
+
 pub mod utils;
 pub mod core;
-pub mod macros;
\ No newline at end of file
+pub mod macros;
@@ -1,9 +1,18 @@
-use std::env;
+use std::{env, process, thread};
 
-use rust_lox_interpreter::core::lox::Lox;
+use rust_lox_interpreter::core::lox::{Lox, STACK_SIZE};
 
 fn main() {
     let args: Vec<String> = env::args().collect();
-    let mut lox = Lox::new();
-    lox.init(args);
+    let runner = thread::Builder::new()
+        .stack_size(STACK_SIZE)
+        .spawn(move || {
+            let mut lox = Lox::new();
+            lox.init(args);
+        })
+        .expect("failed to start the interpreter thread");
+    // The panic message is already out
+    if runner.join().is_err() {
+        process::exit(101);
+    }
 }
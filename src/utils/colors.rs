@@ -1,6 +1,9 @@
+use std::io::IsTerminal;
+
 #[derive(Debug)]
 pub enum Color {
     Reset,
+    Bold,
     Red,
     Green,
     Yellow,
@@ -14,6 +17,7 @@ impl Color {
     pub fn to_ansi_code(&self) -> &str {
         match self {
             Color::Reset => "\x1b[0m",     // Reset
+            Color::Bold => "\x1b[1m",      // Negrita
             Color::Red => "\x1b[31m",      // Rojo
             Color::Green => "\x1b[32m",    // Verde
             Color::Yellow => "\x1b[33m",   // Amarillo
@@ -23,14 +27,33 @@ impl Color {
             Color::White => "\x1b[37m",    // Blanco
         }
     }
-    
+
+    /// False when `NO_COLOR` is set (https://no-color.org) or stdout is not a terminal.
+    pub fn stdout_enabled() -> bool {
+        std::env::var_os("NO_COLOR").is_none() && std::io::stdout().is_terminal()
+    }
+
+    /// False when `NO_COLOR` is set or stderr is not a terminal.
+    pub fn stderr_enabled() -> bool {
+        std::env::var_os("NO_COLOR").is_none() && std::io::stderr().is_terminal()
+    }
+
+    /// Wraps `text` in this color, or returns it untouched when `enabled` is false.
+    pub fn paint(&self, text: &str, enabled: bool) -> String {
+        if enabled {
+            format!("{}{}{}", self.to_ansi_code(), text, Color::Reset.to_ansi_code())
+        } else {
+            text.to_string()
+        }
+    }
+
     pub fn cprintln(text: &str, color: Color) {
-        println!("{}{}{}",color.to_ansi_code(), text, Color::Reset.to_ansi_code());
+        println!("{}", color.paint(text, Self::stdout_enabled()));
     }
     pub fn cprint(text: &str, color: Color) {
-        print!("{}{}{}",color.to_ansi_code(), text, Color::Reset.to_ansi_code());
+        print!("{}", color.paint(text, Self::stdout_enabled()));
     }
     pub fn ecprintln(text: &str, color: Color) {
-        eprintln!("{}{}{}",color.to_ansi_code(), text, Color::Reset.to_ansi_code());
+        eprintln!("{}", color.paint(text, Self::stderr_enabled()));
     }
 }
@@ -0,0 +1,232 @@
+use std::fs;
+use std::path::PathBuf;
+use std::process::Command;
+
+use rust_lox_interpreter::core::oop::native_class::NativeClass;
+use rust_lox_interpreter::core::session::{Backend, LoxSession};
+use rust_lox_interpreter::core::syntax::components::expression::LoxValue;
+
+fn scripts() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests").join("scripts")
+}
+
+// What `rox run` prints for `tests/scripts/<name>.lox`: stdout, then
+// stderr, then the exit code
+fn run(backend: Backend, name: &str) -> (String, String, Option<i32>) {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_rox"));
+    command.current_dir(scripts()).arg("-q");
+    if backend == Backend::Vm {
+        command.arg("--vm");
+    }
+    let output = command.arg("run").arg(format!("{}.lox", name)).output().unwrap();
+    let text = |bytes: Vec<u8>| String::from_utf8(bytes).unwrap();
+    (text(output.stdout), text(output.stderr), output.status.code())
+}
+
+// Runs the script on both backends, which must agree, and returns what
+// they printed and the exit code
+fn same_on_both(name: &str) -> (String, Option<i32>) {
+    let walker = run(Backend::TreeWalker, name);
+    let vm = run(Backend::Vm, name);
+    assert_eq!(walker, vm, "{}.lox runs differently on the VM", name);
+    (walker.0 + &walker.1, walker.2)
+}
+
+// Like `same_on_both`, also checking the output against `<name>.out` and
+// the exit code against `code`
+fn check(name: &str, code: i32) {
+    let expected = fs::read_to_string(scripts().join(format!("{}.out", name))).unwrap();
+    let (output, status) = same_on_both(name);
+    assert_eq!(output, expected, "unexpected output from {}.lox", name);
+    assert_eq!(status, Some(code), "unexpected exit code from {}.lox", name);
+}
+
+// Exit codes of `rox run`
+const OK: i32 = 0;
+const EX_DATAERR: i32 = 65;
+const EX_SOFTWARE: i32 = 70;
+
+#[test]
+fn lists() {
+    check("lists", OK);
+}
+
+#[test]
+fn maps() {
+    check("maps", OK);
+}
+
+#[test]
+fn interpolation() {
+    check("interpolation", OK);
+}
+
+#[test]
+fn integers() {
+    check("integers", OK);
+}
+
+#[test]
+fn integer_overflow() {
+    check("integer_overflow", EX_SOFTWARE);
+}
+
+#[test]
+fn compound_assignment() {
+    check("compound", OK);
+}
+
+#[test]
+fn labeled_loops() {
+    check("loops", OK);
+}
+
+#[test]
+fn stack_overflow() {
+    check("stack_overflow", EX_SOFTWARE);
+}
+
+#[test]
+fn lambdas_are_values() {
+    check("lambdas", EX_SOFTWARE);
+}
+
+#[test]
+fn default_and_rest_parameters() {
+    check("parameters", EX_SOFTWARE);
+}
+
+#[test]
+fn exact_arity() {
+    check("arity_exact", EX_SOFTWARE);
+}
+
+#[test]
+fn range_arity() {
+    check("arity_range", EX_SOFTWARE);
+}
+
+#[test]
+fn at_least_arity() {
+    check("arity_at_least", EX_SOFTWARE);
+}
+
+#[test]
+fn init_takes_the_class_arguments() {
+    check("init", EX_SOFTWARE);
+}
+
+#[test]
+fn every_parse_error_is_reported() {
+    check("parse_errors", EX_DATAERR);
+}
+
+#[test]
+fn resolve_errors_stop_the_script() {
+    check("resolve_error", EX_DATAERR);
+}
+
+#[test]
+fn stack_overflow_through_callbacks() {
+    let (output, status) = same_on_both("stack_overflow_callback");
+    assert!(output.contains("error[E0330]"));
+    assert_eq!(status, Some(EX_SOFTWARE));
+}
+
+fn eval_on_both(source: &str, setup: impl Fn(&mut LoxSession)) -> Result<LoxValue, String> {
+    let [walker, vm] = [Backend::TreeWalker, Backend::Vm].map(|backend| {
+        let mut session = LoxSession::new();
+        session.set_backend(backend);
+        setup(&mut session);
+        session.eval(source).map_err(|e| e.to_string())
+    });
+    assert_eq!(walker, vm, "`{}` evaluates differently on the VM", source);
+    walker
+}
+
+#[test]
+fn lists_and_maps_leave_the_session() {
+    let value = eval_on_both("var xs = [1, {\"k\": [2.5]}]; xs.push(xs); xs;", |_| {}).unwrap();
+    let LoxValue::List(list) = &value else {
+        panic!("expected a list, got {:?}", value);
+    };
+    assert_eq!(list.borrow().len(), 3);
+    assert!(matches!(&list.borrow()[2], LoxValue::List(inner) if std::rc::Rc::ptr_eq(inner, list)));
+}
+
+#[test]
+fn host_functions_see_the_same_arguments() {
+    let setup = |session: &mut LoxSession| {
+        session.register_fn("size", |items: LoxValue| match items {
+            LoxValue::List(list) => list.borrow().len() as f64,
+            _ => -1.0,
+        });
+        session.register_fn("shout", |s: String| s.to_uppercase());
+    };
+    assert_eq!(eval_on_both("size([1, 2, 3]);", setup), Ok(LoxValue::Number(3.0)));
+    let error = eval_on_both("class C {} shout(C());", setup).unwrap_err();
+    assert!(error.contains("found instance"), "{}", error);
+}
+
+#[test]
+fn closures_outlive_a_failed_call() {
+    for backend in [Backend::TreeWalker, Backend::Vm] {
+        let mut session = LoxSession::new();
+        session.set_backend(backend);
+        session.eval("var g; fn f() { var x = 1; g = () => x; nope; }").unwrap();
+        assert!(session.eval("f();").is_err());
+        assert_eq!(session.eval("g();").unwrap(), LoxValue::Integer(1), "on {:?}", backend);
+    }
+}
+
+#[test]
+fn runaway_recursion_fails_on_a_spawned_thread() {
+    let errors = std::thread::spawn(|| {
+        [Backend::TreeWalker, Backend::Vm].map(|backend| {
+            let mut session = LoxSession::new();
+            session.set_backend(backend);
+            session.eval("fn f(n) { return [n].map((x) => f(x + 1)); } f(0);").unwrap_err().to_string()
+        })
+    })
+    .join()
+    .unwrap();
+    for error in errors {
+        assert!(error.contains("Stack overflow"), "{}", error);
+    }
+}
+
+#[test]
+fn floats_past_the_integer_range_are_rejected() {
+    let setup = |session: &mut LoxSession| {
+        session.register_fn("wide", |n: i64| n.to_string());
+        session.register_fn("narrow", |n: i32| n.to_string());
+    };
+    assert!(eval_on_both("wide(9223372036854775808.0);", setup).is_err());
+    assert_eq!(
+        eval_on_both("narrow(2147483647.0);", setup),
+        Ok(LoxValue::String("2147483647".to_string()))
+    );
+    assert!(eval_on_both("narrow(2147483648.0);", setup).is_err());
+}
+
+#[test]
+fn native_instances_leave_the_session() {
+    struct Counter {
+        count: i64,
+    }
+    for backend in [Backend::TreeWalker, Backend::Vm] {
+        let mut session = LoxSession::new();
+        session.set_backend(backend);
+        session.register_class(
+            NativeClass::<Counter>::new("Counter")
+                .constructor(|| Counter { count: 0 })
+                .method("bump", |counter: &mut Counter| counter.count += 1)
+                .getter("count", |counter: &Counter| counter.count),
+        );
+        let value = session.eval("var c = Counter(); c.bump(); c;").unwrap();
+        let counter = value.as_native::<Counter>().unwrap_or_else(|| panic!("no Counter on {:?}", backend));
+        assert_eq!(counter.borrow().count, 1);
+        counter.borrow_mut().count = 10;
+        assert_eq!(session.eval("c.bump(); c.count;").unwrap(), LoxValue::Integer(11), "on {:?}", backend);
+    }
+}
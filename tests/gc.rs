@@ -0,0 +1,78 @@
+use rust_lox_interpreter::core::session::{Backend, LoxSession};
+use rust_lox_interpreter::core::syntax::components::expression::LoxValue;
+
+// Every iteration leaves behind an instance holding itself and a bound
+// method of itself, and a closure stored in the scope it captured.
+const CYCLES: &str = "
+class Node {
+  pub init() { this.me = this; this.callback = this.get; }
+  pub get() { return this; }
+}
+
+fn churn(n) {
+  var i = 0;
+  while (i < n) {
+    var node = Node();
+    fn again() { return again; }
+    i = i + 1;
+  }
+}
+";
+
+fn session(backend: Backend) -> LoxSession {
+    let mut session = LoxSession::new();
+    session.set_backend(backend);
+    session.eval(CYCLES).unwrap();
+    session
+}
+
+fn reclaims_cycles(backend: Backend) {
+    let mut session = session(backend);
+    session.eval("var kept = Node(); churn(100);").unwrap();
+    let before = session.heap_stats().live;
+
+    assert!(session.collect_garbage() >= 200);
+    assert!(session.heap_stats().live < before);
+    // Objects still reachable from globals survive
+    assert_eq!(session.eval("kept.get().me == nil;").unwrap(), LoxValue::Boolean(false));
+    assert_eq!(session.collect_garbage(), 0);
+}
+
+fn bounded_in_long_runs(backend: Backend) {
+    let mut session = session(backend);
+    session.eval("churn(20000);").unwrap();
+    let stats = session.heap_stats();
+
+    assert!(stats.collections > 0);
+    assert!(stats.reclaimed >= 20000);
+    assert!(stats.live <= stats.threshold);
+}
+
+#[test]
+fn tree_walker_reclaims_cycles() {
+    reclaims_cycles(Backend::TreeWalker);
+}
+
+#[test]
+fn vm_reclaims_cycles() {
+    reclaims_cycles(Backend::Vm);
+}
+
+#[test]
+fn tree_walker_heap_stays_bounded() {
+    bounded_in_long_runs(Backend::TreeWalker);
+}
+
+#[test]
+fn vm_heap_stays_bounded() {
+    bounded_in_long_runs(Backend::Vm);
+}
+
+#[test]
+fn gc_native_returns_freed_count() {
+    for backend in [Backend::TreeWalker, Backend::Vm] {
+        let mut session = session(backend);
+        let freed = session.eval("churn(10); gc();").unwrap();
+        assert!(matches!(freed, LoxValue::Integer(n) if n > 0));
+    }
+}